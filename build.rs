@@ -0,0 +1,40 @@
+//! 编译时注入构建信息，供 GET /version 使用：
+//! - `BUILD_GIT_SHA`：优先取环境变量 GIT_SHA（容器里构建时通常没有 .git），否则 `git rev-parse`
+//! - `BUILD_UNIX_TIME`：构建时间（unix 秒），运行时再格式化成 RFC 3339
+//!
+//! 另外 migrations/ 由 sqlx::migrate! 在编译时嵌进二进制，加了迁移文件也要重新编译
+
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn main() {
+    let git_sha = std::env::var("GIT_SHA")
+        .ok()
+        .filter(|s| !s.trim().is_empty())
+        .or_else(|| {
+            let out = Command::new("git")
+                .args(["rev-parse", "--short=12", "HEAD"])
+                .output()
+                .ok()?;
+            out.status
+                .success()
+                .then(|| String::from_utf8_lossy(&out.stdout).trim().to_string())
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let build_time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    println!("cargo:rustc-env=BUILD_GIT_SHA={git_sha}");
+    println!("cargo:rustc-env=BUILD_UNIX_TIME={build_time}");
+
+    // 源码变了才重新生成构建时间；换了 commit 也要刷新 sha
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-changed=migrations");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
@@ -0,0 +1,59 @@
+-- 引入版本化迁移时的完整表结构，和 migrations/sqlite 的同一版本一一对应；整数一律 BIGINT，自增用 BIGSERIAL（同样不会复用 id）。
+-- 全部用 IF NOT EXISTS：迁移之前建的库（启动时已先补齐到这个结构）上执行这一版只会记下版本号。
+-- 已经发布的迁移不要再改（sqlx 会校验 checksum），表结构的变化一律加新的迁移
+-- value 的唯一索引取决于 VALUE_HASH_DEDUP，不在迁移里，由启动时的 init_db 按配置建
+CREATE TABLE IF NOT EXISTS mappings (
+    id           BIGSERIAL PRIMARY KEY,
+    namespace    TEXT NOT NULL DEFAULT '',
+    code         TEXT,
+    value        TEXT,
+    value_bin    BYTEA,
+    value_hash   BYTEA,
+    decode_count BIGINT NOT NULL DEFAULT 0,
+    hit_count    BIGINT NOT NULL DEFAULT 0,
+    expires_at   BIGINT,
+    deleted_at   BIGINT,
+    created_at   BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT),
+    last_accessed_at BIGINT
+);
+
+CREATE UNIQUE INDEX IF NOT EXISTS uq_mappings_namespace_code ON mappings(namespace, code);
+CREATE INDEX IF NOT EXISTS idx_mappings_expires_at ON mappings(expires_at);
+
+CREATE TABLE IF NOT EXISTS events (
+    id          BIGSERIAL PRIMARY KEY,
+    action      TEXT NOT NULL, -- 'encode' | 'decode' | 'update' | 'delete' | 'import' | 'rotate' | 'reserve'
+    mapping_id  BIGINT,
+    code        TEXT,
+    value       TEXT,
+    created_at  BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT)
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_created_at ON events(created_at);
+
+CREATE TABLE IF NOT EXISTS audit_log (
+    id          BIGSERIAL PRIMARY KEY,
+    action      TEXT NOT NULL, -- 'create' | 'restore' | 'import' | 'update' | 'delete' | 'rotate' | 'reserve'
+    namespace   TEXT NOT NULL,
+    code        TEXT NOT NULL,
+    value_hash  TEXT NOT NULL, -- value 的 SHA-256（hex），不存原文
+    client_ip   TEXT,
+    created_at  BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_code ON audit_log(namespace, code);
+
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    idem_key    TEXT PRIMARY KEY,
+    value       TEXT NOT NULL,
+    code        TEXT NOT NULL,
+    created_at  BIGINT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys(created_at);
+
+-- 非默认命名空间各自的短码计数器（默认命名空间直接用 mappings 的自增 id）
+CREATE TABLE IF NOT EXISTS namespace_counters (
+    namespace   TEXT PRIMARY KEY,
+    last_seq    BIGINT NOT NULL
+);
@@ -0,0 +1,11 @@
+-- POST /encode/alias：同一个映射的额外短码，和 migrations/sqlite/0002_aliases.sql 对应
+CREATE TABLE IF NOT EXISTS aliases (
+    id          BIGSERIAL PRIMARY KEY,
+    namespace   TEXT NOT NULL,
+    code        TEXT NOT NULL,
+    mapping_id  BIGINT NOT NULL REFERENCES mappings(id) ON DELETE CASCADE,
+    created_at  BIGINT NOT NULL DEFAULT (EXTRACT(EPOCH FROM NOW())::BIGINT),
+    UNIQUE (namespace, code)
+);
+
+CREATE INDEX IF NOT EXISTS idx_aliases_mapping_id ON aliases(mapping_id);
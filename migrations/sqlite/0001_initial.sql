@@ -0,0 +1,72 @@
+-- 引入版本化迁移时的完整表结构。全部用 IF NOT EXISTS：迁移之前建的库（启动时已先补齐到这个结构）上执行这一版只会记下版本号。
+-- 已经发布的迁移不要再改（sqlx 会校验 checksum），表结构的变化一律加新的迁移，并同步 migrations/postgres
+
+-- namespace: 命名空间，'' 为默认命名空间
+-- value: 原始字符串（同一命名空间内去重）
+-- value_bin: 二进制 value（同一命名空间内去重），和 value 恰好有一列非空
+-- value_hash: value 的 SHA-256（见 Value::dedup_hash），每次写 value 时一起写
+-- code: 完整短码（含 CODE_PREFIX / CODE_SUFFIX 和校验位，长度随 CODE_MIN_LEN / CODE_MAX_LEN 配置；同一命名空间内唯一），分配之前为 NULL
+-- hit_count: 所有查找路径（POST/GET decode、跳转）的命中次数，由 HitCounter 批量写回
+-- expires_at: 过期时间（unix 秒），NULL 表示永不过期
+-- deleted_at: SOFT_DELETE 模式下的删除时间（unix 秒），NULL 表示未删除
+-- last_accessed_at: 最近一次命中的时间（unix 秒），只在 TRACK_LAST_ACCESS 下由 HitCounter 写入，NULL 表示没有记录
+-- value 的唯一索引取决于 VALUE_HASH_DEDUP，不在迁移里，由启动时的 init_db 按配置建
+CREATE TABLE IF NOT EXISTS mappings (
+    id           INTEGER PRIMARY KEY AUTOINCREMENT,
+    namespace    TEXT NOT NULL DEFAULT '',
+    code         TEXT,
+    value        TEXT,
+    value_bin    BLOB,
+    value_hash   BLOB,
+    decode_count INTEGER NOT NULL DEFAULT 0,
+    hit_count    INTEGER NOT NULL DEFAULT 0,
+    expires_at   INTEGER,
+    deleted_at   INTEGER,
+    created_at   INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    last_accessed_at INTEGER,
+    UNIQUE (namespace, code)
+);
+
+CREATE INDEX IF NOT EXISTS idx_mappings_code ON mappings(code);
+CREATE INDEX IF NOT EXISTS idx_mappings_expires_at ON mappings(expires_at);
+
+-- 事件表：记录每次 encode/decode/update/delete/import 的时间
+CREATE TABLE IF NOT EXISTS events (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    action      TEXT NOT NULL, -- 'encode' | 'decode' | 'update' | 'delete' | 'import' | 'rotate' | 'reserve'
+    mapping_id  INTEGER,
+    code        TEXT,
+    value       TEXT,
+    created_at  INTEGER NOT NULL DEFAULT (strftime('%s','now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_events_created_at ON events(created_at);
+
+-- 审计日志（AUDIT_LOG）：谁在什么时候创建 / 修改 / 删除了哪个映射，和映射的变更在同一个事务里写入
+CREATE TABLE IF NOT EXISTS audit_log (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    action      TEXT NOT NULL, -- 'create' | 'restore' | 'import' | 'update' | 'delete' | 'rotate' | 'reserve'
+    namespace   TEXT NOT NULL,
+    code        TEXT NOT NULL,
+    value_hash  TEXT NOT NULL, -- value 的 SHA-256（hex），不存原文
+    client_ip   TEXT,
+    created_at  INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_code ON audit_log(namespace, code);
+
+-- Idempotency-Key -> 当时 encode 的结果，过期后由后台任务清理
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    idem_key    TEXT PRIMARY KEY,
+    value       TEXT NOT NULL,
+    code        TEXT NOT NULL,
+    created_at  INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_idempotency_keys_created_at ON idempotency_keys(created_at);
+
+-- 非默认命名空间各自的短码计数器（默认命名空间直接用 mappings 的自增 id）
+CREATE TABLE IF NOT EXISTS namespace_counters (
+    namespace   TEXT PRIMARY KEY,
+    last_seq    INTEGER NOT NULL
+);
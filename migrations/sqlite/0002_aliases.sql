@@ -0,0 +1,13 @@
+-- POST /encode/alias：同一个映射的额外短码，decode 别名和 decode 主 code 得到同一个 value。
+-- code 在同一命名空间内跨 mappings 和 aliases 唯一，由写入方（code_taken）保证；映射被删掉（包括过期清理）时别名跟着删。
+-- events / audit_log 的 action 多了 'alias'
+CREATE TABLE IF NOT EXISTS aliases (
+    id          INTEGER PRIMARY KEY AUTOINCREMENT,
+    namespace   TEXT NOT NULL,
+    code        TEXT NOT NULL,
+    mapping_id  INTEGER NOT NULL REFERENCES mappings(id) ON DELETE CASCADE,
+    created_at  INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+    UNIQUE (namespace, code)
+);
+
+CREATE INDEX IF NOT EXISTS idx_aliases_mapping_id ON aliases(mapping_id);
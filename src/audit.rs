@@ -0,0 +1,79 @@
+use std::convert::Infallible;
+use std::net::IpAddr;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use sha2::{Digest, Sha256};
+
+use crate::AppState;
+use crate::db::Tx;
+use crate::ratelimit;
+use crate::value::Value;
+
+/// 当前写请求的审计上下文。AUDIT_LOG 关闭时 `enabled` 为 false，record 什么都不做；
+/// 作为 extractor 使用，handler 把它一路传进写映射的事务里
+#[derive(Clone, Copy)]
+pub struct Audit {
+    enabled: bool,
+    client_ip: Option<IpAddr>,
+}
+
+impl Audit {
+    /// 不写审计：启动自检这类不是由请求触发的写入用
+    pub const DISABLED: Audit = Audit {
+        enabled: false,
+        client_ip: None,
+    };
+}
+
+impl FromRequestParts<AppState> for Audit {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if !state.audit_log {
+            return Ok(Audit::DISABLED);
+        }
+        Ok(Audit {
+            enabled: true,
+            client_ip: ratelimit::client_ip(&parts.headers, &parts.extensions, state.trust_proxy),
+        })
+    }
+}
+
+/// 审计表里只存 value 的 SHA-256（hex），不存原文：能核对"是不是这个 value"，泄露了也还原不出来
+pub fn value_hash(value: &Value) -> String {
+    Sha256::digest(value.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// 在调用方的事务里写一条审计记录，和映射的变更一起提交或回滚。
+/// action 为 'create' | 'restore' | 'import' | 'update' | 'delete' | 'rotate' | 'reserve' | 'alias'（reserve 没有 value，value_hash 是空串的哈希）
+pub async fn record(
+    tx: &mut Tx<'_>,
+    audit: Audit,
+    action: &str,
+    ns: &str,
+    code: &str,
+    value: &Value,
+) -> Result<(), sqlx::Error> {
+    if !audit.enabled {
+        return Ok(());
+    }
+    sqlx::query(
+        "INSERT INTO audit_log (action, namespace, code, value_hash, client_ip, created_at) \
+         VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(action)
+    .bind(ns)
+    .bind(code)
+    .bind(value_hash(value))
+    .bind(audit.client_ip.map(|ip| ip.to_string()))
+    .bind(crate::now_unix())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
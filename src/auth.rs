@@ -0,0 +1,59 @@
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use std::sync::Arc;
+
+use crate::ApiError;
+
+/// API_KEYS 配置的 Bearer token 列表
+pub struct ApiKeys {
+    keys: Vec<String>,
+}
+
+impl ApiKeys {
+    /// 解析逗号分隔的 key 列表；全是空串时返回 None（即不启用鉴权）
+    pub fn parse(raw: &str) -> Option<Arc<Self>> {
+        let keys: Vec<String> = raw
+            .split(',')
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        (!keys.is_empty()).then(|| Arc::new(ApiKeys { keys }))
+    }
+
+    fn contains(&self, token: &str) -> bool {
+        // 不短路：每个 key 都比一遍，避免从耗时推测出 key
+        self.keys.iter().fold(false, |found, key| {
+            constant_time_eq(key.as_bytes(), token.as_bytes()) | found
+        })
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// 校验 `Authorization: Bearer <key>`，不匹配返回 401
+pub async fn require_api_key(
+    State(keys): State<Arc<ApiKeys>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let token = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    match token {
+        Some(token) if keys.contains(token) => Ok(next.run(req).await),
+        _ => Err(ApiError::Unauthorized),
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// 固定容量的 LRU 缓存（code -> 映射），`lru` crate 外面包一把 Mutex。
+///
+/// get 也要调整最近使用顺序，所以读写都拿同一把锁：临界区只有一次哈希查找和指针调整，比一次数据库查询便宜得多。
+pub struct LruCache<V> {
+    inner: Mutex<lru::LruCache<String, V>>,
+}
+
+impl<V: Clone> LruCache<V> {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = NonZeroUsize::new(capacity).expect("lru capacity must be positive");
+        LruCache {
+            inner: Mutex::new(lru::LruCache::new(capacity)),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.lock().unwrap().cap().get()
+    }
+
+    /// 当前所有 key，从最近使用到最久未使用
+    pub fn keys(&self) -> Vec<String> {
+        self.inner
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        self.inner.lock().unwrap().get(key).cloned()
+    }
+
+    /// 已有的 key 覆盖并提到最前；满了淘汰最久未使用的
+    pub fn insert(&self, key: &str, value: V) {
+        self.inner.lock().unwrap().put(key.to_string(), value);
+    }
+
+    pub fn remove(&self, key: &str) {
+        self.inner.lock().unwrap().pop(key);
+    }
+}
+
+/// 按 TTL 过期的小缓存：条目很少（例如每个命名空间一个计数），过期前直接返回上次算好的值。
+/// 不做淘汰，过期的条目在下次 insert 同一个 key 时覆盖
+pub struct TtlCache<V> {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, V)>>,
+}
+
+impl<V: Clone> TtlCache<V> {
+    /// ttl 为 0 时相当于不缓存
+    pub fn new(ttl: Duration) -> Self {
+        TtlCache {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let entries = self.entries.lock().unwrap();
+        let (at, value) = entries.get(key)?;
+        (at.elapsed() < self.ttl).then(|| value.clone())
+    }
+
+    pub fn insert(&self, key: &str, value: V) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_owned(), (Instant::now(), value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(keys: &[&str]) -> LruCache<usize> {
+        let cache = LruCache::new(keys.len());
+        for (i, key) in keys.iter().enumerate() {
+            cache.insert(key, i);
+        }
+        cache
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_at_capacity() {
+        let cache = filled(&["a", "b", "c"]);
+        assert_eq!(cache.keys(), ["c", "b", "a"]);
+        cache.insert("d", 3);
+        assert_eq!(cache.keys(), ["d", "c", "b"]);
+        assert_eq!(cache.get("a"), None);
+        assert_eq!(cache.capacity(), 3);
+    }
+
+    #[test]
+    fn get_and_reinsert_promote() {
+        let cache = filled(&["a", "b", "c"]);
+        assert_eq!(cache.get("a"), Some(0));
+        assert_eq!(cache.keys(), ["a", "c", "b"]);
+        // 覆盖已有的 key 不占新位置
+        cache.insert("b", 10);
+        assert_eq!(cache.keys(), ["b", "a", "c"]);
+        cache.insert("d", 3);
+        assert_eq!(cache.keys(), ["d", "b", "a"]);
+        assert_eq!(cache.get("b"), Some(10));
+    }
+
+    #[test]
+    fn remove_head_tail_and_middle() {
+        let cache = filled(&["a", "b", "c", "d", "e"]);
+        cache.remove("e");
+        assert_eq!(cache.keys(), ["d", "c", "b", "a"]);
+        cache.remove("a");
+        assert_eq!(cache.keys(), ["d", "c", "b"]);
+        cache.remove("c");
+        cache.remove("missing");
+        assert_eq!(cache.keys(), ["d", "b"]);
+        assert_eq!((cache.get("d"), cache.get("c")), (Some(3), None));
+        // 删掉之后空出来的位置照常使用，满了才淘汰
+        for key in ["x", "y", "z"] {
+            cache.insert(key, 0);
+        }
+        assert_eq!(cache.keys(), ["z", "y", "x", "d", "b"]);
+        cache.insert("w", 0);
+        assert_eq!(cache.keys(), ["w", "z", "y", "x", "d"]);
+    }
+}
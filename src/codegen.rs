@@ -0,0 +1,390 @@
+use std::sync::Arc;
+
+use crate::{ApiError, CodeConfig, CodeStrategy, feistel::Feistel};
+
+/// 由编号生成短码：默认命名空间的编号是 mappings 的自增 id，其它命名空间是各自计数器的序号。
+///
+/// encode 只通过这个 trait 拿短码，换一种编码方式实现它、在 `from_config` 里选上即可，不用改 encode。
+/// `random` 策略不按编号生成（见 next_random_code），不经过这里
+pub trait CodeGenerator: Send + Sync {
+    /// 编号超出 max_len 位能表示的范围时返回 Exhausted
+    fn code_for(&self, id: i64) -> Result<String, ApiError>;
+}
+
+/// 按 CODE_STRATEGY 选出生成器（启动时调用一次，放进 AppState）
+pub fn from_config(cfg: &CodeConfig) -> Arc<dyn CodeGenerator> {
+    match (cfg.strategy, &cfg.feistel) {
+        (CodeStrategy::Feistel, Some(feistel)) => Arc::new(FeistelSequential {
+            inner: Base62Sequential::new(cfg.clone()),
+            feistel: feistel.clone(),
+        }),
+        _ => Arc::new(Base62Sequential::new(cfg.clone())),
+    }
+}
+
+/// 默认实现：编号直接按字符集做进制编码（默认字符集下就是 base62），不足 min_len 左侧补齐
+pub struct Base62Sequential {
+    cfg: CodeConfig,
+    /// max_len 位能表示的最大编号 base^max_len - 1，构造时算好，超过它直接返回 Exhausted
+    max_id: i64,
+}
+
+impl Base62Sequential {
+    pub fn new(cfg: CodeConfig) -> Self {
+        // config 已经保证 base^max_len 放得下 i64，这里仍然用 checked，放不下时整个 i64 都可编码
+        let max_id = u32::try_from(cfg.max_len)
+            .ok()
+            .and_then(|len| (cfg.charset.len() as i64).checked_pow(len))
+            .map_or(i64::MAX, |space| space - 1);
+        Base62Sequential { cfg, max_id }
+    }
+
+    /// 按 ID_OFFSET / ID_STEP 换算并平移保留区间后的编号，超出 [1, max_id] 时返回 Exhausted
+    fn shifted(&self, id: i64) -> Result<i64, ApiError> {
+        if id <= 0 {
+            return Err(ApiError::BadRequest("invalid id".to_string()));
+        }
+        let id = auto_number(&self.cfg, id).ok_or_else(|| self.cfg.exhausted())?;
+        if id > self.max_id {
+            return Err(self.cfg.exhausted());
+        }
+        Ok(id)
+    }
+}
+
+impl CodeGenerator for Base62Sequential {
+    fn code_for(&self, id: i64) -> Result<String, ApiError> {
+        let id = self.shifted(id)?;
+        id_to_code(&self.cfg, id)
+    }
+}
+
+/// feistel 策略：编号先在保留区间之后的 [reserved_below_id, base^max_len - 1] 内做密钥置换再编码，
+/// 短码依然无碰撞但看不出顺序，也不会落进 RESERVED_BELOW_ID 留给 custom_code 的那段
+pub struct FeistelSequential {
+    inner: Base62Sequential,
+    feistel: Arc<Feistel>,
+}
+
+impl CodeGenerator for FeistelSequential {
+    fn code_for(&self, id: i64) -> Result<String, ApiError> {
+        let id = self.inner.shifted(id)?;
+        // 置换范围是去掉保留区间后的 [1, max_id - shift]（见 config），先平移回去、置换完再平移
+        let shift = (self.inner.cfg.reserved_below_id - 1).max(0);
+        let scrambled = self.feistel.scramble_id((id - shift) as u64) as i64;
+        id_to_code(&self.inner.cfg, scrambled + shift)
+    }
+}
+
+/// 第 id 个自动分配（id >= 1）实际编码的编号：先按 ID_OFFSET / ID_STEP 换算成 id_offset + (id - 1) * id_step，
+/// 让多个实例各占一个剩余类或区间；有保留区间（RESERVED_BELOW_ID）时再整体往后平移，跳过 [1, reserved_below_id)。
+/// 溢出 i64 时返回 None
+pub fn auto_number(cfg: &CodeConfig, id: i64) -> Option<i64> {
+    (id - 1)
+        .checked_mul(cfg.id_step)?
+        .checked_add(cfg.id_offset)?
+        .checked_add((cfg.reserved_below_id - 1).max(0))
+}
+
+/// 调用方已经保证 id <= max_id，结果不会超过 max_len 位
+fn id_to_code(cfg: &CodeConfig, id: i64) -> Result<String, ApiError> {
+    if id <= 0 {
+        return Err(ApiError::BadRequest("invalid id".to_string()));
+    }
+    let mut n = id as u64;
+
+    let base = cfg.charset.len() as u64;
+    let mut buf = Vec::with_capacity(cfg.max_len);
+    while n > 0 {
+        let rem = (n % base) as usize;
+        buf.push(cfg.charset[rem]);
+        n /= base;
+    }
+    debug_assert!(buf.len() <= cfg.max_len, "id beyond max_id");
+    // 不足 min_len 时用 charset[0] 在左侧补齐（默认字符集下是“0”，自定义字符集就是它的第一个字符），
+    // 补出来的字符一定在字符集内，也就能原样通过 decode 的校验
+    buf.resize(buf.len().max(cfg.min_len), cfg.charset[0]);
+    buf.reverse();
+    Ok(cfg.full_code(String::from_utf8(buf).expect("charset is ascii")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::validate_code;
+
+    fn code_config(vars: &[(&str, &str)]) -> CodeConfig {
+        Config::from_vars(vars).unwrap().code
+    }
+
+    /// id 按字符集进制编码后的位数（不补齐）
+    fn digits(id: i64, base: i64) -> usize {
+        std::iter::successors(Some(id), |n| Some(n / base).filter(|&n| n > 0)).count()
+    }
+
+    #[test]
+    fn pads_to_min_len_with_first_charset_char() {
+        // base58 的第一个字符是 '1'，自定义字符集的是 'x'
+        for charset in ["base58", "xyzabcdefghijklmnopq"] {
+            for min_len in [1, 3, 4, 5] {
+                let min = min_len.to_string();
+                let cfg = code_config(&[
+                    ("CODE_CHARSET", charset),
+                    ("CODE_MIN_LEN", &min),
+                    ("CODE_MAX_LEN", "6"),
+                ]);
+                let (pad, base) = (cfg.charset[0], cfg.charset.len() as i64);
+                assert_ne!(pad, b'0');
+                let generator = Base62Sequential::new(cfg.clone());
+                for id in
+                    (1..=2_000).chain([base - 1, base, base * base, base.pow(4) - 1, base.pow(5)])
+                {
+                    let code = generator.code_for(id).unwrap();
+                    let natural = digits(id, base);
+                    assert_eq!(
+                        code.len(),
+                        natural.max(min_len),
+                        "{charset} min_len={min_len} id={id}: {code}"
+                    );
+                    let padding = &code.as_bytes()[..code.len() - natural];
+                    assert!(
+                        padding.iter().all(|&b| b == pad),
+                        "{charset} id={id}: {code}"
+                    );
+                    validate_code(&cfg, &code).unwrap_or_else(|e| panic!("{code} rejected: {e}"));
+                }
+            }
+        }
+    }
+
+    fn exhausted(result: Result<impl std::fmt::Debug, ApiError>) -> bool {
+        matches!(result, Err(ApiError::Exhausted { .. }))
+    }
+
+    #[test]
+    fn max_id_is_the_longest_code_and_one_more_is_exhausted() {
+        for (charset, max_len) in [("base62", 3), ("0123456789abcdef", 4), ("base36", 12)] {
+            let cfg = code_config(&[
+                ("CODE_CHARSET", charset),
+                ("CODE_MAX_LEN", &max_len.to_string()),
+            ]);
+            let generator = Base62Sequential::new(cfg.clone());
+            let max_id = (cfg.charset.len() as i64).pow(max_len as u32) - 1;
+            assert_eq!(generator.max_id, max_id);
+            let last = (*cfg.charset.last().unwrap() as char)
+                .to_string()
+                .repeat(max_len);
+            assert_eq!(generator.code_for(max_id).unwrap(), last);
+            assert!(exhausted(generator.code_for(max_id + 1)), "{charset}");
+            assert!(exhausted(generator.code_for(i64::MAX)), "{charset}");
+        }
+    }
+
+    #[test]
+    fn non_positive_ids_are_rejected() {
+        let generator = Base62Sequential::new(code_config(&[]));
+        for id in [0, -1, i64::MIN] {
+            assert!(
+                matches!(generator.code_for(id), Err(ApiError::BadRequest(_))),
+                "{id}"
+            );
+        }
+    }
+
+    #[test]
+    fn offset_and_step_exhaust_at_the_last_fitting_number() {
+        // 62^2 - 1 = 3843：第 n 个编号是 10 + (n - 1) * 7，n = 548 时是 3839，n = 549 超出
+        let cfg = code_config(&[("CODE_MAX_LEN", "2"), ("ID_OFFSET", "10"), ("ID_STEP", "7")]);
+        let generator = Base62Sequential::new(cfg);
+        assert_eq!(generator.shifted(1).unwrap(), 10);
+        assert_eq!(generator.shifted(548).unwrap(), 3839);
+        assert!(exhausted(generator.shifted(549)));
+    }
+
+    #[test]
+    fn reserved_ids_shift_the_range() {
+        let cfg = code_config(&[("CODE_MAX_LEN", "2"), ("RESERVED_BELOW_ID", "100")]);
+        let generator = Base62Sequential::new(cfg);
+        assert_eq!(generator.shifted(1).unwrap(), 100);
+        assert_eq!(generator.shifted(3843 - 99).unwrap(), 3843);
+        assert!(exhausted(generator.shifted(3843 - 98)));
+
+        // 保留到 max_id 时只剩 max_id 本身
+        let cfg = code_config(&[("CODE_MAX_LEN", "2"), ("RESERVED_BELOW_ID", "3843")]);
+        let generator = Base62Sequential::new(cfg);
+        assert_eq!(generator.shifted(1).unwrap(), 3843);
+        assert!(exhausted(generator.shifted(2)));
+    }
+
+    /// 没有前后缀、校验位时短码对应的编号（code_for 的逆运算）
+    fn code_number(cfg: &CodeConfig, code: &str) -> i64 {
+        let base = cfg.charset.len() as i64;
+        code.bytes().fold(0, |n, b| {
+            n * base + cfg.charset.iter().position(|&c| c == b).unwrap() as i64
+        })
+    }
+
+    #[test]
+    fn feistel_never_scrambles_into_the_reserved_range() {
+        // 2 位短码：去掉保留的 1..=999 还剩 2844 个编号，置换正好用完它们
+        let vars = [
+            ("CODE_STRATEGY", "feistel"),
+            ("CODE_FEISTEL_KEY", "test-key"),
+            ("CODE_MAX_LEN", "2"),
+            ("RESERVED_BELOW_ID", "1000"),
+        ];
+        let cfg = code_config(&vars);
+        let codes = assert_deterministic_and_injective(&vars, 2844);
+        let mut numbers: Vec<i64> = codes.iter().map(|code| code_number(&cfg, code)).collect();
+        numbers.sort();
+        assert_eq!(numbers, (1000..=3843).collect::<Vec<_>>());
+        assert!(exhausted(from_config(&cfg).code_for(2845)));
+
+        // 大一些的空间（再加上 ID_OFFSET / ID_STEP）抽样
+        let vars = [
+            vars[0],
+            vars[1],
+            ("CODE_MAX_LEN", "4"),
+            ("RESERVED_BELOW_ID", "238328"),
+            ("ID_STEP", "3"),
+        ];
+        let cfg = code_config(&vars);
+        for code in assert_deterministic_and_injective(&vars, 20_000) {
+            assert!(code_number(&cfg, &code) >= 238_328, "{code}");
+        }
+    }
+
+    #[test]
+    fn overflowing_auto_numbers_are_exhausted_not_wrapped() {
+        // step 乘法溢出
+        let cfg = code_config(&[("ID_STEP", &i64::MAX.to_string())]);
+        assert_eq!(auto_number(&cfg, 1), Some(1));
+        assert_eq!(auto_number(&cfg, 2), None);
+        assert!(exhausted(Base62Sequential::new(cfg).code_for(2)));
+
+        // 36^12 - 1 超过 i64::MAX 的一半：offset 和保留区间都取到最大时加法溢出
+        let max_id = (36i64.pow(12) - 1).to_string();
+        let cfg = code_config(&[
+            ("CODE_CHARSET", "base36"),
+            ("CODE_MAX_LEN", "12"),
+            ("ID_OFFSET", &max_id),
+            ("RESERVED_BELOW_ID", &max_id),
+        ]);
+        assert_eq!(auto_number(&cfg, 1), None);
+        let generator = Base62Sequential::new(cfg);
+        assert!(exhausted(generator.code_for(1)));
+        assert!(exhausted(generator.code_for(i64::MAX)));
+    }
+
+    /// 经 from_config 拿到的生成器在 [1, ids] 上：同一配置两次构造结果相同、互不重复、都能通过 decode 的校验
+    fn assert_deterministic_and_injective(vars: &[(&str, &str)], ids: i64) -> Vec<String> {
+        let cfg = code_config(vars);
+        let (a, b) = (from_config(&cfg), from_config(&cfg));
+        let codes: Vec<String> = (1..=ids).map(|id| a.code_for(id).unwrap()).collect();
+        for (id, code) in (1..=ids).zip(&codes) {
+            assert_eq!(&b.code_for(id).unwrap(), code, "{vars:?} id={id}");
+            assert_eq!(&a.code_for(id).unwrap(), code, "{vars:?} id={id}");
+            validate_code(&cfg, code).unwrap_or_else(|e| panic!("{vars:?}: {code} rejected: {e}"));
+        }
+        let unique: std::collections::HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), codes.len(), "{vars:?}");
+        codes
+    }
+
+    #[test]
+    fn every_strategy_is_deterministic_and_injective() {
+        for strategy in ["sequential", "random", "feistel"] {
+            let base = [
+                ("CODE_STRATEGY", strategy),
+                ("CODE_FEISTEL_KEY", "test-key"),
+            ];
+            assert_deterministic_and_injective(&base, 5_000);
+            let mut vars = base.to_vec();
+            vars.extend([
+                ("CODE_CHECKSUM", "1"),
+                ("CODE_PREFIX", "x-"),
+                ("ID_OFFSET", "3"),
+                ("ID_STEP", "2"),
+            ]);
+            assert_deterministic_and_injective(&vars, 5_000);
+        }
+    }
+
+    #[test]
+    fn sequential_and_random_number_in_order() {
+        // random 策略分配短码时不经过 code_for（见 next_random_code），from_config 给它的仍是顺序编码
+        for strategy in ["sequential", "random"] {
+            let codes = assert_deterministic_and_injective(&[("CODE_STRATEGY", strategy)], 63);
+            assert_eq!(&codes[..3], ["01", "02", "03"]);
+            assert_eq!(codes[61], "10");
+            assert_eq!(codes[62], "11");
+        }
+    }
+
+    #[test]
+    fn feistel_permutes_the_whole_code_space() {
+        // 16^2 - 1 = 255 个编号正好用完 2 位短码的空间（不含 "00"）
+        let vars = [
+            ("CODE_STRATEGY", "feistel"),
+            ("CODE_FEISTEL_KEY", "test-key"),
+            ("CODE_CHARSET", "0123456789abcdef"),
+            ("CODE_MAX_LEN", "2"),
+        ];
+        let codes = assert_deterministic_and_injective(&vars, 255);
+        let sequential = assert_deterministic_and_injective(&vars[2..], 255);
+        assert_ne!(codes, sequential);
+        let mut sorted = codes.clone();
+        sorted.sort();
+        assert_eq!(sorted, sequential);
+        assert!(exhausted(from_config(&code_config(&vars)).code_for(256)));
+
+        // 换一个密钥就是另一个置换
+        let other = [("CODE_FEISTEL_KEY", "other-key"), vars[0], vars[2], vars[3]];
+        assert_ne!(assert_deterministic_and_injective(&other, 255), codes);
+    }
+
+    /// README 里的例子：数字挪到最后
+    const LETTERS_FIRST: &str = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+    #[test]
+    fn reordered_charset_keeps_the_digits_and_swaps_the_chars() {
+        let default_cfg = code_config(&[]);
+        let cfg = code_config(&[("CODE_CHARSET_ORDER", LETTERS_FIRST)]);
+        let default_codes = assert_deterministic_and_injective(&[], 5_000);
+        let codes =
+            assert_deterministic_and_injective(&[("CODE_CHARSET_ORDER", LETTERS_FIRST)], 5_000);
+        assert_eq!(&codes[..3], ["ab", "ac", "ad"]);
+        for (default_code, code) in default_codes.iter().zip(&codes) {
+            let translated: String = default_code
+                .bytes()
+                .map(|b| {
+                    cfg.charset[default_cfg.charset.iter().position(|&c| c == b).unwrap()] as char
+                })
+                .collect();
+            assert_eq!(&translated, code);
+            validate_code(&cfg, code).unwrap_or_else(|e| panic!("{code} rejected: {e}"));
+        }
+    }
+
+    #[test]
+    fn charset_order_must_be_a_permutation() {
+        let missing = &LETTERS_FIRST[1..];
+        let duplicate = format!("b{missing}");
+        let foreign = format!("-{missing}");
+        let longer = format!("{LETTERS_FIRST}a");
+        for order in [missing, &duplicate, &foreign, &longer] {
+            assert!(
+                Config::from_vars(&[("CODE_CHARSET_ORDER", order)]).is_err(),
+                "{order:?}"
+            );
+        }
+        // 重排的是 CODE_CHARSET 指定的字符集
+        let vars = [
+            ("CODE_CHARSET", "0123456789abcdef"),
+            ("CODE_CHARSET_ORDER", "fedcba9876543210"),
+        ];
+        assert_eq!(&code_config(&vars).charset[..], b"fedcba9876543210");
+        assert!(Config::from_vars(&[vars[1]]).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderValue, StatusCode, Version, header},
+    middleware::Next,
+    response::Response,
+};
+use tower_http::{
+    CompressionLevel,
+    compression::{CompressionLayer, Predicate, predicate::SizeAbove},
+    decompression::RequestDecompressionLayer,
+};
+
+use crate::ApiError;
+
+/// 长度已知且小于这个值的响应不压缩：gzip 头尾就有 18 字节，省不了多少
+const MIN_COMPRESS_BYTES: u16 = 1024;
+
+/// 客户端 Accept-Encoding 接受 gzip 时压缩响应体（JSON / NDJSON / 文本），并加上 `Vary: Accept-Encoding`。
+///
+/// 边压缩边输出，GET /export 这种流式响应也不会被攒到内存里。
+/// 带 ETag 的响应不压缩，避免同一个 ETag 对应两种不同的字节
+pub fn compress_response(level: u32) -> CompressionLayer<impl Predicate> {
+    CompressionLayer::new()
+        .no_br()
+        .no_deflate()
+        .no_zstd()
+        .quality(CompressionLevel::Precise(level as i32))
+        .compress_when(SizeAbove::new(MIN_COMPRESS_BYTES).and(is_compressible))
+}
+
+/// 请求头 `Content-Encoding: gzip` 时把请求体换成解压后的数据流，handler 看到的就是普通请求体。
+/// 和 check_request_encoding 一起挂载，后者负责拒绝其它编码
+pub fn decompress_request() -> RequestDecompressionLayer {
+    RequestDecompressionLayer::new()
+        .no_br()
+        .no_deflate()
+        .no_zstd()
+}
+
+/// 解压之前先规范请求的 Content-Encoding：`x-gzip`、大小写和空白都当作 `gzip`，`identity` 直接去掉；
+/// 其它编码返回 JSON 的 415。MAX_BODY_BYTES / MAX_BATCH_BODY_BYTES 限制的是解压后的大小
+pub async fn check_request_encoding(mut req: Request, next: Next) -> Result<Response, ApiError> {
+    let Some(encoding) = req.headers().get(header::CONTENT_ENCODING) else {
+        return Ok(next.run(req).await);
+    };
+    let encoding = encoding
+        .to_str()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    match encoding.as_str() {
+        "identity" => {
+            req.headers_mut().remove(header::CONTENT_ENCODING);
+        }
+        "gzip" | "x-gzip" => {
+            req.headers_mut()
+                .insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        _ => return Err(ApiError::UnsupportedEncoding(encoding)),
+    }
+    Ok(next.run(req).await)
+}
+
+/// 只压缩文本类的响应；图片、带 ETag 的、没有响应体的状态码原样返回
+fn is_compressible(
+    status: StatusCode,
+    _: Version,
+    headers: &HeaderMap,
+    _: &axum::http::Extensions,
+) -> bool {
+    if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+    {
+        return false;
+    }
+    if headers.contains_key(header::ETAG) {
+        return false;
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    content_type.starts_with("application/json")
+        || content_type.starts_with("application/x-ndjson")
+        || content_type.starts_with("text/")
+}
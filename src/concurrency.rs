@@ -0,0 +1,29 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::{Arc, atomic::Ordering};
+
+use crate::metrics::Metrics;
+
+/// 请求处理完（handler 返回响应头）时把在处理数减回去；流式响应体不算在内
+struct InFlight<'a>(&'a Metrics);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.requests_in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// requests_in_flight 指标：挂在 MAX_CONCURRENT_REQUESTS 的 ConcurrencyLimitLayer 里面，
+/// 只统计拿到名额、正在处理的请求（被 503 拒绝的不算）。tower 的限流层不暴露当前并发数，所以单独数一遍
+pub async fn track_in_flight(
+    State(metrics): State<Arc<Metrics>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    metrics.requests_in_flight.fetch_add(1, Ordering::Relaxed);
+    let _in_flight = InFlight(&metrics);
+    next.run(req).await
+}
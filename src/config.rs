@@ -0,0 +1,576 @@
+use std::{fmt::Display, str::FromStr, sync::Arc, time::Duration};
+
+use regex_automata::meta::Regex;
+
+use crate::auth::ApiKeys;
+use crate::cors::AllowedOrigins;
+use crate::db::{Backend, PoolConfig};
+use crate::feistel::Feistel;
+use crate::fieldcase::FieldCase;
+use crate::logging::LogFormat;
+use crate::normalize::{Normalizer, TrimMode};
+use crate::{CodeConfig, CodeStrategy};
+
+/// 字符集至少这么多个字符，太小的话短码空间不够用
+const MIN_CHARSET_LEN: usize = 16;
+
+const CHARSET: &[u8; 62] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+/// base36：只有数字和小写字母，用于 CASE_INSENSITIVE 模式
+const BASE36_CHARSET: &[u8; 36] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+/// base58（bitcoin 字母表）：去掉了容易看混的 0 / O / I / l
+const BASE58_CHARSET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// 启动时从环境变量一次性解析出的全部配置。
+///
+/// 所有校验都在这里做完：值写错（解析不了、越界）直接启动失败，而不是悄悄回落到默认值。
+pub struct Config {
+    pub log_format: LogFormat,
+    /// FIELD_CASE：响应 JSON 的字段名风格，snake（默认）或 camel
+    pub field_case: FieldCase,
+    pub db_url: String,
+    /// DATABASE_URL_READ：只读查询走的库（例如 litefs 副本），未设置时读写共用 DATABASE_URL
+    pub db_read_url: Option<String>,
+    /// VALUE_HASH_DEDUP：value 的唯一索引建在 SHA-256 上而不是原文上
+    pub value_hash_dedup: bool,
+    pub listen_addr: String,
+    /// TLS_CERT / TLS_KEY（PEM 文件路径），都设置时直接提供 HTTPS，都不设置时为 None
+    pub tls: Option<TlsFiles>,
+    /// HTTP2：接受 HTTP/2（明文为 h2c，TLS 下通过 ALPN 协商），关闭时只有 HTTP/1.1
+    pub http2: bool,
+    pub backend: Backend,
+    pub pool: PoolConfig,
+    /// MAX_CONCURRENT_REQUESTS：同时处理的业务请求上限，0 为不限
+    pub max_concurrent_requests: usize,
+    pub code: CodeConfig,
+    pub redirect_mode: bool,
+    /// DISABLE_ENCODE：不挂载 encode 系列和所有会改数据的接口，包括管理接口里的（只读镜像）
+    pub disable_encode: bool,
+    /// DISABLE_DECODE：不挂载 decode 系列和其它会返回 value 的公开读接口（只写的中转服务）
+    pub disable_decode: bool,
+    /// BASE_URL：对外的短链接前缀（如 `https://s.example.com`），启动时校验过是 http(s) URL
+    pub base_url: Option<String>,
+    pub disable_metrics: bool,
+    pub disable_openapi: bool,
+    pub soft_delete: bool,
+    pub debug_fields: bool,
+    /// DEBUG_TIMING：响应头 X-DB-Time-Ms 带上本次请求数据库操作的耗时
+    pub debug_timing: bool,
+    /// SERVER_TIMING：响应头 Server-Timing 带上数据库耗时和总耗时
+    pub server_timing: bool,
+    /// TRACK_LAST_ACCESS：写回命中计数时顺带记录 last_accessed_at，并挂载 GET /admin/stale
+    pub track_last_access: bool,
+    pub hit_flush_interval: Duration,
+    /// SKIP_SELF_CHECK：跳过启动自检（测试、只读数据库用户）
+    pub skip_self_check: bool,
+    pub expired_sweep_interval: Duration,
+    pub capacity_check_interval: Duration,
+    /// 短码空间用量超过这个比例时告警（CODE_CAPACITY_WARN_FRACTION）
+    pub capacity_warn_fraction: f64,
+    pub idempotency_ttl_secs: i64,
+    pub max_value_len: usize,
+    /// VALUE_PATTERN：新建映射时 value 必须匹配的正则，启动时编译好
+    pub value_pattern: Option<Regex>,
+    pub max_body_bytes: usize,
+    /// POST /import 导入内容的大小 / 行数上限（IMPORT_MAX_FILE_BYTES / IMPORT_MAX_LINES）
+    pub import_max_file_bytes: u64,
+    pub import_max_lines: usize,
+    pub max_batch_body_bytes: usize,
+    pub decode_cache_max_age_secs: i64,
+    pub decode_lru_capacity: usize,
+    /// DECODE_LRU_WARMUP：启动时按 hit_count 预热进 LRU 的条数，0 为不预热
+    pub decode_lru_warmup: usize,
+    /// DECODE_LRU_DUMP_PATH：退出时把 LRU 里的 key 写到这个文件，下次启动时按它预热；不设置则不启用
+    pub decode_lru_dump_path: Option<String>,
+    pub shutdown_drain_timeout: Duration,
+    /// 单个请求的处理时限（REQUEST_TIMEOUT_SECS），超时返回 408
+    pub request_timeout: Duration,
+    /// POST /admin/vacuum 等待 VACUUM 完成的时限（VACUUM_TIMEOUT_SECS）
+    pub vacuum_timeout: Duration,
+    /// GET /count 结果的缓存时间（COUNT_CACHE_TTL_SECS），0 为不缓存
+    pub count_cache_ttl: Duration,
+    /// 响应 gzip 压缩级别（COMPRESSION_LEVEL，1..=9），0 为不压缩响应
+    pub compression_level: u32,
+    pub encode_max_attempts: u32,
+    pub normalize: Normalizer,
+    /// 未设置 ENCODE_RATE_LIMIT_PER_SEC 时为 None（不限流）
+    pub encode_rate_limit: Option<RateLimitConfig>,
+    /// SCAN_DETECT_THRESHOLD 为 0（默认）时为 None（不做扫描检测）
+    pub scan_detect: Option<ScanDetectConfig>,
+    /// 未设置 API_KEYS 时为 None（不启用鉴权）
+    pub api_keys: Option<Arc<ApiKeys>>,
+    pub require_api_key_for_decode: bool,
+    /// AUDIT_LOG：在写映射的事务里同时写 audit_log 表
+    pub audit_log: bool,
+    /// TRUST_PROXY：客户端 IP 取 X-Forwarded-For（限流和审计日志共用）
+    pub trust_proxy: bool,
+    /// 未设置 ALLOWED_ORIGINS 时为 None（不启用 CORS）
+    pub allowed_origins: Option<Arc<AllowedOrigins>>,
+}
+
+pub struct TlsFiles {
+    pub cert: String,
+    pub key: String,
+}
+
+pub struct ScanDetectConfig {
+    /// 窗口内未命中超过这么多次就告警
+    pub threshold: u64,
+    pub window: Duration,
+}
+
+pub struct RateLimitConfig {
+    pub rate: f64,
+    pub burst: f64,
+    pub trust_proxy: bool,
+}
+
+impl Config {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Self::load(&Env {
+            lookup: &|name| std::env::var(name).ok(),
+        })
+    }
+
+    /// 测试用：只从给定的键值表读配置，不受运行测试时的环境变量影响
+    #[cfg(test)]
+    pub fn from_vars(vars: &[(&str, &str)]) -> anyhow::Result<Self> {
+        Self::load(&Env {
+            lookup: &|name| {
+                vars.iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.to_string())
+            },
+        })
+    }
+
+    fn load(env: &Env) -> anyhow::Result<Self> {
+        let db_url = env
+            .string("DATABASE_URL")
+            .unwrap_or_else(|| "sqlite://./shortcodes.db".to_string());
+        let backend = Backend::from_url(&db_url)?;
+        let db_read_url = env.string("DATABASE_URL_READ");
+        if let Some(url) = &db_read_url
+            && Backend::from_url(url)? != backend
+        {
+            anyhow::bail!(
+                "invalid DATABASE_URL_READ={url} (must use the same backend as DATABASE_URL)"
+            );
+        }
+
+        let trust_proxy = env.flag("TRUST_PROXY");
+        let encode_rate: f64 = env.or("ENCODE_RATE_LIMIT_PER_SEC", 0.0)?;
+        if !(encode_rate >= 0.0 && encode_rate.is_finite()) {
+            anyhow::bail!("invalid ENCODE_RATE_LIMIT_PER_SEC={encode_rate} (must be >= 0)");
+        }
+        let encode_rate_limit = if encode_rate > 0.0 {
+            let burst: f64 = env.or("ENCODE_RATE_LIMIT_BURST", encode_rate.ceil().max(1.0))?;
+            if !(burst >= 1.0 && burst.is_finite()) {
+                anyhow::bail!("invalid ENCODE_RATE_LIMIT_BURST={burst} (must be >= 1)");
+            }
+            Some(RateLimitConfig {
+                rate: encode_rate,
+                burst,
+                trust_proxy,
+            })
+        } else {
+            None
+        };
+
+        let scan_threshold: u64 = env.or("SCAN_DETECT_THRESHOLD", 0)?;
+        let scan_detect = if scan_threshold > 0 {
+            Some(ScanDetectConfig {
+                threshold: scan_threshold,
+                window: Duration::from_secs(env.positive("SCAN_DETECT_WINDOW_SECS", 60)?),
+            })
+        } else {
+            None
+        };
+
+        let decode_cache_max_age_secs: i64 = env.or("DECODE_CACHE_MAX_AGE_SECS", 300)?;
+        if decode_cache_max_age_secs < 0 {
+            anyhow::bail!(
+                "invalid DECODE_CACHE_MAX_AGE_SECS={decode_cache_max_age_secs} (must be >= 0)"
+            );
+        }
+
+        let capacity_warn_fraction: f64 = env.or("CODE_CAPACITY_WARN_FRACTION", 0.9)?;
+        if !(capacity_warn_fraction > 0.0 && capacity_warn_fraction <= 1.0) {
+            anyhow::bail!(
+                "invalid CODE_CAPACITY_WARN_FRACTION={capacity_warn_fraction} (must be in (0, 1])"
+            );
+        }
+
+        let compression_level: u32 = env.or("COMPRESSION_LEVEL", 6)?;
+        if compression_level > 9 {
+            anyhow::bail!("invalid COMPRESSION_LEVEL={compression_level} (must be 0..=9)");
+        }
+
+        let tls = match (env.string("TLS_CERT"), env.string("TLS_KEY")) {
+            (Some(cert), Some(key)) => Some(TlsFiles { cert, key }),
+            (None, None) => None,
+            _ => anyhow::bail!("TLS_CERT and TLS_KEY must be set together"),
+        };
+
+        // 跳转本身就是一次 decode，关掉 decode 之后不该还能靠它读出 value
+        let (redirect_mode, disable_decode) =
+            (env.flag("REDIRECT_MODE"), env.flag("DISABLE_DECODE"));
+        if redirect_mode && disable_decode {
+            anyhow::bail!("REDIRECT_MODE cannot be combined with DISABLE_DECODE");
+        }
+
+        // 默认按连接数放大：命中缓存、不查库的请求很便宜，但排队等连接的请求多到这个数就该拒了
+        let pool = pool_config(env)?;
+        let max_concurrent_requests =
+            env.or("MAX_CONCURRENT_REQUESTS", pool.max_connections as usize * 8)?;
+
+        Ok(Config {
+            log_format: env.or("LOG_FORMAT", LogFormat::Text)?,
+            field_case: env.or("FIELD_CASE", FieldCase::Snake)?,
+            db_url,
+            db_read_url,
+            value_hash_dedup: env.flag("VALUE_HASH_DEDUP"),
+            listen_addr: env
+                .string("LISTEN_ADDR")
+                .unwrap_or_else(|| "0.0.0.0:3000".to_string()),
+            tls,
+            http2: env.flag("HTTP2"),
+            backend,
+            pool,
+            max_concurrent_requests,
+            code: code_config(env)?,
+            redirect_mode,
+            disable_encode: env.flag("DISABLE_ENCODE"),
+            disable_decode,
+            base_url: env.string("BASE_URL").map(parse_base_url).transpose()?,
+            disable_metrics: env.flag("DISABLE_METRICS"),
+            disable_openapi: env.flag("DISABLE_OPENAPI"),
+            soft_delete: env.flag("SOFT_DELETE"),
+            debug_fields: env.flag("DEBUG_FIELDS"),
+            debug_timing: env.flag("DEBUG_TIMING"),
+            server_timing: env.flag("SERVER_TIMING"),
+            track_last_access: env.flag("TRACK_LAST_ACCESS"),
+            hit_flush_interval: Duration::from_secs(env.positive("HIT_FLUSH_INTERVAL_SECS", 5)?),
+            skip_self_check: env.flag("SKIP_SELF_CHECK"),
+            expired_sweep_interval: Duration::from_secs(
+                env.positive("EXPIRED_SWEEP_INTERVAL_SECS", 60)?,
+            ),
+            capacity_check_interval: Duration::from_secs(
+                env.positive("CAPACITY_CHECK_INTERVAL_SECS", 60)?,
+            ),
+            capacity_warn_fraction,
+            idempotency_ttl_secs: env.positive("IDEMPOTENCY_TTL_SECS", 86_400)?,
+            max_value_len: env.positive("MAX_VALUE_LEN", 2048)?,
+            value_pattern: env
+                .string("VALUE_PATTERN")
+                .map(parse_value_pattern)
+                .transpose()?,
+            max_body_bytes: env.positive("MAX_BODY_BYTES", 64 * 1024)?,
+            import_max_file_bytes: env.positive("IMPORT_MAX_FILE_BYTES", 100 * 1024 * 1024)?,
+            import_max_lines: env.positive("IMPORT_MAX_LINES", 1_000_000)?,
+            max_batch_body_bytes: env.positive("MAX_BATCH_BODY_BYTES", 4 * 1024 * 1024)?,
+            decode_cache_max_age_secs,
+            decode_lru_capacity: env.or("DECODE_LRU_CAPACITY", 0)?,
+            decode_lru_warmup: env.or("DECODE_LRU_WARMUP", 0)?,
+            decode_lru_dump_path: env.string("DECODE_LRU_DUMP_PATH"),
+            shutdown_drain_timeout: Duration::from_secs(env.or("SHUTDOWN_DRAIN_TIMEOUT_SECS", 30)?),
+            request_timeout: Duration::from_secs(env.positive("REQUEST_TIMEOUT_SECS", 30)?),
+            vacuum_timeout: Duration::from_secs(env.positive("VACUUM_TIMEOUT_SECS", 600)?),
+            count_cache_ttl: Duration::from_secs(env.or("COUNT_CACHE_TTL_SECS", 5)?),
+            compression_level,
+            encode_max_attempts: env.positive("ENCODE_MAX_ATTEMPTS", 3)?,
+            normalize: Normalizer {
+                trim: env.or("NORMALIZE_TRIM", TrimMode::Off)?,
+                lowercase_host: env.flag("NORMALIZE_URL_LOWERCASE_HOST"),
+                strip_default_port: env.flag("NORMALIZE_URL_STRIP_DEFAULT_PORT"),
+                strip_trailing_slash: env.flag("NORMALIZE_URL_STRIP_TRAILING_SLASH"),
+            },
+            encode_rate_limit,
+            scan_detect,
+            api_keys: env.string("API_KEYS").and_then(|v| ApiKeys::parse(&v)),
+            require_api_key_for_decode: env.flag("REQUIRE_API_KEY_FOR_DECODE"),
+            audit_log: env.flag("AUDIT_LOG"),
+            trust_proxy,
+            allowed_origins: env
+                .string("ALLOWED_ORIGINS")
+                .map(|v| AllowedOrigins::parse(&v))
+                .transpose()?
+                .flatten(),
+        })
+    }
+}
+
+fn parse_value_pattern(raw: String) -> anyhow::Result<Regex> {
+    Regex::new(&raw).map_err(|e| {
+        // 语法错误的详细信息（位置、原因）不在 Display 里
+        let detail = e
+            .syntax_error()
+            .map_or_else(|| e.to_string(), ToString::to_string);
+        anyhow::anyhow!("invalid VALUE_PATTERN={raw:?}: {detail}")
+    })
+}
+
+/// BASE_URL 必须是不带查询串和 fragment 的 http(s) URL，后面要直接拼上 `/{code}`
+fn parse_base_url(raw: String) -> anyhow::Result<String> {
+    let valid = url::Url::parse(&raw).ok().is_some_and(|u| {
+        matches!(u.scheme(), "http" | "https") && u.query().is_none() && u.fragment().is_none()
+    });
+    if !valid {
+        anyhow::bail!("invalid BASE_URL={raw} (must be an http(s) URL without query or fragment)");
+    }
+    Ok(raw)
+}
+
+fn pool_config(env: &Env) -> anyhow::Result<PoolConfig> {
+    // SQLITE_MAX_CONNECTIONS 是旧名字，继续兼容
+    let max_default: u32 = env.positive("SQLITE_MAX_CONNECTIONS", 5)?;
+    let max_connections: u32 = env.positive("DB_MAX_CONNECTIONS", max_default)?;
+    let min_connections: u32 = env.or("DB_MIN_CONNECTIONS", 0)?;
+    if min_connections > max_connections {
+        anyhow::bail!(
+            "invalid DB_MIN_CONNECTIONS={min_connections} (must be <= DB_MAX_CONNECTIONS={max_connections})"
+        );
+    }
+    Ok(PoolConfig {
+        max_connections,
+        min_connections,
+        acquire_timeout: Duration::from_secs(env.positive("DB_ACQUIRE_TIMEOUT_SECS", 30)?),
+        idle_timeout: Duration::from_secs(env.positive("DB_IDLE_TIMEOUT_SECS", 600)?),
+        sqlite_busy_timeout: Duration::from_millis(env.or("SQLITE_BUSY_TIMEOUT_MS", 5_000)?),
+        sqlite_wal: !env.flag("SQLITE_DISABLE_WAL"),
+    })
+}
+
+fn code_config(env: &Env) -> anyhow::Result<CodeConfig> {
+    let min_len: usize = env.or("CODE_MIN_LEN", 2)?;
+    let max_len: usize = env.or("CODE_MAX_LEN", 5)?;
+
+    // 大小写不敏感模式默认用 base36，且字符集里不能出现大写字母（否则小写化之后就对不上了）
+    let case_insensitive = env.flag("CASE_INSENSITIVE");
+    let charset = match env.string("CODE_CHARSET") {
+        Some(v) => parse_charset(&v)?,
+        None if case_insensitive => BASE36_CHARSET.to_vec(),
+        None => CHARSET.to_vec(),
+    };
+    if case_insensitive && charset.iter().any(u8::is_ascii_uppercase) {
+        anyhow::bail!(
+            "CASE_INSENSITIVE requires a CODE_CHARSET without uppercase letters (e.g. base36)"
+        );
+    }
+    let charset = match env.string("CODE_CHARSET_ORDER") {
+        Some(v) => parse_charset_order(&v, &charset)?,
+        None => charset,
+    };
+
+    // id 是 i64：base^max_len 必须放得下，否则最长的短码永远填不满
+    let max_len_ok = u32::try_from(max_len)
+        .ok()
+        .and_then(|len| (charset.len() as i64).checked_pow(len))
+        .is_some();
+    if min_len == 0 || min_len > max_len || !max_len_ok {
+        anyhow::bail!(
+            "invalid code length bounds: CODE_MIN_LEN={min_len}, CODE_MAX_LEN={max_len} \
+             (need 1 <= min <= max, and {}^max must fit in i64)",
+            charset.len()
+        );
+    }
+
+    let strategy: CodeStrategy = env.or("CODE_STRATEGY", CodeStrategy::Sequential)?;
+
+    // 保留的编号必须比 max_len 位能表示的最大编号小，否则一个短码都分配不出来
+    let reserved_below_id: i64 = env.or("RESERVED_BELOW_ID", 0)?;
+    let max_id = (charset.len() as i64).pow(max_len as u32) - 1;
+    if !(0..=max_id).contains(&reserved_below_id) {
+        anyhow::bail!(
+            "invalid RESERVED_BELOW_ID={reserved_below_id} (must be 0..={max_id} for CODE_MAX_LEN={max_len})"
+        );
+    }
+    // 第一个编号必须落在短码空间内；step 只要求为正，太大时只是更早耗尽
+    let id_offset: i64 = env.or("ID_OFFSET", 1)?;
+    if !(1..=max_id).contains(&id_offset) {
+        anyhow::bail!(
+            "invalid ID_OFFSET={id_offset} (must be 1..={max_id} for CODE_MAX_LEN={max_len})"
+        );
+    }
+    let id_step: i64 = env.positive("ID_STEP", 1)?;
+
+    let feistel = match strategy {
+        CodeStrategy::Feistel => {
+            let key = env.string("CODE_FEISTEL_KEY").ok_or_else(|| {
+                anyhow::anyhow!("CODE_FEISTEL_KEY is required when CODE_STRATEGY=feistel")
+            })?;
+            // 置换范围 = 保留区间之后的全部编号：[1, max_id - (reserved_below_id - 1)]，
+            // 置换完再平移，保留的编号不会被置换出来
+            let domain = (max_id - (reserved_below_id - 1).max(0)) as u64;
+            let feistel = Feistel::new(key.as_bytes(), domain);
+            // 启动自检：两端各抽一段 id 验证置换可逆、且不越界
+            for id in (1..=domain.min(1_000)).chain(domain.saturating_sub(1_000).max(1)..=domain) {
+                let scrambled = feistel.scramble_id(id);
+                if !(1..=domain).contains(&scrambled) || feistel.unscramble_id(scrambled) != id {
+                    anyhow::bail!("feistel permutation self-check failed at id {id}");
+                }
+            }
+            Some(Arc::new(feistel))
+        }
+        _ => None,
+    };
+
+    Ok(CodeConfig {
+        min_len,
+        max_len,
+        charset: charset.into(),
+        strategy,
+        random_max_attempts: env.positive("CODE_RANDOM_MAX_ATTEMPTS", 8)?,
+        feistel,
+        case_insensitive,
+        checksum: env.flag("CODE_CHECKSUM"),
+        prefix: parse_code_affix(env, "CODE_PREFIX", case_insensitive)?.into(),
+        suffix: parse_code_affix(env, "CODE_SUFFIX", case_insensitive)?.into(),
+        reserved_below_id,
+        id_offset,
+        id_step,
+    })
+}
+
+/// 解析 CODE_CHARSET：预设名（base62 / base36 / base58）或直接给出字符集。
+/// 字符必须是 URL 路径里不需要转义的 ASCII（字母数字或 -_.~），且不能重复。
+fn parse_charset(v: &str) -> anyhow::Result<Vec<u8>> {
+    let charset = match v {
+        "base62" => CHARSET.to_vec(),
+        "base36" => BASE36_CHARSET.to_vec(),
+        "base58" => BASE58_CHARSET.to_vec(),
+        custom => custom.as_bytes().to_vec(),
+    };
+
+    if let Some(&b) = charset
+        .iter()
+        .find(|&&b| !(b.is_ascii_alphanumeric() || b"-_.~".contains(&b)))
+    {
+        anyhow::bail!(
+            "invalid CODE_CHARSET: character {:?} is not allowed",
+            b as char
+        );
+    }
+    if let Some((i, &b)) = charset
+        .iter()
+        .enumerate()
+        .find(|&(i, b)| charset[..i].contains(b))
+    {
+        anyhow::bail!(
+            "invalid CODE_CHARSET: duplicate character {:?} at position {i}",
+            b as char
+        );
+    }
+    if charset.len() < MIN_CHARSET_LEN {
+        anyhow::bail!(
+            "invalid CODE_CHARSET: need at least {MIN_CHARSET_LEN} characters, got {}",
+            charset.len()
+        );
+    }
+    Ok(charset)
+}
+
+/// 解析 CODE_CHARSET_ORDER：必须恰好是当前字符集的一个重排（字符相同、只是顺序不同），
+/// 重排后下标变了，同一个 id 会编码成不同的短码
+fn parse_charset_order(v: &str, charset: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let order = v.as_bytes();
+    if let Some(&b) = order.iter().find(|b| !charset.contains(b)) {
+        anyhow::bail!(
+            "invalid CODE_CHARSET_ORDER: character {:?} is not in CODE_CHARSET",
+            b as char
+        );
+    }
+    if let Some((i, &b)) = order
+        .iter()
+        .enumerate()
+        .find(|&(i, b)| order[..i].contains(b))
+    {
+        anyhow::bail!(
+            "invalid CODE_CHARSET_ORDER: duplicate character {:?} at position {i}",
+            b as char
+        );
+    }
+    if order.len() != charset.len() {
+        anyhow::bail!(
+            "invalid CODE_CHARSET_ORDER: must be a permutation of CODE_CHARSET ({} characters), got {}",
+            charset.len(),
+            order.len()
+        );
+    }
+    Ok(order.to_vec())
+}
+
+/// CODE_PREFIX / CODE_SUFFIX 最长字节数
+const MAX_CODE_AFFIX_LEN: usize = 16;
+
+/// CODE_PREFIX / CODE_SUFFIX：和字符集一样只能用 URL 路径里不需要转义的 ASCII，但不要求在字符集里。
+/// 大小写不敏感模式下传入的 code 会整个转成小写，所以前后缀里不能有大写字母
+fn parse_code_affix(env: &Env, name: &str, case_insensitive: bool) -> anyhow::Result<String> {
+    let Some(affix) = env.string(name) else {
+        return Ok(String::new());
+    };
+    if let Some(c) = affix
+        .chars()
+        .find(|&c| !(c.is_ascii_alphanumeric() || "-_.~".contains(c)))
+    {
+        anyhow::bail!("invalid {name}={affix:?}: character {c:?} is not allowed");
+    }
+    if affix.len() > MAX_CODE_AFFIX_LEN {
+        anyhow::bail!("invalid {name}={affix:?} (must be at most {MAX_CODE_AFFIX_LEN} chars)");
+    }
+    if case_insensitive && affix.chars().any(|c| c.is_ascii_uppercase()) {
+        anyhow::bail!("invalid {name}={affix:?} (must be lowercase when CASE_INSENSITIVE is set)");
+    }
+    Ok(affix)
+}
+
+/// 配置的来源：正常运行时是进程的环境变量，测试里换成一张固定的表
+struct Env<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+}
+
+impl Env<'_> {
+    /// 非空的环境变量；未设置或只有空白视为没配置
+    fn string(&self, name: &str) -> Option<String> {
+        (self.lookup)(name).filter(|v| !v.trim().is_empty())
+    }
+
+    /// 解析环境变量，没配置时用默认值；配置了但解析失败直接报错
+    fn or<T>(&self, name: &str, default: T) -> anyhow::Result<T>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.string(name) {
+            Some(v) => v
+                .trim()
+                .parse()
+                .map_err(|e| anyhow::anyhow!("invalid {name}={v:?}: {e}")),
+            None => Ok(default),
+        }
+    }
+
+    /// 同 or，但要求大于 0
+    fn positive<T>(&self, name: &str, default: T) -> anyhow::Result<T>
+    where
+        T: FromStr + PartialOrd + Default + Display,
+        T::Err: Display,
+    {
+        let v = self.or(name, default)?;
+        if v <= T::default() {
+            anyhow::bail!("invalid {name}={v} (must be positive)");
+        }
+        Ok(v)
+    }
+
+    /// 布尔型环境变量：1 / true / yes / on（不区分大小写）视为开启
+    fn flag(&self, name: &str) -> bool {
+        (self.lookup)(name).is_some_and(|v| {
+            matches!(
+                v.trim().to_ascii_lowercase().as_str(),
+                "1" | "true" | "yes" | "on"
+            )
+        })
+    }
+}
@@ -0,0 +1,65 @@
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use std::{sync::Arc, time::Duration};
+use tower_http::cors::{AllowOrigin, CorsLayer};
+
+/// 预检结果的缓存时间
+const PREFLIGHT_MAX_AGE: Duration = Duration::from_secs(600);
+const ALLOW_METHODS: [Method; 4] = [Method::GET, Method::POST, Method::DELETE, Method::OPTIONS];
+const ALLOW_HEADERS: [HeaderName; 5] = [
+    header::AUTHORIZATION,
+    header::CONTENT_TYPE,
+    HeaderName::from_static("idempotency-key"),
+    header::IF_NONE_MATCH,
+    HeaderName::from_static("x-request-id"),
+];
+const EXPOSE_HEADERS: [HeaderName; 3] = [
+    header::ETAG,
+    header::RETRY_AFTER,
+    HeaderName::from_static("x-request-id"),
+];
+
+/// ALLOWED_ORIGINS 配置：`*` 表示任意来源，否则是逗号分隔的 origin 白名单
+pub enum AllowedOrigins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+impl AllowedOrigins {
+    /// 解析逗号分隔的 origin 列表；为空时返回 None（不启用 CORS，保持原来的行为）
+    pub fn parse(raw: &str) -> anyhow::Result<Option<Arc<Self>>> {
+        let origins: Vec<&str> = raw
+            .split(',')
+            .map(|o| o.trim().trim_end_matches('/'))
+            .filter(|o| !o.is_empty())
+            .collect();
+        if origins.is_empty() {
+            return Ok(None);
+        }
+        if origins.contains(&"*") {
+            return Ok(Some(Arc::new(AllowedOrigins::Any)));
+        }
+        let origins = origins
+            .into_iter()
+            .map(|o| {
+                HeaderValue::from_str(o)
+                    .map_err(|_| anyhow::anyhow!("invalid ALLOWED_ORIGINS entry: {o:?}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Some(Arc::new(AllowedOrigins::List(origins))))
+    }
+
+    /// 处理预检请求，并给允许的跨域请求加上 CORS 响应头；不允许的 origin 原样放行、不加 Allow-Origin。
+    /// 响应总是带 `Vary: origin, access-control-request-method, access-control-request-headers`，CDN 按 Origin 分开缓存
+    pub fn layer(&self) -> CorsLayer {
+        let origin = match self {
+            AllowedOrigins::Any => AllowOrigin::any(),
+            AllowedOrigins::List(list) => AllowOrigin::list(list.iter().cloned()),
+        };
+        CorsLayer::new()
+            .allow_origin(origin)
+            .allow_methods(ALLOW_METHODS)
+            .allow_headers(ALLOW_HEADERS)
+            .expose_headers(EXPOSE_HEADERS)
+            .max_age(PREFLIGHT_MAX_AGE)
+    }
+}
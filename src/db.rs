@@ -0,0 +1,529 @@
+use sqlx::Connection;
+use sqlx::any::AnyPoolOptions;
+use sqlx::migrate::{Migrate, Migrator};
+use std::time::Duration;
+
+use crate::value::Value;
+
+/// 通过 sqlx 的 Any 驱动统一 SQLite / PostgreSQL：SQL 里一律用 `$N` 占位符（SQLite 也支持），
+/// 只有建表等 DDL 需要按后端分支。
+pub type Pool = sqlx::AnyPool;
+pub type Tx<'c> = sqlx::Transaction<'c, sqlx::Any>;
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!("at least one database backend feature must be enabled: `sqlite` or `postgres`");
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Sqlite,
+    Postgres,
+}
+
+impl Backend {
+    /// 按 DATABASE_URL 的 scheme 选择后端，并检查对应的 cargo feature 是否编译进来了
+    pub fn from_url(db_url: &str) -> anyhow::Result<Self> {
+        let backend = if db_url.starts_with("sqlite:") {
+            Backend::Sqlite
+        } else if db_url.starts_with("postgres://") || db_url.starts_with("postgresql://") {
+            Backend::Postgres
+        } else {
+            anyhow::bail!("unsupported DATABASE_URL (expected sqlite: or postgres://): {db_url}");
+        };
+
+        let compiled = match backend {
+            Backend::Sqlite => cfg!(feature = "sqlite"),
+            Backend::Postgres => cfg!(feature = "postgres"),
+        };
+        if !compiled {
+            anyhow::bail!(
+                "this build does not include the {backend:?} backend (enable the cargo feature)"
+            );
+        }
+        Ok(backend)
+    }
+}
+
+/// 连接池参数（DB_* / SQLITE_* 环境变量），由 Config 解析
+#[derive(Debug)]
+pub struct PoolConfig {
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub acquire_timeout: Duration,
+    pub idle_timeout: Duration,
+    pub sqlite_busy_timeout: Duration,
+    /// 文件库是否启用 WAL（内存库总是不启用）
+    pub sqlite_wal: bool,
+}
+
+/// 建立连接池。SQLite 需要的 PRAGMA 在每个新连接上执行：
+/// 走 Any 驱动时 SqliteConnectOptions 只会从 URL 解析，builder 上的设置带不过去。
+pub async fn connect(db_url: &str, backend: Backend, cfg: &PoolConfig) -> anyhow::Result<Pool> {
+    sqlx::any::install_default_drivers();
+
+    let mut options = AnyPoolOptions::new()
+        .max_connections(cfg.max_connections)
+        .min_connections(cfg.min_connections)
+        .acquire_timeout(cfg.acquire_timeout)
+        .idle_timeout(cfg.idle_timeout);
+
+    if backend == Backend::Sqlite {
+        ensure_sqlite_file_exists(db_url)?;
+
+        // SQLite 是文件级锁模型：并发写入时容易 SQLITE_BUSY（code=5 / "database is locked"）。
+        // 这里设置 busy_timeout 让它等待锁释放，同时（文件库）启用 WAL 以提升并发读写能力。
+        let busy_timeout_ms = cfg.sqlite_busy_timeout.as_millis();
+        let wal = cfg.sqlite_wal && !is_sqlite_memory_db(db_url);
+        options = options.after_connect(move |conn, _meta| {
+            Box::pin(async move {
+                sqlx::query(&format!("PRAGMA busy_timeout = {busy_timeout_ms}"))
+                    .execute(&mut *conn)
+                    .await?;
+                sqlx::query("PRAGMA foreign_keys = ON")
+                    .execute(&mut *conn)
+                    .await?;
+                if wal {
+                    sqlx::query("PRAGMA journal_mode = WAL")
+                        .execute(&mut *conn)
+                        .await?;
+                    sqlx::query("PRAGMA synchronous = NORMAL")
+                        .execute(&mut *conn)
+                        .await?;
+                }
+                Ok(())
+            })
+        });
+    }
+
+    Ok(options.connect(db_url).await?)
+}
+
+/// SQLite 库的大小（字节）：page_count × page_size。checkpoint 之后就是主文件的大小
+pub async fn sqlite_size(pool: &Pool) -> Result<i64, sqlx::Error> {
+    let page_count: i64 = sqlx::query_scalar("PRAGMA page_count")
+        .fetch_one(pool)
+        .await?;
+    let page_size: i64 = sqlx::query_scalar("PRAGMA page_size")
+        .fetch_one(pool)
+        .await?;
+    Ok(page_count * page_size)
+}
+
+/// VACUUM 重写整个库文件，回收删除留下的空闲页；WAL 模式下新内容先写进 -wal 文件，
+/// 再 checkpoint(TRUNCATE) 一次主文件才会真的变小。optimize 时顺带 `PRAGMA optimize`（更新查询规划器的统计信息）
+pub async fn sqlite_vacuum(pool: &Pool, optimize: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("VACUUM").execute(pool).await?;
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(pool)
+        .await?;
+    if optimize {
+        sqlx::query("PRAGMA optimize").execute(pool).await?;
+    }
+    Ok(())
+}
+
+fn is_sqlite_memory_db(db_url: &str) -> bool {
+    match sqlite_file_path_from_url(db_url) {
+        None => db_url == "sqlite::memory:",
+        Some(p) => p == ":memory:" || p == "file::memory:",
+    }
+}
+
+fn ensure_sqlite_file_exists(db_url: &str) -> anyhow::Result<()> {
+    // sqlx sqlite 会在需要时创建文件，但这里额外做一层保证：
+    // - 若 DB 文件路径的父目录不存在，先创建目录
+    // - 若 DB 文件不存在，先 touch 创建文件
+    let Some(mut path) = sqlite_file_path_from_url(db_url) else {
+        return Ok(());
+    };
+
+    // 去掉可能的 querystring（例如 ?mode=rwc）
+    if let Some((p, _q)) = path.split_once('?') {
+        path = p;
+    }
+
+    if path.is_empty() {
+        return Ok(());
+    }
+
+    // 内存库：sqlite::memory: / :memory:
+    if path == ":memory:" || path == "file::memory:" {
+        return Ok(());
+    }
+
+    let p = std::path::Path::new(path);
+    if let Some(parent) = p.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if !p.exists() {
+        let _f = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(p)?;
+    }
+
+    Ok(())
+}
+
+fn sqlite_file_path_from_url(db_url: &str) -> Option<&str> {
+    if db_url == "sqlite::memory:" {
+        return None;
+    }
+
+    if let Some(rest) = db_url.strip_prefix("sqlite://") {
+        return Some(rest);
+    }
+
+    if let Some(rest) = db_url.strip_prefix("sqlite:") {
+        // 兼容 sqlite:./db.sqlite 或 sqlite:///abs/path.sqlite
+        return Some(rest.strip_prefix("//").unwrap_or(rest));
+    }
+
+    None
+}
+
+/// 版本化的表结构迁移，编译时从 migrations/ 嵌进二进制。两个后端的 DDL 写法不同，各一套，版本号一一对应
+static SQLITE_MIGRATOR: Migrator = sqlx::migrate!("./migrations/sqlite");
+static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+/// 建表 / 升级表结构：跑完 migrations/ 里还没执行过的迁移（已执行的版本记在 sqlx 的 _sqlx_migrations 表里），
+/// 再按配置建 value 的唯一索引。hash_dedup 为 VALUE_HASH_DEDUP：唯一索引建在 value_hash 上，而不是 value / value_bin 原文上
+pub async fn init_db(pool: &Pool, backend: Backend, hash_dedup: bool) -> Result<(), sqlx::Error> {
+    let applied = applied_migrations(pool).await?;
+
+    // 引入版本化迁移之前建的库：还没有任何迁移记录，但表已经在了，可能还是更老的结构。
+    // 先用原来的办法把它补齐到第一个迁移的结构，第一个迁移全是 IF NOT EXISTS，在这种库上只会记下版本号
+    if applied.is_empty() {
+        match backend {
+            Backend::Sqlite => upgrade_unversioned_sqlite(pool).await?,
+            Backend::Postgres => upgrade_unversioned_postgres(pool).await?,
+        }
+    }
+
+    let migrator = match backend {
+        Backend::Sqlite => &SQLITE_MIGRATOR,
+        Backend::Postgres => &POSTGRES_MIGRATOR,
+    };
+    migrator.run(pool).await?;
+    for migration in migrator.iter().filter(|m| !applied.contains(&m.version)) {
+        tracing::info!(version = migration.version, description = %migration.description, "applied migration");
+    }
+    tracing::info!(
+        version = migrator.iter().map(|m| m.version).max().unwrap_or(0),
+        "database schema is up to date"
+    );
+
+    backfill_value_hash(pool).await?;
+    match backend {
+        Backend::Sqlite => sqlite_value_indexes(pool, hash_dedup).await,
+        Backend::Postgres => value_unique_indexes(pool, hash_dedup, true).await,
+    }
+}
+
+/// 已经执行过的迁移版本；第一次启动时顺带建出 _sqlx_migrations 表
+async fn applied_migrations(pool: &Pool) -> Result<Vec<i64>, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    Ok(conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| m.version)
+        .collect())
+}
+
+/// 没有迁移记录的 SQLite 库：表不存在（新库）什么都不做，交给迁移去建；
+/// 存在则按先后加上的列逐个补齐，再把最老的表结构重建成按命名空间唯一
+async fn upgrade_unversioned_sqlite(pool: &Pool) -> Result<(), sqlx::Error> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'mappings'",
+    )
+    .fetch_one(pool)
+    .await?
+        > 0;
+    if !exists {
+        return Ok(());
+    }
+
+    add_column_if_missing(
+        pool,
+        r#"ALTER TABLE mappings ADD COLUMN decode_count INTEGER NOT NULL DEFAULT 0;"#,
+    )
+    .await?;
+    add_column_if_missing(
+        pool,
+        r#"ALTER TABLE mappings ADD COLUMN hit_count INTEGER NOT NULL DEFAULT 0;"#,
+    )
+    .await?;
+    add_column_if_missing(
+        pool,
+        r#"ALTER TABLE mappings ADD COLUMN expires_at INTEGER;"#,
+    )
+    .await?;
+    add_column_if_missing(
+        pool,
+        r#"ALTER TABLE mappings ADD COLUMN deleted_at INTEGER;"#,
+    )
+    .await?;
+    add_column_if_missing(pool, r#"ALTER TABLE mappings ADD COLUMN value_hash BLOB;"#).await?;
+    add_column_if_missing(
+        pool,
+        r#"ALTER TABLE mappings ADD COLUMN last_accessed_at INTEGER;"#,
+    )
+    .await?;
+
+    migrate_sqlite_mappings(pool).await
+}
+
+/// SQLite 的 value 唯一索引。早期的表把 (namespace, value) / (namespace, value_bin) 写成了表上的 UNIQUE 约束，删不掉：
+/// 切到哈希去重时只能重建一次表；不切换时直接沿用，不再另建一份同样的索引
+async fn sqlite_value_indexes(pool: &Pool, hash_dedup: bool) -> Result<(), sqlx::Error> {
+    let inline_value_unique = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'mappings' \
+         AND sql LIKE '%UNIQUE (namespace, value)%'",
+    )
+    .fetch_one(pool)
+    .await?
+        > 0;
+    if inline_value_unique && hash_dedup {
+        tracing::info!("rebuilding mappings table to move value uniqueness to value_hash");
+        rebuild_sqlite_mappings(pool, SQLITE_MAPPINGS_COPY_COLUMNS).await?;
+    }
+    value_unique_indexes(pool, hash_dedup, !inline_value_unique || hash_dedup).await
+}
+
+/// SQLite mappings 表当前的完整结构，重建老表时用；新库的表由迁移建。
+/// 加了改 mappings 的迁移之后这里（和下面的列清单、索引）要跟着改成最新的结构
+const SQLITE_MAPPINGS_COLUMNS: &str = r#"
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            namespace    TEXT NOT NULL DEFAULT '',
+            code         TEXT,
+            value        TEXT,
+            value_bin    BLOB,
+            value_hash   BLOB,
+            decode_count INTEGER NOT NULL DEFAULT 0,
+            hit_count    INTEGER NOT NULL DEFAULT 0,
+            expires_at   INTEGER,
+            deleted_at   INTEGER,
+            created_at   INTEGER NOT NULL DEFAULT (strftime('%s','now')),
+            last_accessed_at INTEGER,
+            UNIQUE (namespace, code)
+"#;
+
+/// 重建表时会连带删掉的索引，建完新表后重新建上
+const SQLITE_MAPPINGS_INDEXES: [&str; 2] = [
+    "CREATE INDEX IF NOT EXISTS idx_mappings_code ON mappings(code);",
+    "CREATE INDEX IF NOT EXISTS idx_mappings_expires_at ON mappings(expires_at);",
+];
+
+/// 重建表时从当前结构原样拷过去的列
+const SQLITE_MAPPINGS_COPY_COLUMNS: &str = "id, namespace, code, value, value_bin, value_hash, decode_count, hit_count, expires_at, deleted_at, created_at, \
+     last_accessed_at";
+
+/// 老库的表结构需要改约束时重建：
+/// - 最早的 value 是 NOT NULL、也没有 value_bin 列；
+/// - 引入命名空间之前 code / value / value_bin 是单列 UNIQUE，现在改成 (namespace, 列) 联合唯一。
+///
+/// SQLite 改不了列约束、也不能 ADD 一个 UNIQUE 列，只能按官方的步骤建新表、拷数据、替换。
+/// AUTOINCREMENT 的计数器要一起搬过去，保证 id 不会被复用
+async fn migrate_sqlite_mappings(pool: &Pool) -> Result<(), sqlx::Error> {
+    let has_column = |name: &'static str| {
+        sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM pragma_table_info('mappings') WHERE name = $1",
+        )
+        .bind(name)
+        .fetch_one(pool)
+    };
+    if has_column("namespace").await? > 0 {
+        return Ok(());
+    }
+    tracing::info!("migrating mappings table to per-namespace uniqueness");
+
+    // 最老的库没有 value_bin：先补一个普通列（不带 UNIQUE 可以直接 ADD），下面统一按完整列表拷贝
+    if has_column("value_bin").await? == 0 {
+        sqlx::query("ALTER TABLE mappings ADD COLUMN value_bin BLOB")
+            .execute(pool)
+            .await?;
+    }
+
+    rebuild_sqlite_mappings(
+        pool,
+        "id, code, value, value_bin, value_hash, decode_count, hit_count, expires_at, deleted_at, created_at",
+    )
+    .await
+}
+
+/// 按 SQLITE_MAPPINGS_COLUMNS 建新表，拷贝 columns 列的数据后替换老表。
+/// aliases 的外键指向 mappings：外键检查开着时 DROP TABLE 会把别名级联删光，按官方步骤在这条连接上先关掉，
+/// 完成后再打开（事务里改不了这个 PRAGMA）
+async fn rebuild_sqlite_mappings(pool: &Pool, columns: &str) -> Result<(), sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA foreign_keys = OFF")
+        .execute(&mut *conn)
+        .await?;
+    let result = rebuild_sqlite_mappings_tx(&mut conn, columns).await;
+    sqlx::query("PRAGMA foreign_keys = ON")
+        .execute(&mut *conn)
+        .await?;
+    result
+}
+
+async fn rebuild_sqlite_mappings_tx(
+    conn: &mut sqlx::AnyConnection,
+    columns: &str,
+) -> Result<(), sqlx::Error> {
+    let mut tx = conn.begin().await?;
+    let seq =
+        sqlx::query_scalar::<_, i64>("SELECT seq FROM sqlite_sequence WHERE name = 'mappings'")
+            .fetch_optional(&mut *tx)
+            .await?;
+    sqlx::query(&format!(
+        "CREATE TABLE mappings_new ({SQLITE_MAPPINGS_COLUMNS});"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(&format!(
+        "INSERT INTO mappings_new ({columns}) SELECT {columns} FROM mappings"
+    ))
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DROP TABLE mappings").execute(&mut *tx).await?;
+    sqlx::query("ALTER TABLE mappings_new RENAME TO mappings")
+        .execute(&mut *tx)
+        .await?;
+    for index in SQLITE_MAPPINGS_INDEXES {
+        sqlx::query(index).execute(&mut *tx).await?;
+    }
+    if let Some(seq) = seq {
+        sqlx::query("DELETE FROM sqlite_sequence WHERE name = 'mappings'")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO sqlite_sequence (name, seq) VALUES ('mappings', $1)")
+            .bind(seq)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// 老库的行没有 value_hash：分批算好补上（SQLite 没有内置的 SHA-256，只能在这边算）。
+/// 补完之后所有写 value 的地方都会同时写 value_hash，这里就只剩一次找不到行的查询
+async fn backfill_value_hash(pool: &Pool) -> Result<(), sqlx::Error> {
+    let mut filled = 0;
+    loop {
+        let rows = sqlx::query_as::<_, (i64, Option<String>, Option<Vec<u8>>)>(
+            "SELECT id, value, value_bin FROM mappings WHERE value_hash IS NULL LIMIT 1000",
+        )
+        .fetch_all(pool)
+        .await?;
+        if rows.is_empty() {
+            break;
+        }
+        filled += rows.len();
+        let mut tx = pool.begin().await?;
+        for (id, text, bytes) in rows {
+            sqlx::query("UPDATE mappings SET value_hash = $1 WHERE id = $2")
+                .bind(Value::from_columns(text, bytes).dedup_hash())
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+    }
+    if filled > 0 {
+        tracing::info!(rows = filled, "backfilled mappings.value_hash");
+    }
+    Ok(())
+}
+
+/// value 的唯一索引：默认建在 (namespace, value) / (namespace, value_bin) 上；VALUE_HASH_DEDUP 时只建
+/// (namespace, value_hash)，长 value 不再进 B-tree。两种模式可以来回切换，启动时删掉另一种模式的索引。
+/// create_value_indexes 为 false 时表上已经有等价的 UNIQUE 约束（SQLite 老表）
+async fn value_unique_indexes(
+    pool: &Pool,
+    hash_dedup: bool,
+    create_value_indexes: bool,
+) -> Result<(), sqlx::Error> {
+    if hash_dedup {
+        for column in ["value", "value_bin"] {
+            sqlx::query(&format!(
+                "DROP INDEX IF EXISTS uq_mappings_namespace_{column};"
+            ))
+            .execute(pool)
+            .await?;
+        }
+        sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS uq_mappings_namespace_value_hash ON mappings(namespace, value_hash);")
+            .execute(pool)
+            .await?;
+        return Ok(());
+    }
+
+    sqlx::query("DROP INDEX IF EXISTS uq_mappings_namespace_value_hash;")
+        .execute(pool)
+        .await?;
+    if create_value_indexes {
+        for column in ["value", "value_bin"] {
+            sqlx::query(&format!(
+                "CREATE UNIQUE INDEX IF NOT EXISTS uq_mappings_namespace_{column} ON mappings(namespace, {column});"
+            ))
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// SQLite 没有 ADD COLUMN IF NOT EXISTS，这里重复执行会报错，我们忽略“duplicate column name”。
+async fn add_column_if_missing(pool: &Pool, sql: &str) -> Result<(), sqlx::Error> {
+    if let Err(e) = sqlx::query(sql).execute(pool).await {
+        let msg = e.to_string();
+        if !msg.contains("duplicate column name") {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
+/// 没有迁移记录的 PostgreSQL 库：表不存在（新库）什么都不做；存在则补上后来加的列，
+/// 并把唯一性从单列改成 (namespace, 列)：老库的单列 UNIQUE 约束是 PostgreSQL 自动命名的，
+/// 新的 (namespace, code) 唯一索引由第一个迁移建
+async fn upgrade_unversioned_postgres(pool: &Pool) -> Result<(), sqlx::Error> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM information_schema.tables WHERE table_schema = current_schema() AND table_name = 'mappings'",
+    )
+    .fetch_one(pool)
+    .await?
+        > 0;
+    if !exists {
+        return Ok(());
+    }
+
+    // 老库：value 改成可空（二进制 value 存在 value_bin 里），补上 value_bin
+    sqlx::query(r#"ALTER TABLE mappings ALTER COLUMN value DROP NOT NULL;"#)
+        .execute(pool)
+        .await?;
+    for column in [
+        "value_bin BYTEA",
+        "deleted_at BIGINT",
+        "value_hash BYTEA",
+        "last_accessed_at BIGINT",
+        "namespace TEXT NOT NULL DEFAULT ''",
+    ] {
+        sqlx::query(&format!(
+            "ALTER TABLE mappings ADD COLUMN IF NOT EXISTS {column};"
+        ))
+        .execute(pool)
+        .await?;
+    }
+    for column in ["code", "value", "value_bin"] {
+        sqlx::query(&format!(
+            r#"ALTER TABLE mappings DROP CONSTRAINT IF EXISTS mappings_{column}_key;"#
+        ))
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,147 @@
+use sha2::{Digest, Sha256};
+
+const ROUNDS: usize = 8;
+
+/// 以密钥为参数的可逆置换：把 [1, domain] 内的 id 一一映射到 [1, domain]。
+///
+/// 平衡 Feistel 网络作用在刚好能装下 domain 的偶数位宽上，结果超出 domain 时继续置换
+/// （cycle walking），直到落回范围内。这样短码仍然无碰撞、长度不超过 max_len，但看不出插入顺序。
+///
+/// 这不是加密：目的只是让相邻 id 的短码看起来不相关。
+pub struct Feistel {
+    round_keys: [u64; ROUNDS],
+    half_bits: u32,
+    domain: u64,
+}
+
+impl Feistel {
+    pub fn new(key: &[u8], domain: u64) -> Self {
+        assert!(domain > 0, "feistel domain must be non-empty");
+
+        let digest = Sha256::digest(key);
+        let mut round_keys = [0u64; ROUNDS];
+        for (i, rk) in round_keys.iter_mut().enumerate() {
+            let chunk = &digest[(i % 4) * 8..(i % 4) * 8 + 8];
+            *rk = u64::from_le_bytes(chunk.try_into().expect("8-byte chunk")) ^ i as u64;
+        }
+
+        // domain 个值需要的位数，向上取偶数，左右各一半
+        let bits = u64::BITS - (domain - 1).leading_zeros();
+        let half_bits = bits.div_ceil(2).max(1);
+
+        Feistel {
+            round_keys,
+            half_bits,
+            domain,
+        }
+    }
+
+    /// id ∈ [1, domain] → [1, domain]
+    pub fn scramble_id(&self, id: u64) -> u64 {
+        debug_assert!((1..=self.domain).contains(&id));
+        let mut x = self.permute(id - 1);
+        while x >= self.domain {
+            x = self.permute(x);
+        }
+        x + 1
+    }
+
+    /// scramble_id 的逆运算
+    pub fn unscramble_id(&self, id: u64) -> u64 {
+        debug_assert!((1..=self.domain).contains(&id));
+        let mut x = self.unpermute(id - 1);
+        while x >= self.domain {
+            x = self.unpermute(x);
+        }
+        x + 1
+    }
+
+    fn mask(&self) -> u64 {
+        (1u64 << self.half_bits) - 1
+    }
+
+    fn round(&self, half: u64, rk: u64) -> u64 {
+        mix64(half ^ rk) & self.mask()
+    }
+
+    fn permute(&self, x: u64) -> u64 {
+        let mut l = x >> self.half_bits;
+        let mut r = x & self.mask();
+        for &rk in &self.round_keys {
+            (l, r) = (r, l ^ self.round(r, rk));
+        }
+        (l << self.half_bits) | r
+    }
+
+    fn unpermute(&self, x: u64) -> u64 {
+        let mut l = x >> self.half_bits;
+        let mut r = x & self.mask();
+        for &rk in self.round_keys.iter().rev() {
+            (l, r) = (r ^ self.round(l, rk), l);
+        }
+        (l << self.half_bits) | r
+    }
+}
+
+/// splitmix64 的收尾混合函数，算法固定，保证同一个密钥在任何版本下得到同样的短码
+fn mix64(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 小字符集、短 max_len 下 domain = base^max_len - 1，穷举整个范围
+    fn assert_bijection(key: &[u8], base: u64, max_len: u32) {
+        let domain = base.pow(max_len) - 1;
+        let feistel = Feistel::new(key, domain);
+        let mut seen = vec![false; domain as usize + 1];
+        for id in 1..=domain {
+            let scrambled = feistel.scramble_id(id);
+            assert!(
+                (1..=domain).contains(&scrambled),
+                "{id} -> {scrambled} out of [1, {domain}]"
+            );
+            assert!(!seen[scrambled as usize], "{id} -> {scrambled} collides");
+            seen[scrambled as usize] = true;
+            assert_eq!(feistel.unscramble_id(scrambled), id);
+        }
+    }
+
+    #[test]
+    fn scramble_is_a_bijection_on_small_domains() {
+        for (base, max_len) in [(2, 1), (2, 5), (3, 3), (10, 3), (16, 4), (62, 2)] {
+            assert_bijection(b"test-key", base, max_len);
+        }
+    }
+
+    #[test]
+    fn domain_of_one_maps_to_itself() {
+        let feistel = Feistel::new(b"test-key", 1);
+        assert_eq!(feistel.scramble_id(1), 1);
+        assert_eq!(feistel.unscramble_id(1), 1);
+    }
+
+    #[test]
+    fn round_trips_at_the_ends_of_a_large_domain() {
+        let domain = 62u64.pow(10) - 1;
+        let feistel = Feistel::new(b"test-key", domain);
+        for id in (1..=1_000).chain(domain - 1_000..=domain) {
+            let scrambled = feistel.scramble_id(id);
+            assert!((1..=domain).contains(&scrambled));
+            assert_eq!(feistel.unscramble_id(scrambled), id);
+        }
+    }
+
+    #[test]
+    fn key_changes_the_permutation() {
+        let (a, b) = (Feistel::new(b"key-a", 999), Feistel::new(b"key-b", 999));
+        assert!((1..=999).any(|id| a.scramble_id(id) != b.scramble_id(id)));
+        // 同一个密钥总是同一个置换
+        let again = Feistel::new(b"key-a", 999);
+        assert!((1..=999).all(|id| a.scramble_id(id) == again.scramble_id(id)));
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::error;
+
+use crate::db::Pool;
+
+/// 命中计数器：decode 热路径只在内存里累加，由后台任务定期批量写回 mappings.hit_count。
+///
+/// 选择批量计数而不是每次 tokio::spawn 一条 UPDATE：
+/// - 热点 code 的 N 次命中合并成一条 `hit_count = hit_count + N`，写放大小；
+/// - 累加在 Mutex 内完成、写回用原子的 `+ N`，并发 decode 不会丢计数；
+/// - 写回失败时把计数合并回内存，下次再试。
+///
+/// TRACK_LAST_ACCESS 打开时同一条 UPDATE 顺带写 last_accessed_at（写回时刻），读路径上不多一条语句，
+/// 精度是一个写回间隔。
+///
+/// 命中 LRU 缓存的 decode 不进事务，它的 decode_count 和 events 也在这里攒着、和 hit_count 一起写回；
+/// 未命中缓存的 decode 仍在自己的事务里直接写
+#[derive(Clone, Default)]
+pub struct HitCounter {
+    pending: Arc<Mutex<HashMap<i64, i64>>>,
+    decodes: Arc<Mutex<HashMap<DecodeKey, PendingDecodes>>>,
+    track_last_access: bool,
+}
+
+/// 同一秒内同一个 code 的 decode 合并成一项，写回时按次数展开成多行 events，created_at 精确到秒
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct DecodeKey {
+    id: i64,
+    code: String,
+    at: i64,
+}
+
+struct PendingDecodes {
+    /// events.value：文本 value 原文，二进制 value 为 NULL
+    value: Option<String>,
+    n: i64,
+}
+
+impl HitCounter {
+    pub fn new(track_last_access: bool) -> Self {
+        HitCounter {
+            track_last_access,
+            ..Default::default()
+        }
+    }
+
+    pub fn record(&self, id: i64) {
+        *self.pending.lock().unwrap().entry(id).or_insert(0) += 1;
+    }
+
+    /// 一次命中缓存的 decode（要计入 decode_count 和 events）；code 是请求里的 code
+    pub fn record_decode(&self, id: i64, code: &str, value: Option<&str>) {
+        let key = DecodeKey {
+            id,
+            code: code.to_string(),
+            at: crate::now_unix(),
+        };
+        let mut decodes = self.decodes.lock().unwrap();
+        decodes
+            .entry(key)
+            .or_insert_with(|| PendingDecodes {
+                value: value.map(str::to_string),
+                n: 0,
+            })
+            .n += 1;
+    }
+
+    /// 尚未写回数据库的命中次数
+    pub fn pending(&self, id: i64) -> i64 {
+        self.pending.lock().unwrap().get(&id).copied().unwrap_or(0)
+    }
+
+    /// 所有映射尚未写回数据库的命中次数之和
+    pub fn pending_total(&self) -> i64 {
+        self.pending.lock().unwrap().values().sum()
+    }
+
+    /// 把内存中的计数写回数据库
+    pub async fn flush(&self, pool: &Pool) -> Result<(), sqlx::Error> {
+        let batch = std::mem::take(&mut *self.pending.lock().unwrap());
+        let decodes = std::mem::take(&mut *self.decodes.lock().unwrap());
+        if batch.is_empty() && decodes.is_empty() {
+            return Ok(());
+        }
+
+        let sql = if self.track_last_access {
+            "UPDATE mappings SET hit_count = hit_count + $1, last_accessed_at = $3 WHERE id = $2"
+        } else {
+            "UPDATE mappings SET hit_count = hit_count + $1 WHERE id = $2"
+        };
+        let now = crate::now_unix();
+        let result = async {
+            let mut tx = pool.begin().await?;
+            for (&id, &n) in &batch {
+                let mut query = sqlx::query(sql).bind(n).bind(id);
+                if self.track_last_access {
+                    query = query.bind(now);
+                }
+                query.execute(&mut *tx).await?;
+            }
+            let mut decode_counts: HashMap<i64, i64> = HashMap::new();
+            for (key, decode) in &decodes {
+                *decode_counts.entry(key.id).or_insert(0) += decode.n;
+                for _ in 0..decode.n {
+                    sqlx::query(
+                        "INSERT INTO events (action, mapping_id, code, value, created_at) \
+                         VALUES ('decode', $1, $2, $3, $4)",
+                    )
+                    .bind(key.id)
+                    .bind(&key.code)
+                    .bind(decode.value.as_deref())
+                    .bind(key.at)
+                    .execute(&mut *tx)
+                    .await?;
+                }
+            }
+            for (&id, &n) in &decode_counts {
+                sqlx::query("UPDATE mappings SET decode_count = decode_count + $1 WHERE id = $2")
+                    .bind(n)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await
+        }
+        .await;
+
+        if result.is_err() {
+            let mut pending = self.pending.lock().unwrap();
+            for (id, n) in batch {
+                *pending.entry(id).or_insert(0) += n;
+            }
+            let mut pending = self.decodes.lock().unwrap();
+            for (key, decode) in decodes {
+                pending
+                    .entry(key)
+                    .or_insert(PendingDecodes {
+                        value: decode.value,
+                        n: 0,
+                    })
+                    .n += decode.n;
+            }
+        }
+        result
+    }
+
+    /// 后台定期写回
+    pub async fn run_flusher(self, pool: Pool, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.flush(&pool).await {
+                error!(error = %e, "failed to flush hit counts");
+            }
+        }
+    }
+}
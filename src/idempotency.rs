@@ -0,0 +1,58 @@
+use crate::db::Pool;
+
+/// Idempotency-Key 最长字节数
+pub const MAX_KEY_LEN: usize = 255;
+
+/// 查找 ttl 秒内以该 key 完成过的 encode，返回当时的 (value, code)
+pub async fn lookup(
+    pool: &Pool,
+    key: &str,
+    ttl_secs: i64,
+    now: i64,
+) -> Result<Option<(String, String)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, String)>(
+        "SELECT value, code FROM idempotency_keys WHERE idem_key = $1 AND created_at > $2",
+    )
+    .bind(key)
+    .bind(now - ttl_secs)
+    .fetch_optional(pool)
+    .await
+}
+
+/// 记录 key 对应的结果。已有未过期的记录时保留先到的那条（并发重试只认第一次）
+pub async fn store(
+    pool: &Pool,
+    key: &str,
+    value: &str,
+    code: &str,
+    ttl_secs: i64,
+    now: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO idempotency_keys (idem_key, value, code, created_at) VALUES ($1, $2, $3, $4) \
+         ON CONFLICT(idem_key) DO UPDATE SET value = excluded.value, code = excluded.code, created_at = excluded.created_at \
+         WHERE idempotency_keys.created_at <= $5",
+    )
+    .bind(key)
+    .bind(value)
+    .bind(code)
+    .bind(now)
+    .bind(now - ttl_secs)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// 删除过期的 key，返回删除条数
+pub async fn sweep(pool: &Pool, ttl_secs: i64, now: i64) -> Result<u64, sqlx::Error> {
+    let r = sqlx::query("DELETE FROM idempotency_keys WHERE created_at <= $1")
+        .bind(now - ttl_secs)
+        .execute(pool)
+        .await?;
+    Ok(r.rows_affected())
+}
+
+/// 只有空白不同的 value 视为同一个请求（客户端重试时多带/少带了空格换行之类）
+pub fn same_value(a: &str, b: &str) -> bool {
+    a.split_whitespace().eq(b.split_whitespace())
+}
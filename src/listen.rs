@@ -0,0 +1,71 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use tokio::net::TcpListener;
+
+use crate::tls::TlsListener;
+
+/// LISTEN_ADDR 以这个前缀开头时监听 Unix domain socket，例如 `unix:/run/bpb/sock`
+const UNIX_PREFIX: &str = "unix:";
+
+/// 监听的套接字：TCP、TCP + TLS，或者（仅 unix 平台）Unix domain socket
+pub enum Listener {
+    Tcp(TcpListener),
+    Tls(TlsListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, std::path::PathBuf),
+}
+
+/// 按 LISTEN_ADDR 绑定监听：`unix:<path>` 走 Unix socket，其它按 TCP 地址解析；
+/// 给了 TLS 配置时在 TCP 上直接提供 HTTPS
+pub async fn bind(addr: &str, tls: Option<Arc<rustls::ServerConfig>>) -> anyhow::Result<Listener> {
+    let Some(path) = addr.strip_prefix(UNIX_PREFIX) else {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("failed to bind LISTEN_ADDR {addr}"))?;
+        return Ok(match tls {
+            Some(config) => Listener::Tls(TlsListener::new(listener, config)),
+            None => Listener::Tcp(listener),
+        });
+    };
+    if tls.is_some() {
+        anyhow::bail!("TLS_CERT / TLS_KEY are not supported with a unix socket LISTEN_ADDR");
+    }
+    bind_unix(path)
+}
+
+#[cfg(unix)]
+fn bind_unix(path: &str) -> anyhow::Result<Listener> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if path.is_empty() {
+        anyhow::bail!("invalid LISTEN_ADDR: unix socket path is empty");
+    }
+    let path = std::path::PathBuf::from(path);
+
+    // 上次进程没正常退出会留下 socket 文件，bind 会报 AddrInUse。
+    // 只删确实没人在监听的 socket；普通文件或仍在使用的 socket 不动，直接报错
+    if let Ok(meta) = std::fs::symlink_metadata(&path) {
+        if !meta.file_type().is_socket() {
+            anyhow::bail!("LISTEN_ADDR {} exists and is not a socket", path.display());
+        }
+        if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+            anyhow::bail!(
+                "LISTEN_ADDR {} is in use by another process",
+                path.display()
+            );
+        }
+        tracing::info!(path = %path.display(), "removing stale unix socket");
+        std::fs::remove_file(&path)
+            .with_context(|| format!("failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(&path)
+        .with_context(|| format!("failed to bind unix socket {}", path.display()))?;
+    Ok(Listener::Unix(listener, path))
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &str) -> anyhow::Result<Listener> {
+    anyhow::bail!("unix sockets are not supported on this platform")
+}
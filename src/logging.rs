@@ -0,0 +1,221 @@
+use axum::{extract::Request, response::Response};
+use serde_json::{Map, Value};
+use std::{fmt, str::FromStr, time::Duration};
+use tower_http::{
+    classify::{ServerErrorsAsFailures, SharedClassifier},
+    trace::{DefaultOnBodyChunk, DefaultOnEos, TraceLayer},
+};
+use tracing::{Event, Span, Subscriber, field::Field, info, info_span, span};
+use tracing_subscriber::{
+    EnvFilter,
+    field::RecordFields,
+    fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields, format::Writer},
+    registry::LookupSpan,
+};
+
+/// 日志格式（LOG_FORMAT）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// 人类可读的文本（默认）
+    Text,
+    /// 每条日志一行 JSON，方便日志系统采集
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => anyhow::bail!("invalid LOG_FORMAT: {other} (expected text|json)"),
+        }
+    }
+}
+
+pub fn init(format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder
+            .event_format(JsonFormat)
+            .fmt_fields(JsonFields)
+            .init(),
+    }
+}
+
+/// 访问日志用的 TraceLayer：span 里记 method / path，响应头发出时在 span 里记一条 status / latency_ms，
+/// 请求开始和 5xx 不另外打日志（错误由 handler 自己记）
+pub type AccessLog = TraceLayer<
+    SharedClassifier<ServerErrorsAsFailures>,
+    fn(&Request) -> Span,
+    (),
+    fn(&Response, Duration, &Span),
+    DefaultOnBodyChunk,
+    DefaultOnEos,
+    (),
+>;
+
+/// 每个请求一条日志：method / path / status / latency_ms。挂在 request_id 里面，日志同时带上 request_id
+pub fn access_log() -> AccessLog {
+    TraceLayer::new_for_http()
+        .make_span_with(request_span as fn(&Request) -> Span)
+        .on_request(())
+        .on_response(log_response as fn(&Response, Duration, &Span))
+        .on_failure(())
+}
+
+fn request_span(req: &Request) -> Span {
+    info_span!(target: "http", "http", method = %req.method(), path = %req.uri().path())
+}
+
+fn log_response(resp: &Response, latency: Duration, _: &Span) {
+    info!(
+        target: "http",
+        status = resp.status().as_u16(),
+        latency_ms = latency.as_micros() as f64 / 1000.0,
+        "request"
+    );
+}
+
+/// JSON 格式：`{"timestamp", "level", "target", "message", "fields": {...}}`，
+/// `fields` 里同时带上所在 span 的字段（例如 request_id）
+///
+/// tracing-subscriber 自带的 `.json()` 需要额外依赖 tracing-serde，这里直接用 serde_json 拼
+struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let meta = event.metadata();
+        // 外层 span 的字段先放，同名时事件自己的字段覆盖
+        let mut fields = Map::new();
+        for span in ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+        {
+            if let Some(formatted) = span.extensions().get::<FormattedFields<N>>()
+                && let Ok(Value::Object(span_fields)) = serde_json::from_str(&formatted.fields)
+            {
+                fields.extend(span_fields);
+            }
+        }
+        let mut visitor = JsonVisitor(fields);
+        event.record(&mut visitor);
+        let mut fields = visitor.0;
+
+        let mut line = Map::new();
+        line.insert("timestamp".into(), Value::String(rfc3339_now()));
+        line.insert("level".into(), Value::String(meta.level().as_str().into()));
+        line.insert("target".into(), Value::String(meta.target().into()));
+        if let Some(message) = fields.remove("message") {
+            line.insert("message".into(), message);
+        }
+        if !fields.is_empty() {
+            line.insert("fields".into(), Value::Object(fields));
+        }
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// JSON 格式下 span 字段的存储格式：序列化成一个 JSON 对象，JsonFormat 输出时再解析合并
+struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(Map::new());
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    /// 默认实现是在后面追加文本，会破坏 JSON，这里改成合并
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &span::Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(serde_json::from_str(&current.fields).unwrap_or_default());
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+struct JsonVisitor(Map<String, Value>);
+
+impl tracing::field::Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().into(), serde_json::json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0.insert(field.name().into(), value.to_string().into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{value:?}").into());
+    }
+}
+
+/// 当前 UTC 时间，形如 `2024-01-02T03:04:05.678Z`
+fn rfc3339_now() -> String {
+    rfc3339(std::time::SystemTime::now())
+}
+
+/// 把时间格式化成 UTC 的 RFC 3339，毫秒精度
+pub fn rfc3339(t: std::time::SystemTime) -> String {
+    let now = t.duration_since(std::time::UNIX_EPOCH).unwrap_or_default();
+    let secs = now.as_secs() as i64;
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // days since 1970-01-01 -> 公历日期（Howard Hinnant 的 civil_from_days）
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60,
+        now.subsec_millis()
+    )
+}
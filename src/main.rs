@@ -1,388 +1,4685 @@
+mod audit;
+mod auth;
+mod cache;
+mod codegen;
+mod compression;
+mod concurrency;
+mod config;
+mod cors;
+mod db;
+mod feistel;
+mod fieldcase;
+mod hits;
+mod idempotency;
+mod listen;
+mod logging;
+mod metrics;
+mod namespace;
+mod negotiate;
+mod normalize;
+mod openapi;
+mod qr;
+mod ratelimit;
+mod request_id;
+mod scan;
+mod serve;
+mod timing;
+mod tls;
+mod value;
+
+#[cfg(test)]
+mod tests;
+
 use axum::{
-    Json, Router,
-    extract::State,
-    http::StatusCode,
+    Extension, Form, Router,
+    body::{Body, BodyDataStream, Bytes},
+    error_handling::HandleErrorLayer,
+    extract::{
+        DefaultBodyLimit, FromRequest, MatchedPath, Multipart, Path, Query, Request, State,
+        rejection::{FormRejection, JsonRejection},
+    },
+    http::{HeaderMap, Method, StatusCode, Uri, header},
+    middleware::{self, Next},
     response::{IntoResponse, Response},
-    routing::post,
+    routing::{delete, get, patch, post},
 };
-use serde::{Deserialize, Serialize};
-use sqlx::{
-    Row,
-    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions, SqliteSynchronous},
+use futures_util::{Stream, TryStreamExt};
+use rand::Rng;
+use regex_automata::meta::Regex;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::{Digest, Sha256};
+use sqlx::Row;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
 };
-use std::path::Path;
-use std::{str::FromStr, time::Duration};
-use tracing::{error, info};
+use tower::{BoxError, ServiceBuilder, limit::ConcurrencyLimitLayer, load_shed::LoadShedLayer};
+use tower_http::timeout::TimeoutLayer;
+use tracing::{error, info, warn};
+
+use crate::audit::Audit;
+use crate::cache::{LruCache, TtlCache};
+use crate::codegen::CodeGenerator;
+use crate::config::Config;
+use crate::db::{Pool, Tx};
+use crate::feistel::Feistel;
+use crate::fieldcase::Json;
+use crate::hits::HitCounter;
+use crate::metrics::Metrics;
+use crate::negotiate::Format;
+use crate::normalize::Normalizer;
+use crate::ratelimit::RateLimiter;
+use crate::value::Value;
 
 #[derive(Clone)]
 struct AppState {
-    pool: sqlx::SqlitePool,
+    pool: Pool,
+    /// DATABASE_URL_READ 的连接池；None 时只读查询也走 pool
+    read_pool: Option<Pool>,
+    /// VALUE_HASH_DEDUP：value 的唯一约束在 (namespace, value_hash) 上，ON CONFLICT 要跟着换
+    value_hash_dedup: bool,
+    code: CodeConfig,
+    /// 按编号生成短码（CODE_STRATEGY 选定的实现）
+    generator: Arc<dyn CodeGenerator>,
+    hits: HitCounter,
+    metrics: Arc<Metrics>,
+    /// Idempotency-Key 的保留时间（秒）
+    idempotency_ttl_secs: i64,
+    /// value 的最大长度（UTF-8 字节数）
+    max_value_len: usize,
+    /// VALUE_PATTERN：新建 / 修改映射时 value 必须匹配的正则
+    value_pattern: Option<Regex>,
+    /// GET /decode/{code} 的 Cache-Control max-age（秒）
+    decode_cache_max_age_secs: i64,
+    /// decode 的进程内 LRU 缓存（code -> 映射），DECODE_LRU_CAPACITY=0 时关闭
+    cache: Option<Arc<LruCache<Mapping>>>,
+    /// encode 事务遇到锁冲突时的最大尝试次数（含第一次）
+    encode_max_attempts: u32,
+    /// 文本 value 在去重前的规范化
+    normalize: Normalizer,
+    /// 进程启动时刻，GET /admin/stats 的 uptime 由它算出
+    started_at: Instant,
+    /// 建表 / 迁移（init_db）完成后置为 true，之前业务接口一律 503
+    ready: Arc<AtomicBool>,
+    /// SOFT_DELETE：DELETE 只打上 deleted_at 标记，code 保留给原来的 value
+    soft_delete: bool,
+    /// DEBUG_FIELDS：允许 `?debug=true` 在响应里带上内部 id，生产环境不要开
+    debug_fields: bool,
+    /// GET /count 的结果缓存（COUNT_CACHE_TTL_SECS），key 为命名空间
+    count_cache: Arc<TtlCache<i64>>,
+    /// REDIRECT_MODE：GET /{code} 跳转已启用
+    redirect_mode: bool,
+    /// BASE_URL：对外的短链接前缀，未设置时为 None
+    base_url: Option<Arc<str>>,
+    /// AUDIT_LOG：encode / delete / import 时在同一个事务里写 audit_log
+    audit_log: bool,
+    /// TRUST_PROXY：客户端 IP 取 X-Forwarded-For
+    trust_proxy: bool,
+    /// 维护操作（VACUUM）互斥：同一时间只跑一个
+    maintenance: Arc<tokio::sync::Mutex<()>>,
+    vacuum_timeout: Duration,
+    /// multipart 上传的导入文件上限
+    import_limits: ImportLimits,
+}
+
+impl AppState {
+    /// 只读查询用的连接池（decode、stats、列表、导出等）
+    fn read_pool(&self) -> &Pool {
+        self.read_pool.as_ref().unwrap_or(&self.pool)
+    }
+
+    /// 完整短链接 `<BASE_URL>/<code>`。BASE_URL 末尾有没有 `/` 都只保留一个；
+    /// 未设置 BASE_URL 或不是默认命名空间（GET /{code} 只跳转默认命名空间）时为 None
+    fn short_url(&self, ns: &str, code: &str) -> Option<String> {
+        let base = self
+            .base_url
+            .as_deref()
+            .filter(|_| ns == namespace::DEFAULT)?;
+        Some(format!("{}/{code}", base.trim_end_matches('/')))
+    }
+}
+
+/// 短码格式配置（长度范围 + 字符集）
+#[derive(Clone)]
+struct CodeConfig {
+    min_len: usize,
+    max_len: usize,
+    /// 字符集，下标即该字符代表的数值；charset[0] 相当于前导零
+    charset: Arc<[u8]>,
+    strategy: CodeStrategy,
+    /// random 策略下撞码的最大重试次数
+    random_max_attempts: u32,
+    /// feistel 策略下对 id 做的可逆置换
+    feistel: Option<Arc<Feistel>>,
+    /// CASE_INSENSITIVE：传入的 code 统一转小写再处理（字符集里不能有大写字母）
+    case_insensitive: bool,
+    /// CODE_CHECKSUM：短码末尾追加一位 Luhn mod N 校验字符（不计入 min_len / max_len）
+    checksum: bool,
+    /// CODE_PREFIX / CODE_SUFFIX：加在每个短码前后的固定字符串（可以不在字符集里），默认为空。
+    /// 存库和对外的都是带前后缀的完整短码，不计入 min_len / max_len
+    prefix: Arc<str>,
+    suffix: Arc<str>,
+    /// RESERVED_BELOW_ID：自动分配时跳过 [1, reserved_below_id) 这段编号，留给自定义短码；0 表示不保留
+    reserved_below_id: i64,
+    /// ID_OFFSET / ID_STEP：第 n 个自动分配的编号为 id_offset + (n - 1) * id_step，默认 1 / 1（即 n 本身）。
+    /// 多个写实例各用一个 offset、相同的 step，生成的短码互不重叠，之后可以直接合并
+    id_offset: i64,
+    id_step: i64,
+}
+
+impl CodeConfig {
+    /// 大小写不敏感模式下把 code 转成存储用的小写形式
+    fn canonicalize(&self, code: &str) -> String {
+        if self.case_insensitive {
+            code.to_ascii_lowercase()
+        } else {
+            code.to_string()
+        }
+    }
+
+    /// 短码空间总容量：min_len..=max_len 各长度的 base^len 之和
+    fn max_capacity(&self) -> u64 {
+        let base = self.charset.len() as u64;
+        (self.min_len..=self.max_len)
+            .map(|len| base.saturating_pow(len as u32))
+            .fold(0u64, u64::saturating_add)
+    }
+
+    /// 自动分配能用到的编号空间大小：sequential / feistel 为 max_len 位能表示的最大数（平移前的 id 上限），
+    /// random 为 max_len 位短码的个数。config 已保证 base^max_len 放得下 i64
+    fn auto_capacity(&self) -> u64 {
+        let space = (self.charset.len() as u64).pow(self.max_len as u32);
+        match self.strategy {
+            CodeStrategy::Random => space,
+            CodeStrategy::Sequential | CodeStrategy::Feistel => space - 1,
+        }
+    }
+
+    /// 完整短码的长度范围（开启校验位时比 min_len..=max_len 多一位）
+    fn code_len_bounds(&self) -> (usize, usize) {
+        let extra = usize::from(self.checksum);
+        (self.min_len + extra, self.max_len + extra)
+    }
+
+    /// 短码主体 -> 完整短码：开启 CODE_CHECKSUM 时在末尾追加校验字符（只按主体计算），再加上前后缀
+    fn full_code(&self, mut body: String) -> String {
+        if self.checksum {
+            body.push(self.charset[luhn_check_index(&self.charset, body.as_bytes())] as char);
+        }
+        format!("{}{body}{}", self.prefix, self.suffix)
+    }
+
+    fn exhausted(&self) -> ApiError {
+        ApiError::Exhausted {
+            max_len: self.max_len,
+            max_capacity: self.max_capacity(),
+        }
+    }
+}
+
+/// 短码生成策略（CODE_STRATEGY）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CodeStrategy {
+    /// 自增 id 直接编码（默认），紧凑但可被遍历
+    Sequential,
+    /// 随机生成，避免从短码推测出其它短码
+    Random,
+    /// 自增 id 先经过密钥置换再编码：依然紧凑、无碰撞，但隐藏了插入顺序
+    Feistel,
+}
+
+impl FromStr for CodeStrategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequential" => Ok(CodeStrategy::Sequential),
+            "random" => Ok(CodeStrategy::Random),
+            "feistel" => Ok(CodeStrategy::Feistel),
+            other => {
+                anyhow::bail!("invalid CODE_STRATEGY: {other} (expected sequential|random|feistel)")
+            }
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
 enum ApiError {
     #[error("{0}")]
     BadRequest(String),
+    #[error("unauthorized")]
+    Unauthorized,
     #[error("not found")]
     NotFound,
-    #[error("short code space exhausted (max 5 base62 chars)")]
-    Exhausted,
+    /// 映射已被（软）删除
+    #[error("gone")]
+    Gone,
+    /// code 已经用 POST /reserve 预留，还没有填 value
+    #[error("code is reserved")]
+    Reserved,
+    #[error("{0}")]
+    Conflict(String),
+    /// `?fail_if_exists=true` 时 value 已经有 code，值为已有的 code
+    #[error("value already exists")]
+    AlreadyExists(String),
+    /// 被限流，值为建议的重试等待秒数（Retry-After）
+    #[error("too many requests")]
+    RateLimited(u64),
+    /// 启动中（数据库迁移还没跑完）
+    #[error("service is starting up, please retry")]
+    NotReady,
+    /// 处理超过了 REQUEST_TIMEOUT_SECS
+    #[error("request timed out")]
+    Timeout,
+    /// 请求体的 Content-Encoding 不是 gzip / identity
+    #[error("unsupported content-encoding: {0}")]
+    UnsupportedEncoding(String),
+    /// 请求体超过 MAX_BODY_BYTES / MAX_BATCH_BODY_BYTES
+    #[error("request body too large")]
+    PayloadTooLarge,
+    /// POST /import 的导入内容超过 IMPORT_MAX_FILE_BYTES / IMPORT_MAX_LINES，值为具体超了哪一项
+    #[error("{0}")]
+    ImportFileTooLarge(String),
+    /// 请求体的 Content-Type 不对，值为期望的类型
+    #[error("expected content-type: {0}")]
+    UnsupportedContentType(&'static str),
+    /// 在处理的请求已经到了 MAX_CONCURRENT_REQUESTS
+    #[error("server is overloaded, please retry")]
+    Overloaded,
+    #[error("short code space exhausted (max {max_len} chars)")]
+    Exhausted { max_len: usize, max_capacity: u64 },
+    #[error("failed to generate a unique random code after {0} attempts")]
+    RandomCodeCollision(u32),
     #[error(transparent)]
-    Sqlx(#[from] sqlx::Error),
+    Sqlx(sqlx::Error),
+}
+
+/// 请求体 JSON：和 axum 的 Json 一样反序列化，但解析失败（语法错误、缺字段、类型不对、Content-Type 不对）
+/// 时按 ApiError 返回统一的错误 JSON，而不是 axum 默认的纯文本 400 / 415 / 422。
+/// 请求体超过大小限制同样返回错误 JSON（413），其它拒绝（读请求体失败等）保持 axum 原来的响应
+struct JsonBody<T>(T);
+
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match axum::Json::<T>::from_request(req, state).await {
+            Ok(axum::Json(body)) => Ok(JsonBody(body)),
+            Err(rejection) => Err(json_rejection(rejection)),
+        }
+    }
+}
+
+/// serde 的错误信息（带字段路径和行列号）在 rejection 的 source 里，body_text 前面还有一段 axum 自己的前缀
+fn rejection_detail(e: &dyn std::error::Error) -> String {
+    e.source()
+        .map_or_else(|| e.to_string(), ToString::to_string)
+}
+
+fn json_rejection(rejection: JsonRejection) -> Response {
+    match rejection {
+        JsonRejection::JsonSyntaxError(e) => {
+            ApiError::BadRequest(format!("malformed JSON body: {}", rejection_detail(&e)))
+                .into_response()
+        }
+        JsonRejection::JsonDataError(e) => {
+            ApiError::BadRequest(format!("invalid JSON body: {}", rejection_detail(&e)))
+                .into_response()
+        }
+        JsonRejection::MissingJsonContentType(_) => {
+            ApiError::UnsupportedContentType("application/json").into_response()
+        }
+        JsonRejection::BytesRejection(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            ApiError::PayloadTooLarge.into_response()
+        }
+        other => other.into_response(),
+    }
+}
+
+/// 表单版的 json_rejection（POST /encode 也接受 application/x-www-form-urlencoded）
+fn form_rejection(rejection: FormRejection) -> Response {
+    match rejection {
+        FormRejection::FailedToDeserializeForm(e) => {
+            ApiError::BadRequest(format!("invalid form body: {}", rejection_detail(&e)))
+                .into_response()
+        }
+        FormRejection::FailedToDeserializeFormBody(e) => {
+            ApiError::BadRequest(format!("invalid form body: {}", rejection_detail(&e)))
+                .into_response()
+        }
+        FormRejection::BytesRejection(e) if e.status() == StatusCode::PAYLOAD_TOO_LARGE => {
+            ApiError::PayloadTooLarge.into_response()
+        }
+        other => other.into_response(),
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    /// 唯一约束冲突（并发写入同一个 value / code）不是服务端故障，按 409 返回让客户端重试或换一个 code
+    fn from(e: sqlx::Error) -> Self {
+        if is_unique_violation(&e) {
+            warn!(error = %e, "unique constraint violation");
+            ApiError::Conflict(
+                "conflicting concurrent write (value or code already exists), please retry"
+                    .to_string(),
+            )
+        } else {
+            ApiError::Sqlx(e)
+        }
+    }
+}
+
+/// 唯一约束冲突：按数据库返回的错误码判断，见 is_unique_violation_code
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    e.as_database_error()
+        .and_then(|db| db.code())
+        .is_some_and(|code| is_unique_violation_code(&code))
+}
+
+/// SQLite 的扩展错误码 SQLITE_CONSTRAINT_UNIQUE（2067）/ SQLITE_CONSTRAINT_PRIMARYKEY（1555），
+/// PostgreSQL 的 SQLSTATE unique_violation（23505）
+fn is_unique_violation_code(code: &str) -> bool {
+    matches!(code, "2067" | "1555" | "23505")
 }
 
 #[derive(Serialize)]
 struct ErrorResponse {
     error: String,
+    /// 稳定的机器可读错误码（见 ApiError::code），error 是给人看的说明、措辞可能会变
+    code: &'static str,
+    /// 以下两项只在短码空间耗尽（507）时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remaining: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_capacity: Option<u64>,
+    /// 只在 fail_if_exists 的 409 里返回：value 已有的 code
+    #[serde(skip_serializing_if = "Option::is_none")]
+    existing_code: Option<String>,
 }
 
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
+        self.into_response_as(Format::Json)
+    }
+}
+
+impl ApiError {
+    /// 每个变体对应的错误码，写进 ErrorResponse.code；客户端按它分支，改了就是不兼容变更
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::NotFound => "not_found",
+            ApiError::Gone => "gone",
+            ApiError::Reserved => "reserved",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::AlreadyExists(_) => "already_exists",
+            ApiError::RateLimited(_) => "rate_limited",
+            ApiError::NotReady => "not_ready",
+            ApiError::Overloaded => "overloaded",
+            ApiError::Timeout => "timeout",
+            ApiError::UnsupportedEncoding(_) => "unsupported_encoding",
+            ApiError::UnsupportedContentType(_) => "unsupported_content_type",
+            ApiError::PayloadTooLarge | ApiError::ImportFileTooLarge(_) => "payload_too_large",
+            ApiError::Exhausted { .. } => "exhausted",
+            ApiError::RandomCodeCollision(_) => "random_code_collision",
+            ApiError::Sqlx(_) => "internal",
+        }
+    }
+
+    /// 按协商出的格式输出错误：JSON 为 ErrorResponse，纯文本只有错误信息
+    fn into_response_as(self, format: Format) -> Response {
         let (status, msg) = match &self {
             ApiError::BadRequest(m) => (StatusCode::BAD_REQUEST, m.clone()),
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
             ApiError::NotFound => (StatusCode::NOT_FOUND, "not found".to_string()),
-            ApiError::Exhausted => (StatusCode::INSUFFICIENT_STORAGE, self.to_string()),
+            ApiError::Gone => (StatusCode::GONE, self.to_string()),
+            ApiError::Conflict(m) => (StatusCode::CONFLICT, m.clone()),
+            ApiError::AlreadyExists(_) | ApiError::Reserved => {
+                (StatusCode::CONFLICT, self.to_string())
+            }
+            ApiError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            ApiError::NotReady | ApiError::Overloaded => {
+                (StatusCode::SERVICE_UNAVAILABLE, self.to_string())
+            }
+            ApiError::Timeout => (StatusCode::REQUEST_TIMEOUT, self.to_string()),
+            ApiError::UnsupportedEncoding(_) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string())
+            }
+            ApiError::UnsupportedContentType(_) => {
+                (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string())
+            }
+            ApiError::PayloadTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, self.to_string()),
+            ApiError::ImportFileTooLarge(m) => (StatusCode::PAYLOAD_TOO_LARGE, m.clone()),
+            ApiError::Exhausted { .. } => (StatusCode::INSUFFICIENT_STORAGE, self.to_string()),
+            // 连续撞码说明 max_len 下的空间已经很拥挤了，和耗尽同样处理
+            ApiError::RandomCodeCollision(_) => {
+                (StatusCode::INSUFFICIENT_STORAGE, self.to_string())
+            }
             ApiError::Sqlx(e) => {
                 error!(error = %e, "database error");
-                (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string())
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal error".to_string(),
+                )
+            }
+        };
+        let max_capacity = match self {
+            ApiError::Exhausted { max_capacity, .. } => Some(max_capacity),
+            _ => None,
+        };
+        let existing_code = match &self {
+            ApiError::AlreadyExists(code) => Some(code.clone()),
+            _ => None,
+        };
+        let mut resp = match format {
+            Format::Json => {
+                let body = ErrorResponse {
+                    error: msg,
+                    code: self.code(),
+                    remaining: max_capacity.map(|_| 0),
+                    max_capacity,
+                    existing_code,
+                };
+                (status, Json(body)).into_response()
             }
+            Format::Text => (status, msg).into_response(),
         };
-        (status, Json(ErrorResponse { error: msg })).into_response()
+        match self {
+            ApiError::Unauthorized => {
+                resp.headers_mut().insert(
+                    header::WWW_AUTHENTICATE,
+                    header::HeaderValue::from_static("Bearer"),
+                );
+            }
+            ApiError::RateLimited(secs) => {
+                resp.headers_mut()
+                    .insert(header::RETRY_AFTER, header::HeaderValue::from(secs));
+            }
+            ApiError::NotReady | ApiError::Overloaded => {
+                resp.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    header::HeaderValue::from(UNAVAILABLE_RETRY_AFTER_SECS),
+                );
+            }
+            _ => {}
+        }
+        resp
     }
 }
 
 type ApiResult<T> = Result<Json<T>, ApiError>;
 
+/// 支持 `Accept: text/plain` 的接口：成功时 JSON 原样输出，纯文本只输出 `text` 取出的部分；错误同样按格式输出
+fn negotiated<T: Serialize>(
+    format: Format,
+    result: ApiResult<T>,
+    text: impl FnOnce(T) -> Response,
+) -> Response {
+    match (result, format) {
+        (Ok(json), Format::Json) => json.into_response(),
+        (Ok(Json(body)), Format::Text) => text(body),
+        (Err(e), format) => e.into_response_as(format),
+    }
+}
+
+/// value 的纯文本形式：文本原样输出，二进制 value 按 application/octet-stream 输出原始字节
+fn value_body(value: Value) -> Response {
+    match value {
+        Value::Text(text) => text.into_response(),
+        Value::Bytes(bytes) => {
+            ([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response()
+        }
+    }
+}
+
 #[derive(Deserialize)]
 struct EncodeRequest {
-    value: String,
+    /// 文本 value；和 value_b64 二选一
+    #[serde(default)]
+    value: Option<String>,
+    /// base64 编码的任意字节
+    #[serde(default)]
+    value_b64: Option<String>,
+    /// 指定自定义短码（vanity code），不传则按自增 id 生成
+    #[serde(default)]
+    custom_code: Option<String>,
+    /// 过期时间（秒），不传则永不过期；只在本次新建映射时生效
+    #[serde(default)]
+    ttl_seconds: Option<u64>,
+    /// 命名空间，不传为默认命名空间
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
-#[derive(Serialize)]
-struct EncodeResponse {
-    code: String,
+/// PATCH /mappings/{code} 的请求体：value 和 value_b64 二选一
+#[derive(Deserialize)]
+struct UpdateRequest {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    value_b64: Option<String>,
 }
 
+/// POST /mappings/delete 的请求体
 #[derive(Deserialize)]
-struct DecodeRequest {
-    code: String,
+struct DeleteBatchRequest {
+    codes: Vec<String>,
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
 #[derive(Serialize)]
-struct DecodeResponse {
-    value: String,
+struct DeleteBatchResponse {
+    deleted: u64,
+    /// 不存在（或已经删除）的 code，原样回显
+    not_found: Vec<String>,
+    /// 没通过 validate_code 的 code，不影响其它 code 的删除
+    invalid: Vec<InvalidCode>,
 }
 
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+#[derive(Serialize)]
+struct InvalidCode {
+    code: String,
+    error: String,
+}
 
-    let db_url =
-        std::env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite://./shortcodes.db".to_string());
-    let listen_addr = std::env::var("LISTEN_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".to_string());
+/// 只带 namespace 的查询参数（GET /decode/{code}、GET /stats/{code}、DELETE / PATCH /mappings/{code}）
+#[derive(Deserialize)]
+struct NamespaceQuery {
+    namespace: Option<String>,
+}
 
-    info!(%db_url, %listen_addr, "starting");
+/// 校验请求里的 namespace，不传时为默认命名空间
+fn parse_namespace(raw: Option<&str>) -> Result<&str, ApiError> {
+    namespace::parse(raw).map_err(ApiError::BadRequest)
+}
 
-    ensure_sqlite_file_exists(&db_url)?;
+impl EncodeRequest {
+    /// 请求里的 value；文本 value 按 NORMALIZE_* 规范化过
+    fn value(&self, normalize: &Normalizer) -> Result<Value, ApiError> {
+        let value = self.value.as_deref().map(|v| normalize.apply(v));
+        Value::from_fields(value, self.value_b64.as_deref()).map_err(ApiError::BadRequest)
+    }
+}
 
-    // SQLite 是文件级锁模型：并发写入时容易 SQLITE_BUSY（code=5 / "database is locked"）。
-    // 这里设置 busy_timeout 让它等待锁释放，同时（文件库）启用 WAL 以提升并发读写能力。
-    let sqlite_max_connections: u32 = std::env::var("SQLITE_MAX_CONNECTIONS")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(5);
-    let sqlite_busy_timeout_ms: u64 = std::env::var("SQLITE_BUSY_TIMEOUT_MS")
-        .ok()
-        .and_then(|v| v.parse().ok())
-        .unwrap_or(1_000);
+#[derive(Serialize)]
+struct EncodeResponse {
+    code: String,
+    /// 这次请求是否新建了映射；返回已有的 code（包括恢复软删除、幂等重放）时为 false
+    created: bool,
+    /// 完整短链接，只在设置了 BASE_URL 且是默认命名空间时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// 内部自增 id，只在 DEBUG_FIELDS=1 且带了 `?debug=true` 时返回
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<i64>,
+}
 
-    let is_memory_db = match sqlite_file_path_from_url(&db_url) {
-        None => db_url == "sqlite::memory:",
-        Some(p) => p == ":memory:" || p == "file::memory:",
-    };
+#[derive(Deserialize)]
+struct EncodeQuery {
+    #[serde(default)]
+    debug: bool,
+    /// 只允许新建：value 已经有 code 时返回 409（带上已有的 code），而不是幂等地返回它
+    #[serde(default)]
+    fail_if_exists: bool,
+}
 
-    let mut connect_options = SqliteConnectOptions::from_str(&db_url)?
-        .foreign_keys(true)
-        .busy_timeout(Duration::from_millis(sqlite_busy_timeout_ms));
+#[derive(Deserialize)]
+struct PreviewRequest {
+    value: String,
+}
 
-    if !is_memory_db {
-        connect_options = connect_options
-            .journal_mode(SqliteJournalMode::Wal)
-            .synchronous(SqliteSynchronous::Normal);
-    }
+#[derive(Serialize)]
+struct PreviewResponse {
+    code: String,
+    /// true 表示 value 已经存在、这是它现有的 code；false 表示按当前状态推算的新 code
+    existing: bool,
+}
 
-    let pool = SqlitePoolOptions::new()
-        .max_connections(sqlite_max_connections)
-        .connect_with(connect_options)
-        .await?;
+#[derive(Deserialize)]
+struct LookupRequest {
+    value: String,
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    init_db(&pool).await?;
+/// POST /admin/value：和 encode 一样，value / value_b64 二选一
+#[derive(Deserialize)]
+struct AdminValueRequest {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    value_b64: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    let app = Router::new()
-        .route("/encode", post(encode))
-        .route("/decode", post(decode))
-        .with_state(AppState { pool });
+#[derive(Deserialize)]
+struct RotateRequest {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    value_b64: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+    /// 旧 code 留作墓碑：之后 decode 它返回 410，也不会再分配给别的 value；默认直接释放
+    #[serde(default)]
+    tombstone: bool,
+}
 
-    let listener = tokio::net::TcpListener::bind(&listen_addr).await?;
-    axum::serve(listener, app).await?;
+#[derive(Serialize)]
+struct RotateResponse {
+    code: String,
+    old_code: String,
+    /// 新 code 的完整短链接，规则同 EncodeResponse.url
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
 
-    Ok(())
+#[derive(Deserialize)]
+struct ReserveRequest {
+    /// 要预留的 code，规则同 EncodeRequest.custom_code（开启校验位时只是主体部分）
+    code: String,
+    #[serde(default)]
+    namespace: Option<String>,
 }
 
-fn ensure_sqlite_file_exists(db_url: &str) -> anyhow::Result<()> {
-    // sqlx sqlite 会在需要时创建文件，但这里额外做一层保证：
-    // - 若 DB 文件路径的父目录不存在，先创建目录
-    // - 若 DB 文件不存在，先 touch 创建文件
-    let Some(mut path) = sqlite_file_path_from_url(db_url) else {
-        return Ok(());
-    };
+#[derive(Serialize)]
+struct ReserveResponse {
+    code: String,
+    /// 完整短链接，规则同 EncodeResponse.url
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+}
 
-    // 去掉可能的 querystring（例如 ?mode=rwc）
-    if let Some((p, _q)) = path.split_once('?') {
-        path = p;
-    }
+#[derive(Deserialize)]
+struct AliasRequest {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    value_b64: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+    /// 要新建几个别名，1..=MAX_ALIAS_COUNT
+    count: u32,
+}
 
-    if path.is_empty() {
-        return Ok(());
-    }
+#[derive(Serialize)]
+struct AliasResponse {
+    /// value 的主 code（encode 返回的那个）
+    code: String,
+    /// 本次新建的别名，都解析到同一个 value
+    aliases: Vec<String>,
+}
 
-    // 内存库：sqlite::memory: / :memory:
-    if path == ":memory:" || path == "file::memory:" {
-        return Ok(());
-    }
+/// POST /encode/alias 单次最多新建的别名数
+const MAX_ALIAS_COUNT: u32 = 100;
 
-    let p = Path::new(path);
-    if let Some(parent) = p.parent() {
-        if !parent.as_os_str().is_empty() {
-            std::fs::create_dir_all(parent)?;
-        }
-    }
+/// encode 锁冲突重试的初始退避（毫秒），之后每次翻倍
+const RETRY_BASE_DELAY_MS: u64 = 20;
 
-    if !p.exists() {
-        let _f = std::fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .open(p)?;
-    }
+/// 单次批量请求允许的最大条目数
+const MAX_BATCH_SIZE: usize = 1000;
 
-    Ok(())
-}
+/// `IN (...)` 一次最多带这么多个参数，剩下的分块查：
+/// 老版本 SQLite 每条语句最多只能绑定 999 个参数（SQLITE_MAX_VARIABLE_NUMBER）
+const IN_LIST_CHUNK: usize = 500;
 
-fn sqlite_file_path_from_url(db_url: &str) -> Option<&str> {
-    if db_url == "sqlite::memory:" {
-        return None;
-    }
+/// `IN (...)` 里的占位符：从 `$first` 开始连续 n 个
+fn in_placeholders(first: usize, n: usize) -> String {
+    (first..first + n)
+        .map(|i| format!("${i}"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-    if let Some(rest) = db_url.strip_prefix("sqlite://") {
-        return Some(rest);
-    }
+#[derive(Deserialize)]
+struct EncodeBatchRequest {
+    values: Vec<String>,
+    /// 整个批次所在的命名空间
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    if let Some(rest) = db_url.strip_prefix("sqlite:") {
-        // 兼容 sqlite:./db.sqlite 或 sqlite:///abs/path.sqlite
-        return Some(rest.strip_prefix("//").unwrap_or(rest));
-    }
+#[derive(Serialize)]
+struct EncodeBatchItem {
+    value: String,
+    code: String,
+}
 
-    None
+#[derive(Serialize)]
+struct EncodeBatchResponse {
+    codes: Vec<EncodeBatchItem>,
 }
 
-async fn init_db(pool: &sqlx::SqlitePool) -> Result<(), sqlx::Error> {
-    // value: 原始字符串（去重）
-    // code: 2-5 位短字符串（唯一）
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS mappings (
-            id          INTEGER PRIMARY KEY AUTOINCREMENT,
-            code        TEXT UNIQUE,
-            value       TEXT NOT NULL UNIQUE,
-            decode_count INTEGER NOT NULL DEFAULT 0,
-            created_at  INTEGER NOT NULL DEFAULT (strftime('%s','now'))
-        );
-        "#,
-    )
-    .execute(pool)
-    .await?;
+/// POST /encode/stream 每行一条：value 和 value_b64 二选一
+#[derive(Deserialize)]
+struct EncodeStreamLine {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    value_b64: Option<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_mappings_code ON mappings(code);"#)
-        .execute(pool)
-        .await?;
+/// POST /encode/stream 的输出行：成功带 code，失败带 error；没有 line 的 error 表示整个流中止了
+#[derive(Serialize)]
+struct EncodeStreamItem {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
-    // 向后兼容：如果老库没有 decode_count 字段，补上
-    // SQLite 没有 IF NOT EXISTS for column，这里重复执行会报错，我们忽略“duplicate column name”。
-    if let Err(e) = sqlx::query(r#"ALTER TABLE mappings ADD COLUMN decode_count INTEGER NOT NULL DEFAULT 0;"#)
-        .execute(pool)
-        .await
-    {
-        let msg = e.to_string();
-        if !msg.contains("duplicate column name") {
-            return Err(e);
-        }
-    }
+/// POST /encode/stream 每多少行提交一次事务（也是输出的粒度）
+const ENCODE_STREAM_CHUNK_SIZE: usize = 500;
 
-    // 事件表：记录每次 encode/decode 的时间
-    sqlx::query(
-        r#"
-        CREATE TABLE IF NOT EXISTS events (
-            id          INTEGER PRIMARY KEY AUTOINCREMENT,
-            action      TEXT NOT NULL, -- 'encode' | 'decode'
-            mapping_id  INTEGER,
-            code        TEXT,
-            value       TEXT,
-            created_at  INTEGER NOT NULL DEFAULT (strftime('%s','now'))
-        );
-        "#,
-    )
-    .execute(pool)
-    .await?;
+/// POST /encode/stream 输出 channel 的容量（按块计）：客户端读得慢时，最多先算好这么多块就停下来不再读请求体
+const ENCODE_STREAM_QUEUE: usize = 2;
 
-    sqlx::query(r#"CREATE INDEX IF NOT EXISTS idx_events_created_at ON events(created_at);"#)
-        .execute(pool)
-        .await?;
+#[derive(Deserialize)]
+struct DecodeRequest {
+    code: String,
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    Ok(())
+/// `{"value": "..."}`，二进制 value 为 `{"value_b64": "..."}`
+#[derive(Serialize)]
+struct DecodeResponse {
+    #[serde(flatten)]
+    value: Value,
 }
 
-async fn encode(State(state): State<AppState>, Json(req): Json<EncodeRequest>) -> ApiResult<EncodeResponse> {
-    if req.value.is_empty() {
-        return Err(ApiError::BadRequest("value is empty".to_string()));
-    }
+#[derive(Deserialize)]
+struct DecodeBatchRequest {
+    codes: Vec<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    // 快路径：已存在则直接返回
-    if let Some((id, code)) = sqlx::query_as::<_, (i64, String)>("SELECT id, code FROM mappings WHERE value = ?1")
-        .bind(&req.value)
-        .fetch_optional(&state.pool)
-        .await?
-    {
-        // 记录事件
-        sqlx::query("INSERT INTO events (action, mapping_id, code, value) VALUES ('encode', ?1, ?2, ?3)")
-            .bind(id)
-            .bind(&code)
-            .bind(&req.value)
-            .execute(&state.pool)
-            .await?;
-        return Ok(Json(EncodeResponse { code }));
-    }
+#[derive(Serialize)]
+struct DecodeBatchItem {
+    code: String,
+    /// 不存在或非法时为 null；二进制 value 也为 null，内容在 value_b64 里
+    value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value_b64: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
 
-    let mut tx = state.pool.begin().await?;
+#[derive(Serialize)]
+struct DecodeBatchResponse {
+    results: Vec<DecodeBatchItem>,
+}
 
-    // 并发安全：同一个 value 只插入一次
-    sqlx::query("INSERT INTO mappings (value) VALUES (?1) ON CONFLICT(value) DO NOTHING")
-        .bind(&req.value)
-        .execute(&mut *tx)
-        .await?;
+#[derive(Deserialize)]
+struct StatsBatchRequest {
+    codes: Vec<String>,
+    #[serde(default)]
+    namespace: Option<String>,
+}
 
-    let row = sqlx::query("SELECT id, code FROM mappings WHERE value = ?1")
-        .bind(&req.value)
-        .fetch_one(&mut *tx)
-        .await?;
+/// 和请求里的 codes 一一对应，不合法、不存在、已过期或已删除的为 null
+#[derive(Serialize)]
+struct StatsBatchResponse {
+    results: Vec<Option<StatsResponse>>,
+}
 
-    let id: i64 = row.get("id");
-    let code: Option<String> = row.get("code");
+#[derive(Clone, Serialize)]
+struct StatsResponse {
+    code: String,
+    #[serde(flatten)]
+    value: Value,
+    hit_count: i64,
+    created_at: i64,
+}
 
-    let final_code = if let Some(code) = code {
-        code
-    } else {
-        let new_code = id_to_code(id)?;
-        sqlx::query("UPDATE mappings SET code = ?1 WHERE id = ?2 AND code IS NULL")
-            .bind(&new_code)
-            .bind(id)
-            .execute(&mut *tx)
-            .await?;
+/// GET /validate/{code} 的结果：不合法时 reason 与 decode 返回 400 时的错误信息一致
+#[derive(Serialize)]
+struct ValidateResponse {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
 
-        let code = sqlx::query_scalar::<_, String>("SELECT code FROM mappings WHERE id = ?1")
-            .bind(id)
-            .fetch_one(&mut *tx)
-            .await?;
-        code
-    };
+#[derive(Serialize)]
+struct CountResponse {
+    count: i64,
+}
 
-    // 记录事件（encode 成功）
-    sqlx::query("INSERT INTO events (action, mapping_id, code, value) VALUES ('encode', ?1, ?2, ?3)")
-        .bind(id)
-        .bind(&final_code)
-        .bind(&req.value)
-        .execute(&mut *tx)
-        .await?;
+#[derive(Serialize)]
+struct AdminStatsResponse {
+    /// 可见的映射数（同 GET /count）
+    mappings_total: i64,
+    /// 所有查找路径的命中次数之和（hit_count，含尚未写回的部分）
+    decodes_total: i64,
+    /// 未启用 DECODE_LRU_CAPACITY 时不输出
+    #[serde(skip_serializing_if = "Option::is_none")]
+    decode_cache_hit_rate: Option<f64>,
+    uptime_secs: u64,
+    namespaces: Vec<NamespaceCapacity>,
+}
 
-    tx.commit().await?;
-    Ok(Json(EncodeResponse { code: final_code }))
+#[derive(Serialize)]
+struct NamespaceCapacity {
+    /// 默认命名空间不输出
+    #[serde(skip_serializing_if = "str::is_empty")]
+    namespace: String,
+    used: i64,
+    capacity: u64,
+    utilization_percent: f64,
 }
 
-async fn decode(State(state): State<AppState>, Json(req): Json<DecodeRequest>) -> ApiResult<DecodeResponse> {
-    validate_code(&req.code)?;
+/// GET /count 不带 namespace（统计全部命名空间）时的缓存 key；合法的 namespace 里不会出现 `*`
+const COUNT_ALL_KEY: &str = "*";
 
-    // 事务：读 value + decode_count++ + 写事件，保证统计不漏
-    let mut tx = state.pool.begin().await?;
+/// 「可见」的映射：已分配 code、有 value（不是预留的 code）、没被软删除、没过期（$1 为当前时间），GET /mappings 和 GET /count 共用
+const LIVE_MAPPINGS_FILTER: &str = "code IS NOT NULL AND (value IS NOT NULL OR value_bin IS NOT NULL) \
+     AND deleted_at IS NULL AND (expires_at IS NULL OR expires_at > $1)";
 
-    let row = sqlx::query("SELECT id, value FROM mappings WHERE code = ?1")
-        .bind(&req.code)
-        .fetch_optional(&mut *tx)
-        .await?
-        .ok_or(ApiError::NotFound)?;
+/// GET /mappings 默认每页条数与上限
+const DEFAULT_LIST_LIMIT: i64 = 50;
+const MAX_LIST_LIMIT: i64 = 200;
 
-    let id: i64 = row.get("id");
-    let value: String = row.get("value");
+#[derive(Deserialize)]
+struct ListParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+    /// 只列出 created_at >= 该值（unix 秒）的映射；用字符串接收，自己解析成 400
+    created_after: Option<String>,
+    /// 只列出 created_at <= 该值（unix 秒）的映射
+    created_before: Option<String>,
+}
+
+/// 解析 unix 秒形式的查询参数，未传时为 None
+fn parse_unix_param(name: &str, raw: Option<&str>) -> Result<Option<i64>, ApiError> {
+    raw.map(|raw| {
+        raw.parse::<i64>()
+            .map_err(|_| ApiError::BadRequest(format!("{name} must be an integer unix timestamp")))
+    })
+    .transpose()
+}
+
+#[derive(Serialize)]
+struct ListItem {
+    /// 默认命名空间不输出
+    #[serde(skip_serializing_if = "str::is_empty")]
+    namespace: String,
+    code: String,
+    #[serde(flatten)]
+    value: Value,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct ListResponse {
+    total: i64,
+    items: Vec<ListItem>,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    q: Option<String>,
+    limit: Option<i64>,
+    /// 只搜这个命名空间；不传时搜全部命名空间
+    namespace: Option<String>,
+}
+
+#[derive(Serialize)]
+struct SearchItem {
+    /// 默认命名空间不输出
+    #[serde(skip_serializing_if = "str::is_empty")]
+    namespace: String,
+    code: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct SearchResponse {
+    items: Vec<SearchItem>,
+    /// 匹配的条数超过 limit，items 只是前 limit 条
+    truncated: bool,
+}
+
+/// GET /admin/search 超过这个耗时打 warn 日志，提醒表已经大到不适合全表扫描
+const SLOW_SEARCH: Duration = Duration::from_secs(1);
+
+#[derive(Deserialize)]
+struct StaleParams {
+    before: Option<i64>,
+    limit: Option<i64>,
+    /// 只列这个命名空间；不传时列全部命名空间
+    namespace: Option<String>,
+}
+
+#[derive(Serialize)]
+struct StaleItem {
+    /// 默认命名空间不输出
+    #[serde(skip_serializing_if = "str::is_empty")]
+    namespace: String,
+    code: String,
+    /// 没有记录过访问时为 null
+    last_accessed_at: Option<i64>,
+    created_at: i64,
+}
+
+#[derive(Serialize)]
+struct StaleResponse {
+    items: Vec<StaleItem>,
+    /// 符合条件的条数超过 limit，items 只是最久没访问的前 limit 条
+    truncated: bool,
+}
+
+/// LIKE 模式里的字面量：转义 `\`、`%`、`_`，配合 `ESCAPE '\'` 使用
+fn escape_like(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '\\' | '%' | '_') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct VersionResponse {
+    version: &'static str,
+    git_sha: &'static str,
+    build_time: String,
+}
+
+/// 启动期间和过载时 503 响应里建议的重试等待秒数
+const UNAVAILABLE_RETRY_AFTER_SECS: u64 = 1;
+
+/// readyz 探测数据库的超时时间，避免 DB 卡住时探针也跟着挂住
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    // 配置在最前面一次性解析完，写错了直接启动失败
+    let config = Config::from_env()?;
+    logging::init(config.log_format);
+    fieldcase::init(config.field_case);
+
+    info!(db_url = %config.db_url, listen_addr = %config.listen_addr, "starting");
+    info!(strategy = ?config.code.strategy, "code strategy");
+    if config.code.reserved_below_id > 0 {
+        info!(
+            reserved_below_id = config.code.reserved_below_id,
+            "auto-assigned codes skip reserved ids"
+        );
+    }
+    if config.code.id_offset != 1 || config.code.id_step != 1 {
+        info!(
+            id_offset = config.code.id_offset,
+            id_step = config.code.id_step,
+            "auto-assigned ids are strided"
+        );
+    }
+    if config.normalize.is_enabled() {
+        info!(normalize = ?config.normalize, "value normalization enabled");
+    }
+    if config.debug_fields {
+        warn!(
+            "DEBUG_FIELDS is enabled: ?debug=true exposes internal ids, do not use in production"
+        );
+    }
+    if config.debug_timing {
+        warn!(
+            "DEBUG_TIMING is enabled: responses expose database timings, do not use in production"
+        );
+    }
+
+    info!(
+        max_connections = config.pool.max_connections,
+        min_connections = config.pool.min_connections,
+        acquire_timeout_secs = config.pool.acquire_timeout.as_secs(),
+        idle_timeout_secs = config.pool.idle_timeout.as_secs(),
+        "database pool"
+    );
+    if config.backend == db::Backend::Sqlite {
+        info!(
+            busy_timeout_ms = config.pool.sqlite_busy_timeout.as_millis() as u64,
+            wal = config.pool.sqlite_wal,
+            "sqlite pragmas"
+        );
+    }
+    let pool = db::connect(&config.db_url, config.backend, &config.pool).await?;
+    // 读池在主库之后连：主库的第一个连接先把文件切到 WAL，读池指向同一个文件时不会和它抢
+    let read_pool = match &config.db_read_url {
+        Some(url) => {
+            info!("read-only queries use DATABASE_URL_READ");
+            Some(db::connect(url, config.backend, &config.pool).await?)
+        }
+        None => None,
+    };
+
+    let state = app_state(&config, pool.clone(), read_pool);
+    let app = router(&config, state.clone());
+    tokio::spawn(
+        state
+            .hits
+            .clone()
+            .run_flusher(pool.clone(), config.hit_flush_interval),
+    );
+
+    let (shutdown_pool, shutdown_hits) = (pool.clone(), state.hits.clone());
+    let shutdown_cache = state.cache.clone().zip(config.decode_lru_dump_path.clone());
+
+    let shutdown_drain_timeout = config.shutdown_drain_timeout;
+    let tls_config = match &config.tls {
+        Some(files) => Some(tls::load_config(&files.cert, &files.key, config.http2)?),
+        None => None,
+    };
+    let listener = listen::bind(&config.listen_addr, tls_config).await?;
+    if let listen::Listener::Tls(_) = &listener {
+        info!("tls enabled");
+    }
+    #[cfg(unix)]
+    if let listen::Listener::Unix(_, path) = &listener {
+        info!(path = %path.display(), "listening on unix socket");
+        // Unix socket 没有对端 IP：不信任 X-Forwarded-For 时无法区分客户端，限流不生效
+        if config
+            .encode_rate_limit
+            .as_ref()
+            .is_some_and(|rl| !rl.trust_proxy)
+        {
+            warn!(
+                "encode rate limit needs TRUST_PROXY=1 behind a unix socket, requests will not be limited"
+            );
+        }
+    }
+
+    // 先开始监听再跑迁移：大库的迁移可能要一阵子，这期间请求拿到的是 503 + Retry-After 而不是连接被拒
+    let (init_pool, init_ready, backend) = (pool.clone(), state.ready.clone(), config.backend);
+    let hash_dedup = config.value_hash_dedup;
+    let (sweep_interval, idempotency_ttl) =
+        (config.expired_sweep_interval, config.idempotency_ttl_secs);
+    let (capacity_code, capacity_metrics) = (config.code.clone(), state.metrics.clone());
+    let (capacity_interval, warn_fraction) = (
+        config.capacity_check_interval,
+        config.capacity_warn_fraction,
+    );
+    let (warmup_cache, warmup) = (state.cache.clone(), config.decode_lru_warmup);
+    let dump_path = config.decode_lru_dump_path.clone();
+    let self_check_code = (!config.skip_self_check)
+        .then(|| (config.code.clone(), codegen::from_config(&config.code)));
+    tokio::spawn(async move {
+        if let Err(e) = db::init_db(&init_pool, backend, hash_dedup).await {
+            // 还没有处理过任何请求，没有需要收尾的状态，直接退出
+            error!(error = %e, "database initialization failed");
+            std::process::exit(1);
+        }
+        if let Some((cfg, generator)) = &self_check_code {
+            match self_check(&init_pool, cfg, generator.as_ref()).await {
+                Ok(code) => info!(code = %code, "startup self-check passed"),
+                Err(e) => {
+                    error!(error = format!("{e:#}"), "startup self-check failed");
+                    std::process::exit(1);
+                }
+            }
+        }
+        // 预热放在置 ready 之前：这期间请求仍然是 503，放开流量时热点 code 已经在缓存里
+        if let Some(cache) = warmup_cache.as_ref().filter(|_| warmup > 0) {
+            match warm_cache(&init_pool, cache, warmup).await {
+                Ok(n) => info!(entries = n, "decode lru cache warmed up"),
+                // 预热只是优化，失败了照常启动
+                Err(e) => warn!(error = %e, "failed to warm up decode lru cache"),
+            }
+        }
+        // 上次退出时的缓存内容比 hit_count 更接近当前的热点，放在后面载入，排在 LRU 表头
+        if let (Some(cache), Some(path)) = (&warmup_cache, &dump_path) {
+            match load_cache_dump(&init_pool, cache, path).await {
+                Ok(n) => info!(entries = n, path = %path, "decode lru cache restored from dump"),
+                Err(e) => {
+                    warn!(error = %e, path = %path, "failed to restore decode lru cache from dump")
+                }
+            }
+        }
+        init_ready.store(true, Ordering::Release);
+        info!("database ready");
+        tokio::spawn(watch_capacity(
+            init_pool.clone(),
+            capacity_code,
+            capacity_metrics,
+            capacity_interval,
+            warn_fraction,
+        ));
+        sweep_expired(init_pool, sweep_interval, idempotency_ttl).await;
+    });
+
+    // 收到 SIGINT/SIGTERM 后停止接受新连接，等进行中的请求处理完；
+    // 超过 SHUTDOWN_DRAIN_TIMEOUT_SECS 仍未结束的连接直接断开
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::watch::channel(());
+    let shutdown = async move {
+        shutdown_signal().await;
+        info!("shutdown signal received, draining in-flight requests");
+        let _ = shutdown_tx.send(());
+    };
+    #[cfg(unix)]
+    let mut socket_path = None;
+    let http2 = config.http2;
+    if http2 {
+        info!("http/2 enabled (h2c, and h2 via alpn with tls)");
+    }
+    let mut server = match listener {
+        listen::Listener::Tcp(listener) => tokio::spawn(serve::serve(
+            listener,
+            app,
+            http2,
+            |addr| Some(*addr),
+            shutdown,
+        )),
+        listen::Listener::Tls(listener) => tokio::spawn(serve::serve(
+            listener,
+            app,
+            http2,
+            |addr| Some(*addr),
+            shutdown,
+        )),
+        // 没有 ConnectInfo<SocketAddr>，依赖对端地址的功能（限流）拿不到 IP 时会跳过
+        #[cfg(unix)]
+        listen::Listener::Unix(listener, path) => {
+            socket_path = Some(path);
+            tokio::spawn(serve::serve(listener, app, http2, |_| None, shutdown))
+        }
+    };
+    tokio::select! {
+        biased;
+        res = &mut server => res?,
+        _ = async {
+            if shutdown_rx.changed().await.is_ok() {
+                tokio::time::sleep(shutdown_drain_timeout).await;
+            } else {
+                std::future::pending::<()>().await;
+            }
+        } => {
+            warn!(timeout_secs = shutdown_drain_timeout.as_secs(), "drain timeout elapsed, closing remaining connections");
+            server.abort();
+        }
+    }
+    info!("server stopped accepting connections");
+    #[cfg(unix)]
+    if let Some(path) = socket_path
+        && let Err(e) = std::fs::remove_file(&path)
+    {
+        warn!(path = %path.display(), error = %e, "failed to remove unix socket");
+    }
+
+    if let Err(e) = shutdown_hits.flush(&shutdown_pool).await {
+        error!(error = %e, "failed to flush hit counts on shutdown");
+    }
+    if let Some((cache, path)) = shutdown_cache {
+        match dump_cache_keys(&cache, &path) {
+            Ok(n) => info!(entries = n, path = %path, "decode lru cache keys dumped"),
+            Err(e) => warn!(error = %e, path = %path, "failed to dump decode lru cache keys"),
+        }
+    }
+    shutdown_pool.close().await;
+    info!("database pool closed, bye");
+
+    Ok(())
+}
+
+/// 按配置建出请求处理用的全部共享状态。后台任务（init_db、命中计数写回、过期清理）由调用方自己启动
+fn app_state(config: &Config, pool: Pool, read_pool: Option<Pool>) -> AppState {
+    if config.track_last_access {
+        info!("last access tracking enabled");
+    }
+    let cache = (config.decode_lru_capacity > 0).then(|| {
+        info!(
+            capacity = config.decode_lru_capacity,
+            "decode lru cache enabled"
+        );
+        Arc::new(LruCache::new(config.decode_lru_capacity))
+    });
+    AppState {
+        pool,
+        read_pool,
+        value_hash_dedup: config.value_hash_dedup,
+        started_at: Instant::now(),
+        generator: codegen::from_config(&config.code),
+        code: config.code.clone(),
+        hits: HitCounter::new(config.track_last_access),
+        metrics: Arc::new(Metrics::default()),
+        idempotency_ttl_secs: config.idempotency_ttl_secs,
+        max_value_len: config.max_value_len,
+        import_limits: ImportLimits {
+            max_bytes: config.import_max_file_bytes,
+            max_lines: config.import_max_lines,
+        },
+        value_pattern: config.value_pattern.clone(),
+        decode_cache_max_age_secs: config.decode_cache_max_age_secs,
+        cache,
+        encode_max_attempts: config.encode_max_attempts,
+        normalize: config.normalize,
+        // 迁移没跑完之前业务接口直接 503，init_db 完成后由调用方置为 true
+        ready: Arc::new(AtomicBool::new(false)),
+        soft_delete: config.soft_delete,
+        debug_fields: config.debug_fields,
+        count_cache: Arc::new(TtlCache::new(config.count_cache_ttl)),
+        redirect_mode: config.redirect_mode,
+        base_url: config.base_url.as_deref().map(Arc::from),
+        audit_log: config.audit_log,
+        trust_proxy: config.trust_proxy,
+        maintenance: Arc::new(tokio::sync::Mutex::new(())),
+        vacuum_timeout: config.vacuum_timeout,
+    }
+}
+
+/// 按配置挂载路由和中间件。写接口（含管理接口里会改数据的）和读接口分开组装，
+/// DISABLE_ENCODE / DISABLE_DECODE 时对应的一整组都不挂载，请求直接 404
+fn router(config: &Config, state: AppState) -> Router {
+    let metrics = state.metrics.clone();
+
+    // 请求体大小限制：普通接口默认 64KB，批量接口单独放宽（超出返回 413）
+    let batch_body_limit = DefaultBodyLimit::max(config.max_batch_body_bytes);
+
+    let mut write_routes = Router::new()
+        .route("/encode", post(encode))
+        .route("/encode/batch", post(encode_batch).layer(batch_body_limit));
+
+    // 按 IP 限流只加在 encode 上（防止有人刷空短码空间），decode 不受影响
+    if let Some(rl) = &config.encode_rate_limit {
+        info!(
+            rate = rl.rate,
+            burst = rl.burst,
+            trust_proxy = rl.trust_proxy,
+            "encode rate limit enabled"
+        );
+        let limiter = Arc::new(RateLimiter::new(rl.rate, rl.burst, rl.trust_proxy));
+        write_routes = write_routes.route_layer(middleware::from_fn_with_state(
+            limiter,
+            ratelimit::rate_limit,
+        ));
+    }
+
+    write_routes = write_routes
+        .route("/encode/preview", post(encode_preview))
+        // 大批量导入，不受 encode 限流；handler 马上返回流式响应，请求超时管不到后台的编码任务
+        .route("/encode/stream", post(encode_stream))
+        .route("/mappings/{code}", delete(delete_mapping));
+
+    // 会返回 value 的读接口都算 decode：关掉 decode 之后不该还能从别的接口读出 value
+    let mut read_routes = Router::new();
+    if !config.disable_decode {
+        read_routes = read_routes
+            .route("/decode", post(decode))
+            .route("/decode/batch", post(decode_batch).layer(batch_body_limit))
+            // get 同时处理 HEAD（axum 去掉响应体），handler 按 Method 区分：HEAD 不计入统计
+            .route("/decode/{code}", get(decode_path))
+            .route("/value/lookup", post(value_lookup))
+            .route("/stats/batch", post(stats_batch).layer(batch_body_limit))
+            .route("/stats/{code}", get(stats))
+            .route("/qr/{code}", get(qr_code));
+    }
+    read_routes = read_routes.route("/validate/{code}", get(validate));
+
+    // 短链接模式：GET /{code} 直接 302 跳转到 value（静态路由优先匹配，不会吃掉 /healthz 等）
+    if config.redirect_mode {
+        info!("redirect mode enabled");
+        // HEAD 同样走 get，返回同样的状态码和 Location，链接检查器探测时不会 405
+        read_routes = read_routes.route("/{code}", get(redirect));
+    }
+
+    // 扫描检测：统计读接口（含跳转）按客户端 IP 的未命中次数，只告警不拦截
+    if let Some(sd) = &config.scan_detect {
+        info!(
+            threshold = sd.threshold,
+            window_secs = sd.window.as_secs(),
+            "scan detection enabled"
+        );
+        let detector = Arc::new(scan::ScanDetector::new(
+            sd.threshold,
+            sd.window,
+            config.trust_proxy,
+            metrics.clone(),
+        ));
+        read_routes =
+            read_routes.route_layer(middleware::from_fn_with_state(detector, scan::track_misses));
+    }
+
+    // 请求超时：包住整个 handler（含数据库调用），超时后丢弃 handler 的 future（未提交的事务随之回滚）。
+    // TimeoutLayer 返回空的 408，由 timeout_response 换成错误 JSON
+    let request_timeout =
+        TimeoutLayer::with_status_code(StatusCode::REQUEST_TIMEOUT, config.request_timeout);
+
+    // 管理接口会暴露全部数据，只在配置了 API_KEYS 时挂载，且一律需要鉴权。
+    // 只读的放 admin_routes；会改数据的放 admin_write_routes，和 write_routes 一起受 DISABLE_ENCODE 控制
+    let mut admin_routes = Router::new();
+    let mut admin_write_routes = Router::new();
+
+    // 配置了 API_KEYS 才启用鉴权；未配置时保持原来的行为（全部公开）
+    if let Some(keys) = &config.api_keys {
+        info!("api key auth enabled for write routes");
+        let require_api_key = middleware::from_fn_with_state(keys.clone(), auth::require_api_key);
+        write_routes = write_routes.route_layer(require_api_key.clone());
+        admin_routes = admin_routes
+            .route("/mappings", get(list_mappings))
+            .route("/export", get(export))
+            .route("/admin/value", post(admin_value))
+            .route("/count", get(count_mappings))
+            .route("/admin/stats", get(admin_stats))
+            .route("/admin/search", get(admin_search))
+            .route_layer(request_timeout.clone());
+        // 没有记录访问时间时每一条都像是没人用，不挂这个接口免得误删
+        if config.track_last_access {
+            admin_routes = admin_routes.route(
+                "/admin/stale",
+                get(admin_stale).route_layer(request_timeout.clone()),
+            );
+        }
+        admin_routes = admin_routes.route_layer(require_api_key.clone());
+
+        admin_write_routes = admin_write_routes
+            .route("/admin/rotate", post(rotate_code))
+            .route("/reserve", post(reserve_code))
+            .route("/encode/alias", post(encode_alias))
+            .route("/mappings/{code}", patch(update_mapping))
+            .route(
+                "/mappings/delete",
+                post(delete_batch).layer(batch_body_limit),
+            )
+            .route_layer(request_timeout.clone())
+            // 导入大文件本来就要跑很久，不受请求超时限制；大小由 import 自己按 IMPORT_MAX_FILE_BYTES 限制
+            .route("/import", post(import).layer(DefaultBodyLimit::disable()));
+        // VACUUM 有自己的时限（VACUUM_TIMEOUT_SECS），PostgreSQL 有 autovacuum，不需要这个接口
+        if config.backend == db::Backend::Sqlite {
+            admin_write_routes = admin_write_routes.route("/admin/vacuum", post(admin_vacuum));
+        }
+        admin_write_routes = admin_write_routes.route_layer(require_api_key.clone());
+        if config.require_api_key_for_decode {
+            info!("api key auth enabled for read routes");
+            read_routes = read_routes.route_layer(require_api_key);
+        }
+    }
+
+    // DISABLE_ENCODE / DISABLE_DECODE：对应的路由根本不挂载，请求直接 404
+    info!(
+        encode = !config.disable_encode,
+        decode = !config.disable_decode,
+        redirect = config.redirect_mode,
+        admin = config.api_keys.is_some(),
+        "routes enabled"
+    );
+    let write_routes = if config.disable_encode {
+        Router::new()
+    } else {
+        write_routes
+            .route_layer(request_timeout.clone())
+            .merge(admin_write_routes)
+    };
+
+    let gated_routes = Router::new()
+        .merge(write_routes)
+        .merge(read_routes.route_layer(request_timeout))
+        .merge(admin_routes)
+        .route_layer(middleware::map_response_with_state(
+            config.request_timeout,
+            timeout_response,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.ready.clone(),
+            require_ready,
+        ));
+    // 并发上限只管业务接口：探针和 /metrics 在过载时更需要能访问
+    let mut gated_routes = gated_routes.route_layer(middleware::from_fn_with_state(
+        metrics.clone(),
+        concurrency::track_in_flight,
+    ));
+    // 同时在处理的请求到了上限，新请求直接 503，不在连接池前面无限排队——排到的时候客户端多半已经超时了
+    if config.max_concurrent_requests > 0 {
+        info!(
+            max = config.max_concurrent_requests,
+            "concurrency limit enabled"
+        );
+        let metrics = metrics.clone();
+        let overloaded = move |_: BoxError| async move {
+            metrics::inc(&metrics.requests_overloaded);
+            ApiError::Overloaded
+        };
+        gated_routes = gated_routes.route_layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(overloaded))
+                .layer(LoadShedLayer::new())
+                .layer(ConcurrencyLimitLayer::new(config.max_concurrent_requests)),
+        );
+    }
+
+    let mut app = Router::new()
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/version", get(version))
+        .merge(gated_routes)
+        .layer(DefaultBodyLimit::max(config.max_body_bytes));
+
+    if config.disable_metrics {
+        info!("metrics endpoint disabled");
+    } else {
+        app = app.route("/metrics", get(metrics_handler));
+    }
+
+    if config.disable_openapi {
+        info!("openapi endpoints disabled");
+    } else {
+        app = app
+            .route("/openapi.json", get(openapi::spec))
+            .route("/docs", get(openapi::docs));
+    }
+
+    let mut app = app.route_layer(middleware::from_fn_with_state(
+        metrics.clone(),
+        track_latency,
+    ));
+    if config.debug_timing || config.server_timing {
+        let headers = timing::TimingHeaders {
+            db_time_ms: config.debug_timing,
+            server_timing: config.server_timing,
+        };
+        app = app.layer(middleware::from_fn_with_state(
+            headers,
+            timing::track_db_time,
+        ));
+    }
+    let mut app = app.layer(logging::access_log());
+
+    // 响应压缩和请求体解压对所有接口生效（包括 /metrics、/openapi.json）
+    if config.compression_level > 0 {
+        app = app.layer(compression::compress_response(config.compression_level));
+    }
+    app = app
+        .layer(compression::decompress_request())
+        .layer(middleware::from_fn(compression::check_request_encoding));
+
+    // CORS 放在最外层：预检请求不经过鉴权和限流
+    if let Some(origins) = &config.allowed_origins {
+        info!("cors enabled");
+        app = app.layer(origins.layer());
+    }
+    // request id 在最外层：CORS 预检和被拒的请求也带上 X-Request-Id，访问日志在它的 span 里
+    app.layer(middleware::from_fn(request_id::request_id))
+        .with_state(state)
+}
+
+/// 等待 Ctrl+C（SIGINT）或 SIGTERM
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!(error = %e, "failed to listen for ctrl_c");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                error!(error = %e, "failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
+
+/// 记录每个路由的请求耗时（route_layer 下才能拿到 MatchedPath）
+async fn track_latency(State(metrics): State<Arc<Metrics>>, req: Request, next: Next) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+        .unwrap_or_default();
+    let start = Instant::now();
+    let resp = next.run(req).await;
+    metrics.observe_latency(&route, start.elapsed());
+    resp
+}
+
+/// GET /metrics：Prometheus 文本格式，mappings_total 在抓取时现查
+async fn metrics_handler(State(state): State<AppState>) -> Result<Response, ApiError> {
+    let mappings_total = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM mappings")
+        .fetch_one(state.read_pool())
+        .await?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(mappings_total),
+    )
+        .into_response())
+}
+
+/// 存活探针：进程在就返回 200，不访问数据库
+async fn healthz() -> Json<HealthResponse> {
+    Json(HealthResponse { status: "ok" })
+}
+
+/// TimeoutLayer 超时时返回的是没有响应体的 408：换成 ApiError::Timeout 的错误 JSON，并记一条日志。
+/// handler 自己返回的 408（已经是 JSON）原样放行
+async fn timeout_response(
+    State(limit): State<Duration>,
+    method: Method,
+    uri: Uri,
+    resp: Response,
+) -> Response {
+    if resp.status() != StatusCode::REQUEST_TIMEOUT
+        || resp.headers().contains_key(header::CONTENT_TYPE)
+    {
+        return resp;
+    }
+    warn!(%method, path = uri.path(), timeout_secs = limit.as_secs(), "request timed out");
+    ApiError::Timeout.into_response()
+}
+
+/// 迁移完成前拒绝请求（503 + Retry-After）
+async fn require_ready(State(ready): State<Arc<AtomicBool>>, req: Request, next: Next) -> Response {
+    if !ready.load(Ordering::Acquire) {
+        return ApiError::NotReady.into_response();
+    }
+    next.run(req).await
+}
+
+/// 就绪探针：迁移完成、且对连接池执行 SELECT 1 成功才返回 200，否则 503
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    if !state.ready.load(Ordering::Acquire) {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(HealthResponse {
+                status: "unavailable",
+            }),
+        );
+    }
+    let check = sqlx::query("SELECT 1").execute(&state.pool);
+    match tokio::time::timeout(READINESS_TIMEOUT, check).await {
+        Ok(Ok(_)) => (StatusCode::OK, Json(HealthResponse { status: "ok" })),
+        Ok(Err(e)) => {
+            error!(error = %e, "readiness check failed");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(HealthResponse {
+                    status: "unavailable",
+                }),
+            )
+        }
+        Err(_) => {
+            error!("readiness check timed out");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(HealthResponse {
+                    status: "unavailable",
+                }),
+            )
+        }
+    }
+}
+
+/// GET /version：crate 版本 + 编译时由 build.rs 注入的 git sha 和构建时间
+async fn version() -> Json<VersionResponse> {
+    let build_secs = env!("BUILD_UNIX_TIME").parse().unwrap_or(0);
+    Json(VersionResponse {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("BUILD_GIT_SHA"),
+        build_time: logging::rfc3339(std::time::UNIX_EPOCH + Duration::from_secs(build_secs)),
+    })
+}
+
+/// 后台定期检查短码空间的用量，超过 warn_fraction 时打 warn 日志并计数，
+/// 让运维在真正 507 之前有时间调大 CODE_MAX_LEN
+async fn watch_capacity(
+    pool: Pool,
+    cfg: CodeConfig,
+    metrics: Arc<Metrics>,
+    interval: Duration,
+    warn_fraction: f64,
+) {
+    let capacity = cfg.auto_capacity();
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        // 每个命名空间的码空间是独立的，取用得最满的那个
+        let used = match namespace_usage(&pool, &cfg).await {
+            Ok(usage) => usage.into_iter().map(|(_, n)| n).max().unwrap_or(0),
+            Err(e) => {
+                error!(error = %e, "failed to check code capacity");
+                continue;
+            }
+        };
+        let ratio = used as f64 / capacity as f64;
+        metrics.set_code_capacity_used(ratio);
+        if ratio >= warn_fraction {
+            metrics::inc(&metrics.code_capacity_warnings);
+            warn!(
+                used,
+                capacity,
+                ratio,
+                max_len = cfg.max_len,
+                "code space is nearly exhausted, consider increasing CODE_MAX_LEN"
+            );
+        }
+    }
+}
+
+/// 每个命名空间已用掉的自动分配码空间。自增方案看最大 id / 命名空间序号换算出的编号（ID_OFFSET / ID_STEP、保留区间的平移）：
+/// 默认命名空间的短码由全局自增 id 生成，别的命名空间插入的行也会占掉 id。
+/// 随机方案没有顺序，看已占用的短码个数
+async fn namespace_usage(pool: &Pool, cfg: &CodeConfig) -> Result<Vec<(String, i64)>, sqlx::Error> {
+    let rows: Vec<(String, i64)> = match cfg.strategy {
+        CodeStrategy::Random => {
+            sqlx::query_as("SELECT namespace, COUNT(*) FROM mappings WHERE code IS NOT NULL GROUP BY namespace ORDER BY namespace")
+                .fetch_all(pool)
+                .await?
+        }
+        CodeStrategy::Sequential | CodeStrategy::Feistel => {
+            sqlx::query_as(
+                "SELECT $1, COALESCE(MAX(id), 0) FROM mappings \
+                 UNION ALL SELECT namespace, last_seq FROM namespace_counters ORDER BY 1",
+            )
+            .bind(namespace::DEFAULT)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    if cfg.strategy == CodeStrategy::Random {
+        return Ok(rows);
+    }
+    let shift = (cfg.reserved_below_id - 1).max(0);
+    Ok(rows
+        .into_iter()
+        .map(|(ns, n)| {
+            let used = if n > 0 {
+                codegen::auto_number(cfg, n).unwrap_or(i64::MAX)
+            } else {
+                shift
+            };
+            (ns, used)
+        })
+        .collect())
+}
+
+/// 后台定期清理已过期的映射，避免表无限增长
+async fn sweep_expired(pool: Pool, interval: Duration, idempotency_ttl_secs: i64) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        match sqlx::query("DELETE FROM mappings WHERE expires_at IS NOT NULL AND expires_at <= $1")
+            .bind(now_unix())
+            .execute(&pool)
+            .await
+        {
+            Ok(r) if r.rows_affected() > 0 => {
+                info!(deleted = r.rows_affected(), "swept expired mappings")
+            }
+            Ok(_) => {}
+            Err(e) => error!(error = %e, "failed to sweep expired mappings"),
+        }
+        match idempotency::sweep(&pool, idempotency_ttl_secs, now_unix()).await {
+            Ok(n) if n > 0 => info!(deleted = n, "swept expired idempotency keys"),
+            Ok(_) => {}
+            Err(e) => error!(error = %e, "failed to sweep expired idempotency keys"),
+        }
+    }
+}
+
+fn now_unix() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// POST /encode：请求体可以是 JSON，也可以是 `application/x-www-form-urlencoded`（字段名相同），
+/// 按 Content-Type 选择；请求体解析失败时沿用 axum 的拒绝响应
+async fn encode(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<EncodeQuery>,
+    audit: Audit,
+    req: Request,
+) -> Response {
+    let is_form = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+    let body = if is_form {
+        Form::<EncodeRequest>::from_request(req, &state)
+            .await
+            .map(|Form(body)| body)
+            .map_err(form_rejection)
+    } else {
+        JsonBody::<EncodeRequest>::from_request(req, &state)
+            .await
+            .map(|JsonBody(body)| body)
+    };
+    let req = match body {
+        Ok(req) => req,
+        Err(rejection) => return rejection,
+    };
+    let result = encode_json(&state, &headers, &query, &req, audit).await;
+    negotiated(Format::from_headers(&headers), result, |resp| {
+        resp.code.into_response()
+    })
+}
+
+async fn encode_json(
+    state: &AppState,
+    headers: &HeaderMap,
+    query: &EncodeQuery,
+    req: &EncodeRequest,
+    audit: Audit,
+) -> ApiResult<EncodeResponse> {
+    metrics::inc(&state.metrics.encode_requests);
+    let debug = state.debug_fields && query.debug;
+    let ns = parse_namespace(req.namespace.as_deref())?;
+
+    let Some(key) = headers.get("idempotency-key") else {
+        let (code, created) = encode_value(state, ns, req, query.fail_if_exists, audit).await?;
+        return encode_response(state, debug, ns, code, created).await;
+    };
+    let key = key
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|k| !k.is_empty() && k.len() <= idempotency::MAX_KEY_LEN)
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!(
+                "Idempotency-Key must be 1..={} visible ASCII chars",
+                idempotency::MAX_KEY_LEN
+            ))
+        })?;
+
+    // 同一个 key 在保留期内重放：不管请求体，直接返回上次的结果
+    let ttl = state.idempotency_ttl_secs;
+    if let Some((value, code)) =
+        timing::db(idempotency::lookup(&state.pool, key, ttl, now_unix())).await?
+    {
+        if !idempotency::same_value(
+            &value,
+            &idempotency_value(ns, &req.value(&state.normalize)?),
+        ) {
+            return Err(ApiError::Conflict(
+                "Idempotency-Key was already used with a different value".to_string(),
+            ));
+        }
+        // 重放没有插入任何东西
+        return encode_response(state, debug, ns, code, false).await;
+    }
+
+    let (code, created) = encode_value(state, ns, req, query.fail_if_exists, audit).await?;
+    let idem_value = idempotency_value(ns, &req.value(&state.normalize)?);
+    timing::db(idempotency::store(
+        &state.pool,
+        key,
+        &idem_value,
+        &code,
+        ttl,
+        now_unix(),
+    ))
+    .await?;
+    encode_response(state, debug, ns, code, created).await
+}
+
+/// 组装 /encode 的响应；debug 时多查一次 id，方便开发时核对 id 和 code 的对应关系
+async fn encode_response(
+    state: &AppState,
+    debug: bool,
+    ns: &str,
+    code: String,
+    created: bool,
+) -> ApiResult<EncodeResponse> {
+    let id = if debug {
+        sqlx::query_scalar::<_, i64>("SELECT id FROM mappings WHERE namespace = $1 AND code = $2")
+            .bind(ns)
+            .bind(&code)
+            .fetch_optional(&state.pool)
+            .await?
+    } else {
+        None
+    };
+    let url = state.short_url(ns, &code);
+    Ok(Json(EncodeResponse {
+        code,
+        created,
+        url,
+        id,
+    }))
+}
+
+/// idempotency_keys 表里记录的 value（只用来比较重放的是不是同一个请求）：二进制 value 记成 base64，
+/// 非默认命名空间加上 `namespace:` 前缀（namespace 里没有冒号，不会和别的组合混淆）
+fn idempotency_value(ns: &str, value: &Value) -> String {
+    let value = match value {
+        Value::Text(text) => text.clone(),
+        Value::Bytes(_) => format!("base64:{}", value.to_b64().unwrap_or_default()),
+    };
+    if ns == namespace::DEFAULT {
+        value
+    } else {
+        format!("{ns}:{value}")
+    }
+}
+
+async fn encode_value(
+    state: &AppState,
+    ns: &str,
+    req: &EncodeRequest,
+    fail_if_exists: bool,
+    audit: Audit,
+) -> Result<(String, bool), ApiError> {
+    let value = &req.value(&state.normalize)?;
+    validate_value(state, value.as_bytes())?;
+    check_whitespace(state, value.as_text())?;
+    check_value_pattern(state, value.as_bytes())?;
+
+    let expires_at = match req.ttl_seconds {
+        Some(0) => {
+            return Err(ApiError::BadRequest(
+                "ttl_seconds must be positive".to_string(),
+            ));
+        }
+        Some(ttl) => Some(
+            i64::try_from(ttl)
+                .ok()
+                .and_then(|ttl| now_unix().checked_add(ttl))
+                .ok_or_else(|| ApiError::BadRequest("ttl_seconds is too large".to_string()))?,
+        ),
+        None => None,
+    };
+
+    if let Some(custom_code) = &req.custom_code {
+        return with_busy_retry(state, || {
+            encode_custom(
+                state,
+                ns,
+                value,
+                custom_code,
+                expires_at,
+                fail_if_exists,
+                audit,
+            )
+        })
+        .await;
+    }
+
+    with_busy_retry(state, || {
+        encode_new(state, ns, value, expires_at, fail_if_exists, audit)
+    })
+    .await
+}
+
+/// 非自定义短码的 encode：已存在直接返回（fail_if_exists 时返回 409），否则在事务内分配。
+/// 重试是安全的：每次都按 value 重新查，已经提交的插入不会重复。返回 (code, 是否本次新建)
+async fn encode_new(
+    state: &AppState,
+    ns: &str,
+    value: &Value,
+    expires_at: Option<i64>,
+    fail_if_exists: bool,
+    audit: Audit,
+) -> Result<(String, bool), ApiError> {
+    // 快路径：已存在（且未过期）则直接返回
+    if let Some((id, code, deleted_at)) = sqlx::query_as::<_, (i64, String, Option<i64>)>(&format!(
+        "SELECT id, code, deleted_at FROM mappings \
+         WHERE namespace = $1 AND value_hash = $4 AND {} = $2 AND (expires_at IS NULL OR expires_at > $3)",
+        value.column()
+    ))
+    .bind(ns)
+    .bind(value)
+    .bind(now_unix())
+    .bind(value.dedup_hash())
+    .fetch_optional(&state.pool)
+        .await?
+    {
+        metrics::inc(&state.metrics.encode_existing);
+        // 软删除过的不算已存在：fail_if_exists 也照常恢复，不能把已删除的 code 当成 existing_code 返回
+        if fail_if_exists && deleted_at.is_none() {
+            return Err(ApiError::AlreadyExists(code));
+        }
+        // 软删除过的 value 重新 encode：恢复原来的 code，旧链接重新生效
+        if deleted_at.is_some() {
+            let mut tx = state.pool.begin().await?;
+            if undelete(&mut *tx, id).await? {
+                audit::record(&mut tx, audit, "restore", ns, &code, value).await?;
+            }
+            tx.commit().await?;
+        }
+        // 记录事件
+        sqlx::query("INSERT INTO events (action, mapping_id, code, value) VALUES ('encode', $1, $2, $3)")
+            .bind(id)
+            .bind(&code)
+            .bind(value.as_text())
+            .execute(&state.pool)
+            .await?;
+        return Ok((code, false));
+    }
+
+    let mut tx = state.pool.begin().await?;
+    let (code, created) = assign_code(
+        &mut tx,
+        &state.code,
+        &*state.generator,
+        ns,
+        value,
+        expires_at,
+        audit,
+    )
+    .await?;
+    // 并发的请求刚好抢先插入了同一个 value：回滚本事务（包括恢复软删除和事件）
+    if fail_if_exists && !created {
+        return Err(ApiError::AlreadyExists(code));
+    }
+    tx.commit().await?;
+    Ok((code, created))
+}
+
+/// 取消软删除标记，返回这一行之前是否处于删除状态
+async fn undelete<'e, E>(executor: E, id: i64) -> Result<bool, sqlx::Error>
+where
+    E: sqlx::Executor<'e, Database = sqlx::Any>,
+{
+    let r = sqlx::query(
+        "UPDATE mappings SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+    )
+    .bind(id)
+    .execute(executor)
+    .await?;
+    Ok(r.rows_affected() > 0)
+}
+
+/// 写事务遇到锁冲突（SQLite busy / locked，PostgreSQL 序列化失败 / 死锁）时退避重试，
+/// 最多 ENCODE_MAX_ATTEMPTS 次；其它错误（耗尽、冲突等）直接返回
+async fn with_busy_retry<T, F, Fut>(state: &AppState, mut f: F) -> Result<T, ApiError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, ApiError>>,
+{
+    let mut attempt = 1;
+    loop {
+        // 只计每次尝试本身，退避等待不算数据库耗时
+        match timing::db(f()).await {
+            Err(ApiError::Sqlx(e)) if is_busy(&e) && attempt < state.encode_max_attempts => {
+                // 指数退避 + 随机抖动，避免几个请求同时醒来再撞一次
+                let base = RETRY_BASE_DELAY_MS << (attempt - 1).min(6);
+                let delay = base + rand::thread_rng().gen_range(0..=base);
+                warn!(attempt, delay_ms = delay, error = %e, "encode transaction busy, retrying");
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// 锁冲突类的瞬时错误：SQLite SQLITE_BUSY(5) / SQLITE_LOCKED(6)（含扩展码），PostgreSQL 40001 / 40P01
+fn is_busy(e: &sqlx::Error) -> bool {
+    let Some(code) = e.as_database_error().and_then(|db| db.code()) else {
+        return false;
+    };
+    match code.parse::<i32>() {
+        Ok(n) => matches!(n & 0xff, 5 | 6),
+        Err(_) => matches!(code.as_ref(), "40001" | "40P01"),
+    }
+}
+
+fn validate_value(state: &AppState, value: &[u8]) -> Result<(), ApiError> {
+    if value.is_empty() {
+        return Err(ApiError::BadRequest("value is empty".to_string()));
+    }
+    // 按 UTF-8 字节数算，和实际存储大小一致
+    if value.len() > state.max_value_len {
+        return Err(ApiError::BadRequest("value too long".to_string()));
+    }
+    Ok(())
+}
+
+/// NORMALIZE_TRIM=strict：同 VALUE_PATTERN，只在写入 value 的接口检查；二进制 value（None）不检查
+fn check_whitespace(state: &AppState, text: Option<&str>) -> Result<(), ApiError> {
+    match text.map(|t| state.normalize.check_whitespace(t)) {
+        Some(Err(e)) => Err(ApiError::BadRequest(format!("value {e}"))),
+        _ => Ok(()),
+    }
+}
+
+/// VALUE_PATTERN：只在写入 value 的接口（encode、preview、import、PATCH）检查，
+/// 查询接口不检查，设置 VALUE_PATTERN 之前存进去的 value 照样能查
+fn check_value_pattern(state: &AppState, value: &[u8]) -> Result<(), ApiError> {
+    match &state.value_pattern {
+        Some(pattern) if !pattern.is_match(value) => Err(ApiError::BadRequest(
+            "value does not match required format".to_string(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// POST /encode/preview：返回该 value 会得到的 code，不写数据库。
+/// 新 value 按当前最大 id + 1 推算，并发插入时实际分配到的 code 可能不同
+async fn encode_preview(
+    State(state): State<AppState>,
+    JsonBody(req): JsonBody<PreviewRequest>,
+) -> ApiResult<PreviewResponse> {
+    let value = Value::Text(state.normalize.apply(&req.value));
+    validate_value(&state, value.as_bytes())?;
+    check_whitespace(&state, value.as_text())?;
+    check_value_pattern(&state, value.as_bytes())?;
+
+    // 只读事务：保证几次查询看到的是同一个快照，结束时直接回滚
+    let mut tx = state.pool.begin().await?;
+
+    if let Some(code) = sqlx::query_scalar::<_, String>(
+        "SELECT code FROM mappings \
+         WHERE namespace = $1 AND value_hash = $4 AND value = $2 AND code IS NOT NULL \
+         AND (expires_at IS NULL OR expires_at > $3)",
+    )
+    .bind(namespace::DEFAULT)
+    .bind(&value)
+    .bind(now_unix())
+    .bind(value.dedup_hash())
+    .fetch_optional(&mut *tx)
+    .await?
+    {
+        return Ok(Json(PreviewResponse {
+            code,
+            existing: true,
+        }));
+    }
+
+    if state.code.strategy == CodeStrategy::Random {
+        return Err(ApiError::BadRequest(
+            "preview is not available for new values with CODE_STRATEGY=random".to_string(),
+        ));
+    }
+
+    let mut id = sqlx::query_scalar::<_, i64>("SELECT COALESCE(MAX(id), 0) + 1 FROM mappings")
+        .fetch_one(&mut *tx)
+        .await?;
+    // 和 next_sequential_code 一样跳过已被自定义短码占用的 code
+    let mut code = state.generator.code_for(id)?;
+    while code_taken(&mut tx, namespace::DEFAULT, &code).await? {
+        id += 1;
+        code = state.generator.code_for(id)?;
+    }
+    Ok(Json(PreviewResponse {
+        code,
+        existing: false,
+    }))
+}
+
+/// POST /value/lookup：只查 value 是否已有 code，不存在返回 404，不会新建映射
+async fn value_lookup(
+    State(state): State<AppState>,
+    JsonBody(req): JsonBody<LookupRequest>,
+) -> ApiResult<EncodeResponse> {
+    let ns = parse_namespace(req.namespace.as_deref())?;
+    let value = Value::Text(state.normalize.apply(&req.value));
+    validate_value(&state, value.as_bytes())?;
+
+    // 单条 SELECT，直接走连接池，不开事务
+    let code = timing::db(
+        sqlx::query_scalar::<_, String>(
+            "SELECT code FROM mappings \
+             WHERE namespace = $1 AND value_hash = $4 AND value = $2 AND code IS NOT NULL AND deleted_at IS NULL \
+             AND (expires_at IS NULL OR expires_at > $3)",
+        )
+        .bind(ns)
+        .bind(&value)
+        .bind(now_unix())
+        .bind(value.dedup_hash())
+        .fetch_optional(state.read_pool()),
+    )
+    .await?
+    .ok_or(ApiError::NotFound)?;
+
+    let url = state.short_url(ns, &code);
+    Ok(Json(EncodeResponse {
+        code,
+        created: false,
+        url,
+        id: None,
+    }))
+}
+
+/// 自定义短码：code 被别的 value 占用、或 value 已有别的 code 时返回 409；
+/// 同一对 (value, code) 重复提交则幂等返回。返回 (code, 是否本次新建)
+async fn encode_custom(
+    state: &AppState,
+    ns: &str,
+    value: &Value,
+    custom_code: &str,
+    expires_at: Option<i64>,
+    fail_if_exists: bool,
+    audit: Audit,
+) -> Result<(String, bool), ApiError> {
+    // 开启校验位时 custom_code 只是主体部分，校验字符由服务端追加
+    let custom_code = state.code.canonicalize(custom_code);
+    validate_code_body(&state.code, &custom_code)?;
+    let custom_code = state.code.full_code(custom_code);
+    let custom_code = custom_code.as_str();
+
+    let mut tx = state.pool.begin().await?;
+
+    // 已过期的映射视为不存在：先清掉，value / code 才能重新使用
+    let column = value.column();
+    let hash = value.dedup_hash();
+    sqlx::query(&format!(
+        "DELETE FROM mappings WHERE namespace = $1 AND ((value_hash = $5 AND {column} = $2) OR code = $3) \
+         AND expires_at IS NOT NULL AND expires_at <= $4"
+    ))
+    .bind(ns)
+    .bind(value)
+    .bind(custom_code)
+    .bind(now_unix())
+    .bind(&hash)
+    .execute(&mut *tx)
+    .await?;
+
+    let by_value = sqlx::query_as::<_, (i64, Option<String>, Option<i64>)>(&format!(
+        "SELECT id, code, deleted_at FROM mappings WHERE namespace = $1 AND value_hash = $3 AND {column} = $2"
+    ))
+    .bind(ns)
+    .bind(value)
+    .bind(&hash)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let (id, created) = match by_value {
+        // 同 encode_new：软删除过的这一对照常恢复
+        Some((_, Some(code), None)) if code == custom_code && fail_if_exists => {
+            return Err(ApiError::AlreadyExists(code));
+        }
+        Some((id, Some(code), _)) if code == custom_code => (id, false),
+        Some((_, Some(code), _)) => {
+            return Err(ApiError::Conflict(format!(
+                "value is already mapped to code {code}"
+            )));
+        }
+        _ => {
+            if code_taken(&mut tx, ns, custom_code).await? {
+                return Err(ApiError::Conflict("code is already taken".to_string()));
+            }
+
+            // value 可能因为并发 encode 已插入但还没分到 code，这里直接把 code 填上。
+            // 哈希去重时冲突的那一行还要比较原文：哈希碰撞的另一个 value 不能被填上这个 code
+            let conflict = if state.value_hash_dedup {
+                "value_hash"
+            } else {
+                column
+            };
+            let id = sqlx::query_scalar::<_, i64>(&format!(
+                "INSERT INTO mappings (namespace, {column}, value_hash, code, expires_at) VALUES ($1, $2, $5, $3, $4) \
+                 ON CONFLICT(namespace, {conflict}) DO UPDATE SET code = excluded.code \
+                 WHERE mappings.code IS NULL AND mappings.{column} = excluded.{column} \
+                 RETURNING id"
+            ))
+            .bind(ns)
+            .bind(value)
+            .bind(custom_code)
+            .bind(expires_at)
+            .bind(&hash)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or_else(|| ApiError::Conflict("value is already mapped to another code".to_string()))?;
+            (id, true)
+        }
+    };
+
+    let restored = undelete(&mut *tx, id).await?;
+    if created || restored {
+        let action = if created { "create" } else { "restore" };
+        audit::record(&mut tx, audit, action, ns, custom_code, value).await?;
+    }
+
+    sqlx::query(
+        "INSERT INTO events (action, mapping_id, code, value) VALUES ('encode', $1, $2, $3)",
+    )
+    .bind(id)
+    .bind(custom_code)
+    .bind(value.as_text())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok((custom_code.to_string(), created))
+}
+
+/// POST /encode/batch：一次请求编码多个 value，整个批次在同一个事务内完成
+async fn encode_batch(
+    State(state): State<AppState>,
+    audit: Audit,
+    JsonBody(req): JsonBody<EncodeBatchRequest>,
+) -> ApiResult<EncodeBatchResponse> {
+    metrics::inc(&state.metrics.encode_requests);
+    let ns = parse_namespace(req.namespace.as_deref())?;
+
+    if req.values.is_empty() {
+        return Err(ApiError::BadRequest("values is empty".to_string()));
+    }
+    if req.values.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!(
+            "too many values (max {MAX_BATCH_SIZE})"
+        )));
+    }
+    let values: Vec<String> = req
+        .values
+        .iter()
+        .map(|v| state.normalize.apply(v))
+        .collect();
+    if let Some(i) = values.iter().position(|v| v.is_empty()) {
+        return Err(ApiError::BadRequest(format!("values[{i}] is empty")));
+    }
+    if let Some(i) = values.iter().position(|v| v.len() > state.max_value_len) {
+        return Err(ApiError::BadRequest(format!("values[{i}] is too long")));
+    }
+    for (i, v) in values.iter().enumerate() {
+        if let Err(e) = state.normalize.check_whitespace(v) {
+            return Err(ApiError::BadRequest(format!("values[{i}] {e}")));
+        }
+    }
+    if let Some(i) = values
+        .iter()
+        .position(|v| check_value_pattern(&state, v.as_bytes()).is_err())
+    {
+        return Err(ApiError::BadRequest(format!(
+            "values[{i}] does not match required format"
+        )));
+    }
+
+    let codes = with_busy_retry(&state, || async {
+        let mut tx = state.pool.begin().await?;
+        let mut codes = Vec::with_capacity(values.len());
+        for value in &values {
+            // 批次内重复的 value 会命中 ON CONFLICT，拿到同一个 code
+            let text = Value::Text(value.clone());
+            let (code, _) = assign_code(
+                &mut tx,
+                &state.code,
+                &*state.generator,
+                ns,
+                &text,
+                None,
+                audit,
+            )
+            .await?;
+            codes.push(EncodeBatchItem {
+                value: value.clone(),
+                code,
+            });
+        }
+        tx.commit().await?;
+        Ok(codes)
+    })
+    .await?;
+
+    Ok(Json(EncodeBatchResponse { codes }))
+}
+
+/// POST /encode/stream：一次性大批量导入用。请求体为 NDJSON（每行 `{"value"}` 或 `{"value_b64"}`，可带 namespace），
+/// 响应同样是 NDJSON，每行输入对应一行 `{"line","code"}` 或 `{"line","error"}`。
+///
+/// 边读边处理，每 ENCODE_STREAM_CHUNK_SIZE 行一个事务，提交之后才输出这一块的结果，所以收到的 code 都已经落库。
+/// 输出经过容量很小的 channel：客户端不读响应时后台任务停在 send 上，也就不再读请求体、不再写库。
+/// 中途出错（数据库错误、某行过长、请求体读失败）时输出一行不带 line 的 error 后结束，出错的那一块整体回滚
+async fn encode_stream(State(state): State<AppState>, audit: Audit, body: Body) -> Response {
+    metrics::inc(&state.metrics.encode_requests);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<Bytes>(ENCODE_STREAM_QUEUE);
+    tokio::spawn(async move {
+        if let Err(e) = encode_stream_chunks(&state, audit, body, &tx).await {
+            warn!(error = %e, "encode stream aborted");
+            let item = EncodeStreamItem {
+                line: None,
+                code: None,
+                error: Some(e.to_string()),
+            };
+            let _ = tx.send(ndjson_lines(&[item])).await;
+        }
+    });
+
+    // poll_recv 在 channel 关闭后一直返回 None，响应压缩在流结束后再 poll 一次也没问题
+    let body = futures_util::stream::poll_fn(move |cx| {
+        rx.poll_recv(cx)
+            .map(|chunk| chunk.map(Ok::<_, std::convert::Infallible>))
+    });
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+/// 按块读入、编码、提交、输出；客户端断开（send 失败）时直接返回 Ok，未提交的块随事务回滚
+async fn encode_stream_chunks(
+    state: &AppState,
+    audit: Audit,
+    body: Body,
+    out: &tokio::sync::mpsc::Sender<Bytes>,
+) -> Result<(), ApiError> {
+    let mut lines = NdjsonLines::new(body.into_data_stream(), max_ndjson_line_len(state));
+    let mut done = false;
+    while !done {
+        // 先把这一块解析好：不合法的行直接记下错误，重试事务时不用再读请求体
+        let mut chunk = Vec::with_capacity(ENCODE_STREAM_CHUNK_SIZE);
+        while chunk.len() < ENCODE_STREAM_CHUNK_SIZE {
+            let Some((line_no, line)) = lines.next().await? else {
+                done = true;
+                break;
+            };
+            chunk.push((line_no, parse_stream_line(state, &line)));
+        }
+        if chunk.is_empty() {
+            break;
+        }
+
+        let items = with_busy_retry(state, || async {
+            let mut tx = state.pool.begin().await?;
+            let mut items = Vec::with_capacity(chunk.len());
+            for (line_no, parsed) in &chunk {
+                let (code, error) = match parsed {
+                    Ok((ns, value)) => {
+                        let (code, _) = assign_code(
+                            &mut tx,
+                            &state.code,
+                            &*state.generator,
+                            ns,
+                            value,
+                            None,
+                            audit,
+                        )
+                        .await?;
+                        (Some(code), None)
+                    }
+                    Err(e) => (None, Some(e.clone())),
+                };
+                items.push(EncodeStreamItem {
+                    line: Some(*line_no),
+                    code,
+                    error,
+                });
+            }
+            tx.commit().await?;
+            Ok(items)
+        })
+        .await?;
+
+        if out.send(ndjson_lines(&items)).await.is_err() {
+            info!("encode stream client disconnected");
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 解析并校验一行，返回 (namespace, value)；错误信息直接写进这一行的输出
+fn parse_stream_line(state: &AppState, line: &[u8]) -> Result<(String, Value), String> {
+    let item: EncodeStreamLine =
+        serde_json::from_slice(line).map_err(|e| format!("invalid json: {e}"))?;
+    let text = item.value.map(|v| state.normalize.apply(&v));
+    let value = Value::from_fields(text, item.value_b64.as_deref())?;
+    validate_value(state, value.as_bytes())
+        .and_then(|()| check_whitespace(state, value.as_text()))
+        .and_then(|()| check_value_pattern(state, value.as_bytes()))
+        .map_err(|e| e.to_string())?;
+    let ns = parse_namespace(item.namespace.as_deref()).map_err(|e| e.to_string())?;
+    Ok((ns.to_string(), value))
+}
+
+fn ndjson_lines(items: &[EncodeStreamItem]) -> Bytes {
+    let mut out = Vec::new();
+    for item in items {
+        serde_json::to_writer(&mut out, &fieldcase::Cased(item))
+            .expect("encode stream item serializes");
+        out.push(b'\n');
+    }
+    Bytes::from(out)
+}
+
+/// 在事务内为 value 分配短码（已存在则返回已有的），并记录 encode 事件。返回 (code, 是否本次新分配)
+async fn assign_code(
+    tx: &mut Tx<'_>,
+    cfg: &CodeConfig,
+    generator: &dyn CodeGenerator,
+    ns: &str,
+    value: &Value,
+    expires_at: Option<i64>,
+    audit: Audit,
+) -> Result<(String, bool), ApiError> {
+    let column = value.column();
+    let hash = value.dedup_hash();
+    // 已过期的映射视为不存在：先清掉，value 才能重新插入
+    sqlx::query(&format!(
+        "DELETE FROM mappings WHERE namespace = $1 AND value_hash = $4 AND {column} = $2 \
+         AND expires_at IS NOT NULL AND expires_at <= $3"
+    ))
+    .bind(ns)
+    .bind(value)
+    .bind(now_unix())
+    .bind(&hash)
+    .execute(&mut **tx)
+    .await?;
+
+    // 先查一次：批次内重复的 value 不再走 INSERT（ON CONFLICT 也会消耗一个自增 id）
+    let select_by_value = format!(
+        "SELECT id, code FROM mappings WHERE namespace = $1 AND value_hash = $3 AND {column} = $2"
+    );
+    let existing = sqlx::query_as::<_, (i64, Option<String>)>(&select_by_value)
+        .bind(ns)
+        .bind(value)
+        .bind(&hash)
+        .fetch_optional(&mut **tx)
+        .await?;
+
+    let (id, code) = match existing {
+        Some((id, code)) => {
+            if undelete(&mut **tx, id).await?
+                && let Some(code) = &code
+            {
+                audit::record(tx, audit, "restore", ns, code, value).await?;
+            }
+            (id, code)
+        }
+        None => {
+            // 并发安全：同一个 value 只插入一次。不写冲突列：code 为空，能冲突的只有 value 的唯一索引，
+            // 不管它建在原文上还是 value_hash 上（VALUE_HASH_DEDUP）
+            sqlx::query(&format!(
+                "INSERT INTO mappings (namespace, {column}, value_hash, expires_at) VALUES ($1, $2, $3, $4) \
+                 ON CONFLICT DO NOTHING"
+            ))
+                .bind(ns)
+                .bind(value)
+                .bind(&hash)
+                .bind(expires_at)
+                .execute(&mut **tx)
+                .await?;
+
+            // 没插进去、按原文又查不到：value_hash 撞上了另一个 value
+            sqlx::query_as::<_, (i64, Option<String>)>(&select_by_value)
+                .bind(ns)
+                .bind(value)
+                .bind(&hash)
+                .fetch_optional(&mut **tx)
+                .await?
+                .ok_or_else(|| {
+                    ApiError::Conflict("value hash collides with another value".to_string())
+                })?
+        }
+    };
+
+    let mut id = id;
+    let created = code.is_none();
+    let final_code = if let Some(code) = code {
+        code
+    } else {
+        let new_code = match cfg.strategy {
+            CodeStrategy::Sequential | CodeStrategy::Feistel if ns == namespace::DEFAULT => {
+                let (new_id, code) =
+                    next_sequential_code(tx, generator, id, value, expires_at).await?;
+                id = new_id;
+                code
+            }
+            CodeStrategy::Sequential | CodeStrategy::Feistel => {
+                next_namespace_code(tx, generator, ns).await?
+            }
+            CodeStrategy::Random => next_random_code(tx, cfg, ns).await?,
+        };
+
+        sqlx::query("UPDATE mappings SET code = $1 WHERE id = $2 AND code IS NULL")
+            .bind(&new_code)
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+
+        let code = sqlx::query_scalar::<_, String>("SELECT code FROM mappings WHERE id = $1")
+            .bind(id)
+            .fetch_one(&mut **tx)
+            .await?;
+        audit::record(tx, audit, "create", ns, &code, value).await?;
+        code
+    };
+
+    // 记录事件（encode 成功）
+    sqlx::query(
+        "INSERT INTO events (action, mapping_id, code, value) VALUES ('encode', $1, $2, $3)",
+    )
+    .bind(id)
+    .bind(&final_code)
+    .bind(value.as_text())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok((final_code, created))
+}
+
+/// 默认命名空间按自增 id 生成短码。这个 id 对应的短码可能已经被自定义短码占用：
+/// 删掉这一行重新插入换一个新 id（AUTOINCREMENT 不会复用 id，所以一定能往前走）。返回 (id, code)
+async fn next_sequential_code(
+    tx: &mut Tx<'_>,
+    generator: &dyn CodeGenerator,
+    mut id: i64,
+    value: &Value,
+    expires_at: Option<i64>,
+) -> Result<(i64, String), ApiError> {
+    let mut code = generator.code_for(id)?;
+    while code_taken(tx, namespace::DEFAULT, &code).await? {
+        sqlx::query("DELETE FROM mappings WHERE id = $1 AND code IS NULL")
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+        id = sqlx::query_scalar::<_, i64>(&format!(
+            "INSERT INTO mappings ({}, value_hash, expires_at) VALUES ($1, $3, $2) RETURNING id",
+            value.column()
+        ))
+        .bind(value)
+        .bind(expires_at)
+        .bind(value.dedup_hash())
+        .fetch_one(&mut **tx)
+        .await?;
+        code = generator.code_for(id)?;
+    }
+    Ok((id, code))
+}
+
+/// 非默认命名空间按该命名空间自己的计数器生成短码，跳过已被自定义短码占用的
+async fn next_namespace_code(
+    tx: &mut Tx<'_>,
+    generator: &dyn CodeGenerator,
+    ns: &str,
+) -> Result<String, ApiError> {
+    loop {
+        let seq = namespace::next_seq(tx, ns).await?;
+        let code = generator.code_for(seq)?;
+        if !code_taken(tx, ns, &code).await? {
+            return Ok(code);
+        }
+    }
+}
+
+/// 随机生成 max_len 位短码，撞上已有短码就重试，最多 random_max_attempts 次
+async fn next_random_code(tx: &mut Tx<'_>, cfg: &CodeConfig, ns: &str) -> Result<String, ApiError> {
+    for _ in 0..cfg.random_max_attempts {
+        let code = random_code(cfg);
+        if !code_taken(tx, ns, &code).await? {
+            return Ok(code);
+        }
+    }
+    Err(ApiError::RandomCodeCollision(cfg.random_max_attempts))
+}
+
+fn random_code(cfg: &CodeConfig) -> String {
+    let mut rng = rand::thread_rng();
+    let body = (0..cfg.max_len)
+        .map(|_| cfg.charset[rng.gen_range(0..cfg.charset.len())] as char)
+        .collect();
+    cfg.full_code(body)
+}
+
+/// code 已经属于某个映射，或者是某个映射的别名（POST /encode/alias）
+async fn code_taken(tx: &mut Tx<'_>, ns: &str, code: &str) -> Result<bool, sqlx::Error> {
+    Ok(sqlx::query_scalar::<_, i64>(
+        "SELECT id FROM mappings WHERE namespace = $1 AND code = $2 \
+         UNION ALL SELECT id FROM aliases WHERE namespace = $1 AND code = $2",
+    )
+    .bind(ns)
+    .bind(code)
+    .fetch_optional(&mut **tx)
+    .await?
+    .is_some())
+}
+
+async fn decode(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    JsonBody(req): JsonBody<DecodeRequest>,
+) -> Response {
+    let result = match parse_namespace(req.namespace.as_deref()) {
+        Ok(ns) => decode_code(&state, ns, &req.code, true).await,
+        Err(e) => Err(e),
+    }
+    .map(|mapping| {
+        Json(DecodeResponse {
+            value: mapping.value,
+        })
+    });
+    negotiated(Format::from_headers(&headers), result, |resp| {
+        value_body(resp.value)
+    })
+}
+
+/// GET /decode/{code}：方便浏览器 / curl 直接访问，逻辑与 POST /decode 一致。
+/// 带上强 ETag + Cache-Control，方便前面挂 CDN（PATCH 改过 value 后 ETag 随之改变）
+async fn decode_path(
+    State(state): State<AppState>,
+    method: Method,
+    Path(code): Path<String>,
+    Query(query): Query<NamespaceQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let format = Format::from_headers(&headers);
+    let decoded = match parse_namespace(query.namespace.as_deref()) {
+        Ok(ns) => decode_code(&state, ns, &code, method != Method::HEAD).await,
+        Err(e) => Err(e),
+    };
+    let mapping = match decoded {
+        Ok(mapping) => mapping,
+        Err(e) => return e.into_response_as(format),
+    };
+
+    let etag = mapping_etag(
+        &state.code.canonicalize(&code),
+        mapping.id,
+        &mapping.value,
+        format,
+    );
+    let max_age = cache_max_age(&state, mapping.expires_at);
+    // 同一个 URL 按 Accept 有 JSON / 纯文本两种表示，CDN 要分开缓存
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, format!("public, max-age={max_age}")),
+        (header::VARY, "accept".to_string()),
+    ];
+
+    if if_none_match(&headers, &etag) {
+        return (StatusCode::NOT_MODIFIED, cache_headers).into_response();
+    }
+    match format {
+        Format::Json => (
+            cache_headers,
+            Json(DecodeResponse {
+                value: mapping.value,
+            }),
+        )
+            .into_response(),
+        Format::Text => (cache_headers, value_body(mapping.value)).into_response(),
+    }
+}
+
+/// 有过期时间的映射不能缓存到过期之后
+fn cache_max_age(state: &AppState, expires_at: Option<i64>) -> i64 {
+    match expires_at {
+        Some(at) => state
+            .decode_cache_max_age_secs
+            .min((at - now_unix()).max(0)),
+        None => state.decode_cache_max_age_secs,
+    }
+}
+
+/// 强 ETag：由 code、mapping id 和 value 决定（code 删除后被重新分配、PATCH 改了 value 都会换 ETag），
+/// 不直接暴露 id。JSON 和纯文本是两种表示，字节不同，ETag 也要不同
+fn mapping_etag(code: &str, id: i64, value: &Value, format: Format) -> String {
+    let input = match format {
+        Format::Json => format!("{code}:{id}:"),
+        Format::Text => format!("{code}:{id}:text:"),
+    };
+    let digest = Sha256::new()
+        .chain_update(input.as_bytes())
+        .chain_update(value.as_bytes())
+        .finalize();
+    let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+    format!("\"{hex}\"")
+}
+
+/// If-None-Match 是否命中（按 RFC 9110 用弱比较，支持逗号分隔的列表和 `*`）
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+}
+
+/// POST /decode/batch：逐条解码，单条失败（非法 / 不存在）不影响整个批次
+async fn decode_batch(
+    State(state): State<AppState>,
+    JsonBody(req): JsonBody<DecodeBatchRequest>,
+) -> Result<(Extension<scan::LookupMisses>, Json<DecodeBatchResponse>), ApiError> {
+    metrics::inc(&state.metrics.decode_requests);
+    let ns = parse_namespace(req.namespace.as_deref())?;
+
+    if req.codes.is_empty() {
+        return Err(ApiError::BadRequest("codes is empty".to_string()));
+    }
+    if req.codes.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!(
+            "too many codes (max {MAX_BATCH_SIZE})"
+        )));
+    }
+
+    let mut tx = timing::db(state.pool.begin()).await?;
+    let mut results = Vec::with_capacity(req.codes.len());
+    let mut hit_ids = Vec::new();
+    let mut misses = 0;
+    for code in req.codes {
+        // 响应里原样回显传入的 code，方便调用方对应
+        let item = match canonical_code(&state.code, &code) {
+            Err(e) => DecodeBatchItem {
+                code,
+                value: None,
+                value_b64: None,
+                error: Some(e.to_string()),
+            },
+            Ok(canonical) => {
+                let found = match cached_mapping(&state, ns, &canonical) {
+                    Some(mapping) => Ok(Some(mapping)),
+                    None => match lookup_and_cache(&state, &mut tx, ns, &canonical, true).await {
+                        // 软删除、预留的条目单独标成 gone / reserved，不让整批失败
+                        Err(e @ (ApiError::Gone | ApiError::Reserved)) => Err(e),
+                        other => Ok(other?),
+                    },
+                };
+                match found {
+                    Err(e) => DecodeBatchItem {
+                        code,
+                        value: None,
+                        value_b64: None,
+                        error: Some(e.to_string()),
+                    },
+                    Ok(found) => {
+                        match &found {
+                            Some(mapping) => hit_ids.push(mapping.id),
+                            None => {
+                                metrics::inc(&state.metrics.decode_not_found);
+                                misses += 1;
+                            }
+                        }
+                        let value = found.map(|mapping| mapping.value);
+                        DecodeBatchItem {
+                            code,
+                            value_b64: value.as_ref().and_then(Value::to_b64),
+                            value: value.and_then(|v| v.as_text().map(str::to_string)),
+                            error: None,
+                        }
+                    }
+                }
+            }
+        };
+        results.push(item);
+    }
+    timing::db(tx.commit()).await?;
+
+    for id in hit_ids {
+        state.hits.record(id);
+    }
+
+    Ok((
+        Extension(scan::LookupMisses(misses)),
+        Json(DecodeBatchResponse { results }),
+    ))
+}
+
+/// 校验 code 并查出映射（先查 LRU 缓存）。count 为 false 时（HEAD 请求：链接检查器探测短链接是否有效）
+/// 校验、404 / 410 和 GET 完全一样，但不计入 decode_count、hit_count 和 events
+async fn decode_code(
+    state: &AppState,
+    ns: &str,
+    code: &str,
+    count: bool,
+) -> Result<Mapping, ApiError> {
+    metrics::inc(&state.metrics.decode_requests);
+    let code = &canonical_code(&state.code, code)?;
+
+    if let Some(mapping) = cached_mapping(state, ns, code) {
+        // 没有事务：decode_count 和 events 跟 hit_count 一样攒着，由后台批量写回
+        if count {
+            state.hits.record(mapping.id);
+            state
+                .hits
+                .record_decode(mapping.id, code, mapping.value.as_text());
+        }
+        return Ok(mapping);
+    }
+
+    let mut tx = timing::db(state.pool.begin()).await?;
+    let Some(mapping) = lookup_and_cache(state, &mut tx, ns, code, count).await? else {
+        metrics::inc(&state.metrics.decode_not_found);
+        return Err(ApiError::NotFound);
+    };
+    timing::db(tx.commit()).await?;
+
+    if count {
+        state.hits.record(mapping.id);
+    }
+
+    Ok(mapping)
+}
+
+/// 先查 LRU 缓存。映射不可变，只需要检查是否已经过期
+fn cached_mapping(state: &AppState, ns: &str, code: &str) -> Option<Mapping> {
+    let cache = state.cache.as_ref()?;
+    let key = namespace::cache_key(ns, code);
+    let mapping = cache.get(&key)?;
+    if mapping.expires_at.is_some_and(|at| at <= now_unix()) {
+        cache.remove(&key);
+        return None;
+    }
+    metrics::inc(&state.metrics.decode_cache_hits);
+    Some(mapping)
+}
+
+/// 缓存未命中：查数据库并回填缓存
+async fn lookup_and_cache(
+    state: &AppState,
+    tx: &mut Tx<'_>,
+    ns: &str,
+    code: &str,
+    count: bool,
+) -> Result<Option<Mapping>, ApiError> {
+    if state.cache.is_some() {
+        metrics::inc(&state.metrics.decode_cache_misses);
+    }
+    let found = timing::db(lookup_code(state, tx, ns, code, count)).await?;
+    // 别名不进缓存：PATCH / DELETE / rotate 只按主 code 让缓存失效，缓存了别名就会读到旧的 value
+    if let (Some(cache), Some((mapping, false))) = (&state.cache, &found) {
+        cache.insert(&namespace::cache_key(ns, code), mapping.clone());
+    }
+    Ok(found.map(|(mapping, _)| mapping))
+}
+
+/// 启动自检：在一个最后回滚的事务里走一遍 encode（给一个随机的哨兵 value 分配短码）和 decode（格式校验、按短码查回 value），
+/// 字符集、前后缀、数据库权限之类的问题在放开流量之前就暴露出来，而不是等到第一个真实请求。返回分配到的短码。
+/// 回滚之后什么都不留下，只有 PostgreSQL 的序列不随事务回滚，每次自检跳过一个 id
+async fn self_check(
+    pool: &Pool,
+    cfg: &CodeConfig,
+    generator: &dyn CodeGenerator,
+) -> anyhow::Result<String> {
+    let value = Value::Text(format!("bpb-self-check-{:016x}", rand::random::<u64>()));
+    let mut tx = pool.begin().await?;
+    let code = match assign_code(
+        &mut tx,
+        cfg,
+        generator,
+        namespace::DEFAULT,
+        &value,
+        None,
+        Audit::DISABLED,
+    )
+    .await
+    {
+        Ok((code, _)) => code,
+        // 短码空间满了只影响新建，已有的短码照样能 decode，不挡启动（watch_capacity 会接着告警）
+        Err(e @ ApiError::Exhausted { .. }) => {
+            warn!(error = %e, "startup self-check skipped encode: code space exhausted");
+            return Ok(String::new());
+        }
+        Err(e) => anyhow::bail!("encode: {e}"),
+    };
+    let canonical = canonical_code(cfg, &code)
+        .map_err(|e| anyhow::anyhow!("generated code {code:?} is rejected: {e}"))?;
+    if canonical != code {
+        anyhow::bail!("generated code {code:?} canonicalizes to {canonical:?}");
+    }
+    let stored = sqlx::query_as::<_, (Option<String>, Option<Vec<u8>>)>(
+        "SELECT value, value_bin FROM mappings WHERE namespace = $1 AND code = $2",
+    )
+    .bind(namespace::DEFAULT)
+    .bind(&code)
+    .fetch_optional(&mut *tx)
+    .await?
+    .map(|(text, bytes)| Value::from_columns(text, bytes));
+    if stored.as_ref() != Some(&value) {
+        anyhow::bail!("decode of {code:?} returned {stored:?}, expected {value:?}");
+    }
+    tx.rollback().await?;
+    Ok(code)
+}
+
+/// 把 hit_count 最高的 limit 条（未删除、未过期）映射一次查出来放进缓存，返回放进去的条数。
+/// 按命中从低到高插入，最热的留在 LRU 表头；limit 超过缓存容量时多出来的只会被挤掉，按容量截断
+async fn warm_cache(
+    pool: &Pool,
+    cache: &LruCache<Mapping>,
+    limit: usize,
+) -> Result<usize, sqlx::Error> {
+    let limit = limit.min(cache.capacity());
+    let rows = sqlx::query(
+        "SELECT namespace, code, id, value, value_bin, expires_at FROM mappings \
+         WHERE (value IS NOT NULL OR value_bin IS NOT NULL) AND deleted_at IS NULL \
+         AND (expires_at IS NULL OR expires_at > $1) \
+         ORDER BY hit_count DESC, id LIMIT $2",
+    )
+    .bind(now_unix())
+    .bind(limit as i64)
+    .fetch_all(pool)
+    .await?;
+
+    for row in rows.iter().rev() {
+        let (ns, code): (String, String) = (row.get("namespace"), row.get("code"));
+        let mapping = Mapping {
+            id: row.get("id"),
+            value: Value::from_columns(row.get("value"), row.get("value_bin")),
+            expires_at: row.get("expires_at"),
+        };
+        cache.insert(&namespace::cache_key(&ns, &code), mapping);
+    }
+    Ok(rows.len())
+}
+
+/// DECODE_LRU_DUMP_PATH 文件的第一行，对不上就当作没有这个文件
+const CACHE_DUMP_HEADER: &str = "bpb-decode-lru-keys v1";
+
+/// 退出时把 LRU 里的 key（不含 value）按从新到旧写进文件，每行 `namespace<TAB>code`。
+/// 先写临时文件再 rename，写到一半退出也不会留下半个文件
+fn dump_cache_keys(cache: &LruCache<Mapping>, path: &str) -> std::io::Result<usize> {
+    let keys = cache.keys();
+    let mut out = String::from(CACHE_DUMP_HEADER);
+    out.push('\n');
+    for key in &keys {
+        let (ns, code) = namespace::split_cache_key(key);
+        out.push_str(ns);
+        out.push('\t');
+        out.push_str(code);
+        out.push('\n');
+    }
+    let tmp = format!("{path}.tmp");
+    std::fs::write(&tmp, out)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(keys.len())
+}
+
+/// 启动时按 dump_cache_keys 写下的 key 重新从数据库查出映射放进缓存，保持原来的先后顺序。
+/// 这只是优化：文件不存在、表头不对或某一行格式不对都直接跳过，不报错；
+/// 已经删掉、过期或不合法的 code 查不到，也就不会进缓存
+async fn load_cache_dump(
+    pool: &Pool,
+    cache: &LruCache<Mapping>,
+    path: &str,
+) -> Result<usize, sqlx::Error> {
+    let Ok(content) = std::fs::read_to_string(path) else {
+        return Ok(0);
+    };
+    let mut lines = content.lines();
+    if lines.next() != Some(CACHE_DUMP_HEADER) {
+        return Ok(0);
+    }
+    let keys: Vec<(&str, &str)> = lines
+        .filter_map(|line| line.split_once('\t'))
+        .filter(|(ns, code)| {
+            !code.is_empty() && (ns.is_empty() || namespace::parse(Some(ns)).is_ok())
+        })
+        .take(cache.capacity())
+        .collect();
+
+    let mut by_ns: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for &(ns, code) in &keys {
+        by_ns.entry(ns).or_default().push(code);
+    }
+    let mut found = HashMap::new();
+    for (ns, codes) in by_ns {
+        for chunk in codes.chunks(IN_LIST_CHUNK) {
+            let sql = format!(
+                "SELECT code, id, value, value_bin, expires_at FROM mappings \
+                 WHERE namespace = $1 AND (value IS NOT NULL OR value_bin IS NOT NULL) AND deleted_at IS NULL \
+                 AND (expires_at IS NULL OR expires_at > $2) AND code IN ({})",
+                in_placeholders(3, chunk.len())
+            );
+            let mut query = sqlx::query(&sql).bind(ns).bind(now_unix());
+            for code in chunk {
+                query = query.bind(*code);
+            }
+            for row in query.fetch_all(pool).await? {
+                let code: String = row.get("code");
+                let mapping = Mapping {
+                    id: row.get("id"),
+                    value: Value::from_columns(row.get("value"), row.get("value_bin")),
+                    expires_at: row.get("expires_at"),
+                };
+                found.insert(namespace::cache_key(ns, &code).into_owned(), mapping);
+            }
+        }
+    }
+
+    // 文件里第一行是最近使用的，倒着插入让它最后落在表头
+    let mut loaded = 0;
+    for &(ns, code) in keys.iter().rev() {
+        let key = namespace::cache_key(ns, code);
+        if let Some(mapping) = found.remove(key.as_ref()) {
+            cache.insert(&key, mapping);
+            loaded += 1;
+        }
+    }
+    Ok(loaded)
+}
+
+/// decode 查到的一条映射
+#[derive(Clone)]
+struct Mapping {
+    id: i64,
+    value: Value,
+    expires_at: Option<i64>,
+}
+
+/// 查找 code：读 value，再在事务内 decode_count++ + 写事件，保证统计不漏（count 为 false 时只读不计）。
+/// code 也可以是别名，查到的是它指向的映射，返回值的第二项标出这种情况；事件里记的是请求的 code，A/B 测试按它区分。
+/// 配置了读池时 SELECT 走读池；副本可能还没同步到刚 encode 的 code，未命中时再到主库查一次
+async fn lookup_code(
+    state: &AppState,
+    tx: &mut Tx<'_>,
+    ns: &str,
+    code: &str,
+    count: bool,
+) -> Result<Option<(Mapping, bool)>, ApiError> {
+    let now = now_unix();
+    let select = || {
+        sqlx::query(
+            "SELECT id, code, value, value_bin, expires_at, deleted_at FROM mappings \
+             WHERE namespace = $1 AND (code = $2 OR id = (SELECT mapping_id FROM aliases WHERE namespace = $1 AND code = $2)) \
+             AND (expires_at IS NULL OR expires_at > $3)",
+        )
+        .bind(ns)
+        .bind(code)
+        .bind(now)
+    };
+    let row = match &state.read_pool {
+        Some(read_pool) => match select().fetch_optional(read_pool).await? {
+            Some(row) => Some(row),
+            None => select().fetch_optional(&mut **tx).await?,
+        },
+        None => select().fetch_optional(&mut **tx).await?,
+    };
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    if row.get::<Option<i64>, _>("deleted_at").is_some() {
+        return Err(ApiError::Gone);
+    }
+    let (text, bin): (Option<String>, Option<Vec<u8>>) = (row.get("value"), row.get("value_bin"));
+    if text.is_none() && bin.is_none() {
+        return Err(ApiError::Reserved);
+    }
+    let id: i64 = row.get("id");
+    let alias = row.get::<String, _>("code") != code;
+    let value = Value::from_columns(text, bin);
+    let expires_at: Option<i64> = row.get("expires_at");
+    if !count {
+        return Ok(Some((
+            Mapping {
+                id,
+                value,
+                expires_at,
+            },
+            alias,
+        )));
+    }
+
+    sqlx::query("UPDATE mappings SET decode_count = decode_count + 1 WHERE id = $1")
+        .bind(id)
+        .execute(&mut **tx)
+        .await?;
+
+    sqlx::query(
+        "INSERT INTO events (action, mapping_id, code, value) VALUES ('decode', $1, $2, $3)",
+    )
+    .bind(id)
+    .bind(code)
+    .bind(value.as_text())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(Some((
+        Mapping {
+            id,
+            value,
+            expires_at,
+        },
+        alias,
+    )))
+}
+
+/// GET /{code}：302 跳转到 value。只允许 http/https，避免 javascript: 之类的跳转。
+/// URL 里只有 code，所以只查默认命名空间。HEAD 返回同样的状态码和 Location，但不计入统计
+async fn redirect(
+    State(state): State<AppState>,
+    method: Method,
+    Path(code): Path<String>,
+) -> Result<Response, ApiError> {
+    let mapping = decode_code(&state, namespace::DEFAULT, &code, method != Method::HEAD).await?;
+
+    let url = mapping
+        .value
+        .as_text()
+        .and_then(|v| url::Url::parse(v).ok())
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .ok_or_else(|| ApiError::BadRequest("value is not an http(s) URL".to_string()))?;
+
+    Ok((StatusCode::FOUND, [(header::LOCATION, url.as_str())]).into_response())
+}
+
+/// GET /validate/{code}：只做格式校验（长度、字符集、校验字符），不查库。
+/// 不合法也返回 200，方便前端做表单校验
+async fn validate(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+) -> Json<ValidateResponse> {
+    let reason = canonical_code(&state.code, &code)
+        .err()
+        .map(|e| e.to_string());
+    Json(ValidateResponse {
+        valid: reason.is_none(),
+        reason,
+    })
+}
+
+/// GET /qr/{code} 每个模块的默认像素数 / 上限
+const QR_DEFAULT_SCALE: u32 = 8;
+const QR_MAX_SCALE: u32 = 32;
+
+#[derive(Deserialize)]
+struct QrQuery {
+    namespace: Option<String>,
+    /// 每个模块（二维码里的一个小方块）的边长，像素
+    scale: Option<u32>,
+    /// 纠错等级 L / M / Q / H
+    ecc: Option<String>,
+}
+
+/// GET /qr/{code}：二维码 PNG。REDIRECT_MODE 且设置了 BASE_URL 时内容是完整的短链接
+/// （只有默认命名空间能跳转），否则就是 code 本身。
+///
+/// 只检查 code 存在，不计入 decode 统计
+async fn qr_code(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<QrQuery>,
+) -> Result<Response, ApiError> {
+    let ns = parse_namespace(query.namespace.as_deref())?;
+    let scale = query.scale.unwrap_or(QR_DEFAULT_SCALE);
+    if !(1..=QR_MAX_SCALE).contains(&scale) {
+        return Err(ApiError::BadRequest(format!(
+            "scale must be 1..={QR_MAX_SCALE}"
+        )));
+    }
+    let ecc = match query.ecc.as_deref() {
+        Some(raw) => qr::parse_ecc(raw)
+            .ok_or_else(|| ApiError::BadRequest("ecc must be one of L, M, Q, H".to_string()))?,
+        None => qrcode::EcLevel::M,
+    };
+    let code = canonical_code(&state.code, &code)?;
+
+    let row = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(
+        "SELECT expires_at, deleted_at FROM mappings \
+         WHERE namespace = $1 AND code = $2 AND (expires_at IS NULL OR expires_at > $3)",
+    )
+    .bind(ns)
+    .bind(&code)
+    .bind(now_unix())
+    .fetch_optional(state.read_pool())
+    .await?;
+    let expires_at = match row {
+        None => return Err(ApiError::NotFound),
+        Some((_, Some(_))) => return Err(ApiError::Gone),
+        Some((expires_at, None)) => expires_at,
+    };
+
+    let content = match state.short_url(ns, &code) {
+        Some(url) if state.redirect_mode => url,
+        _ => code,
+    };
+    let png = qr::png(content.as_bytes(), ecc, scale)
+        .ok_or_else(|| ApiError::BadRequest("content is too long for a QR code".to_string()))?;
+
+    let max_age = cache_max_age(&state, expires_at);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/png".to_string()),
+            (header::CACHE_CONTROL, format!("public, max-age={max_age}")),
+        ],
+        png,
+    )
+        .into_response())
+}
+
+/// GET /stats/{code}：单个 code 的命中统计
+async fn stats(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<NamespaceQuery>,
+) -> ApiResult<StatsResponse> {
+    let ns = parse_namespace(query.namespace.as_deref())?;
+    let code = canonical_code(&state.code, &code)?;
+
+    let row = query_stats(&state, ns, std::slice::from_ref(&code))
+        .await?
+        .remove(&code)
+        .ok_or(ApiError::NotFound)?;
+    if row.deleted {
+        return Err(ApiError::Gone);
+    }
+    if row.reserved {
+        return Err(ApiError::Reserved);
+    }
+    Ok(Json(row.stats))
+}
+
+/// POST /stats/batch：一次查多个 code 的统计，结果和 codes 按位置对应，查不到的为 null。
+/// 和单个 stats 是同一条查询，只是换成 `IN (...)`，按 IN_LIST_CHUNK 分块
+async fn stats_batch(
+    State(state): State<AppState>,
+    JsonBody(req): JsonBody<StatsBatchRequest>,
+) -> Result<(Extension<scan::LookupMisses>, Json<StatsBatchResponse>), ApiError> {
+    let ns = parse_namespace(req.namespace.as_deref())?;
+    if req.codes.is_empty() {
+        return Err(ApiError::BadRequest("codes is empty".to_string()));
+    }
+    if req.codes.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!(
+            "too many codes (max {MAX_BATCH_SIZE})"
+        )));
+    }
+
+    // 不合法的 code 不查库，直接是 null
+    let canonical: Vec<Option<String>> = req
+        .codes
+        .iter()
+        .map(|code| canonical_code(&state.code, code).ok())
+        .collect();
+    let mut unique: Vec<String> = canonical.iter().flatten().cloned().collect();
+    unique.sort_unstable();
+    unique.dedup();
+    let rows = query_stats(&state, ns, &unique).await?;
+
+    let results: Vec<Option<StatsResponse>> = canonical
+        .iter()
+        .map(|code| {
+            let row = rows.get(code.as_ref()?)?;
+            (!row.deleted && !row.reserved).then(|| row.stats.clone())
+        })
+        .collect();
+    let misses = results.iter().filter(|r| r.is_none()).count() as u64;
+    Ok((
+        Extension(scan::LookupMisses(misses)),
+        Json(StatsBatchResponse { results }),
+    ))
+}
+
+/// stats 查到的一行；已软删除、预留的也会查出来，由调用方决定怎么处理
+struct StatsRow {
+    stats: StatsResponse,
+    deleted: bool,
+    reserved: bool,
+}
+
+/// 单个和批量 stats 共用的查询：按 code 查未过期的映射，返回 code -> StatsRow。
+/// codes 按 IN_LIST_CHUNK 分块，每块一条 `IN (...)` 查询
+async fn query_stats(
+    state: &AppState,
+    ns: &str,
+    codes: &[String],
+) -> Result<HashMap<String, StatsRow>, sqlx::Error> {
+    let mut found = HashMap::with_capacity(codes.len());
+    for chunk in codes.chunks(IN_LIST_CHUNK) {
+        let sql = format!(
+            "SELECT id, code, value, value_bin, hit_count, created_at, deleted_at FROM mappings \
+             WHERE namespace = $1 AND (expires_at IS NULL OR expires_at > $2) AND code IN ({})",
+            in_placeholders(3, chunk.len())
+        );
+        let mut query = sqlx::query(&sql).bind(ns).bind(now_unix());
+        for code in chunk {
+            query = query.bind(code);
+        }
+        for row in timing::db(query.fetch_all(state.read_pool())).await? {
+            let code: String = row.get("code");
+            let id: i64 = row.get("id");
+            let (text, bin): (Option<String>, Option<Vec<u8>>) =
+                (row.get("value"), row.get("value_bin"));
+            let reserved = text.is_none() && bin.is_none();
+            let stats = StatsResponse {
+                code: code.clone(),
+                value: Value::from_columns(text, bin),
+                // 加上还没写回数据库的部分
+                hit_count: row.get::<i64, _>("hit_count") + state.hits.pending(id),
+                created_at: row.get("created_at"),
+            };
+            let deleted = row.get::<Option<i64>, _>("deleted_at").is_some();
+            found.insert(
+                code,
+                StatsRow {
+                    stats,
+                    deleted,
+                    reserved,
+                },
+            );
+        }
+    }
+    Ok(found)
+}
+
+/// POST /admin/rotate：给一个 value 换一个新 code（code 泄露或被滥用时），value、id 和统计都不变。
+///
+/// 在同一个事务里把这一行的 code 改成新分配的，tombstone 时再插一行只有旧 code 的墓碑（已软删除、没有 value），
+/// decode 旧 code 返回 410，自动分配和 custom_code 也不会再用到它。新旧 code 都从 LRU 缓存里清掉
+async fn rotate_code(
+    State(state): State<AppState>,
+    audit: Audit,
+    JsonBody(req): JsonBody<RotateRequest>,
+) -> ApiResult<RotateResponse> {
+    let ns = parse_namespace(req.namespace.as_deref())?;
+    let text = req.value.as_deref().map(|v| state.normalize.apply(v));
+    let value = Value::from_fields(text, req.value_b64.as_deref()).map_err(ApiError::BadRequest)?;
+    validate_value(&state, value.as_bytes())?;
+
+    let (old_code, new_code) = with_busy_retry(&state, || async {
+        let mut tx = state.pool.begin().await?;
+        let (id, old_code, expires_at, deleted_at) =
+            sqlx::query_as::<_, (i64, String, Option<i64>, Option<i64>)>(&format!(
+                "SELECT id, code, expires_at, deleted_at FROM mappings \
+                 WHERE namespace = $1 AND value_hash = $4 AND {} = $2 AND code IS NOT NULL \
+                 AND (expires_at IS NULL OR expires_at > $3)",
+                value.column()
+            ))
+            .bind(ns)
+            .bind(&value)
+            .bind(now_unix())
+            .bind(value.dedup_hash())
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        if deleted_at.is_some() {
+            return Err(ApiError::Gone);
+        }
+
+        let new_code = fresh_code(&state, &mut tx, ns).await?;
+        sqlx::query("UPDATE mappings SET code = $1 WHERE id = $2")
+            .bind(&new_code)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        // 墓碑和原映射一起过期，过期后旧 code 和普通的过期映射一样被清理、可以重新使用
+        if req.tombstone {
+            sqlx::query(
+                "INSERT INTO mappings (namespace, code, value_hash, expires_at, deleted_at) VALUES ($1, $2, $3, $4, $5)",
+            )
+            .bind(ns)
+            .bind(&old_code)
+            .bind(placeholder_hash("tombstone", ns, &old_code))
+            .bind(expires_at)
+            .bind(now_unix())
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query("INSERT INTO events (action, mapping_id, code, value) VALUES ('rotate', $1, $2, $3)")
+            .bind(id)
+            .bind(&new_code)
+            .bind(value.as_text())
+            .execute(&mut *tx)
+            .await?;
+        audit::record(&mut tx, audit, "rotate", ns, &new_code, &value).await?;
+        tx.commit().await?;
+        Ok((old_code, new_code))
+    })
+    .await?;
+
+    // 旧 code 可能还在缓存里指向这个 value；新 code 之前若属于一个已删除的映射，也可能有残留
+    if let Some(cache) = &state.cache {
+        cache.remove(&namespace::cache_key(ns, &old_code));
+        cache.remove(&namespace::cache_key(ns, &new_code));
+    }
+    info!(old_code = %old_code, new_code = %new_code, tombstone = req.tombstone, "code rotated");
+    Ok(Json(RotateResponse {
+        url: state.short_url(ns, &new_code),
+        code: new_code,
+        old_code,
+    }))
+}
+
+/// POST /reserve：先占住一个 code、之后再用 PATCH /mappings/{code} 填 value（比如先印好二维码，落地页还没定）。
+///
+/// 插一行只有 code 没有 value 的映射（value_hash 为占位哈希），和普通映射一样占着 code 的唯一性：
+/// 自动分配和 custom_code 都不会再用到它。填 value 之前 decode 返回 409 reserved，列表、导出、计数里都看不到
+async fn reserve_code(
+    State(state): State<AppState>,
+    audit: Audit,
+    JsonBody(req): JsonBody<ReserveRequest>,
+) -> ApiResult<ReserveResponse> {
+    let ns = parse_namespace(req.namespace.as_deref())?;
+    // 和 custom_code 一样：开启校验位时传的只是主体部分，校验字符由服务端追加
+    let code = state.code.canonicalize(&req.code);
+    validate_code_body(&state.code, &code)?;
+    let code = state.code.full_code(code);
+
+    with_busy_retry(&state, || async {
+        let mut tx = state.pool.begin().await?;
+        // 已过期的映射视为不存在，它的 code 可以重新预留
+        sqlx::query("DELETE FROM mappings WHERE namespace = $1 AND code = $2 AND expires_at IS NOT NULL AND expires_at <= $3")
+            .bind(ns)
+            .bind(&code)
+            .bind(now_unix())
+            .execute(&mut *tx)
+            .await?;
+        if code_taken(&mut tx, ns, &code).await? {
+            return Err(ApiError::Conflict("code is already taken".to_string()));
+        }
 
-    sqlx::query("UPDATE mappings SET decode_count = decode_count + 1 WHERE id = ?1")
-        .bind(id)
-        .execute(&mut *tx)
+        let id = sqlx::query_scalar::<_, i64>(
+            "INSERT INTO mappings (namespace, code, value_hash) VALUES ($1, $2, $3) RETURNING id",
+        )
+        .bind(ns)
+        .bind(&code)
+        .bind(placeholder_hash("reserved", ns, &code))
+        .fetch_one(&mut *tx)
         .await?;
+        sqlx::query("INSERT INTO events (action, mapping_id, code) VALUES ('reserve', $1, $2)")
+            .bind(id)
+            .bind(&code)
+            .execute(&mut *tx)
+            .await?;
+        audit::record(&mut tx, audit, "reserve", ns, &code, &Value::Bytes(Vec::new())).await?;
+        tx.commit().await?;
+        Ok(())
+    })
+    .await?;
 
-    sqlx::query("INSERT INTO events (action, mapping_id, code, value) VALUES ('decode', ?1, ?2, ?3)")
-        .bind(id)
-        .bind(&req.code)
+    // 这个 code 之前若属于一个已删除或过期的映射，缓存里可能还有残留
+    if let Some(cache) = &state.cache {
+        cache.remove(&namespace::cache_key(ns, &code));
+    }
+    info!(code = %code, namespace = ns, "code reserved");
+    Ok(Json(ReserveResponse {
+        url: state.short_url(ns, &code),
+        code,
+    }))
+}
+
+/// POST /encode/alias：给 value 额外分配 count 个短码（A/B 测试时区分不同的投放渠道），decode 哪一个都得到同一个 value。
+///
+/// value 还没有 code 时先和 encode 一样建好映射。别名存在 aliases 表里、指向映射的 id，code 和普通映射一样从生成器取、
+/// 跳过已占用的；过期、软删除、PATCH 都跟着映射走，映射被删掉时别名一起删掉
+async fn encode_alias(
+    State(state): State<AppState>,
+    audit: Audit,
+    JsonBody(req): JsonBody<AliasRequest>,
+) -> ApiResult<AliasResponse> {
+    let ns = parse_namespace(req.namespace.as_deref())?;
+    if req.count == 0 || req.count > MAX_ALIAS_COUNT {
+        return Err(ApiError::BadRequest(format!(
+            "count must be 1..={MAX_ALIAS_COUNT}"
+        )));
+    }
+    let text = req.value.as_deref().map(|v| state.normalize.apply(v));
+    let value = Value::from_fields(text, req.value_b64.as_deref()).map_err(ApiError::BadRequest)?;
+    validate_value(&state, value.as_bytes())?;
+    check_whitespace(&state, value.as_text())?;
+    check_value_pattern(&state, value.as_bytes())?;
+
+    let (code, _) = with_busy_retry(&state, || {
+        encode_new(&state, ns, &value, None, false, audit)
+    })
+    .await?;
+
+    let aliases = with_busy_retry(&state, || async {
+        let mut tx = state.pool.begin().await?;
+        let id = sqlx::query_scalar::<_, i64>(
+            "SELECT id FROM mappings WHERE namespace = $1 AND code = $2",
+        )
+        .bind(ns)
+        .bind(&code)
+        .fetch_optional(&mut *tx)
+        .await?
+        // 两个事务之间被并发删掉了
+        .ok_or(ApiError::NotFound)?;
+
+        let mut aliases = Vec::with_capacity(req.count as usize);
+        for _ in 0..req.count {
+            let alias = fresh_code(&state, &mut tx, ns).await?;
+            sqlx::query("INSERT INTO aliases (namespace, code, mapping_id) VALUES ($1, $2, $3)")
+                .bind(ns)
+                .bind(&alias)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO events (action, mapping_id, code, value) VALUES ('alias', $1, $2, $3)",
+            )
+            .bind(id)
+            .bind(&alias)
+            .bind(value.as_text())
+            .execute(&mut *tx)
+            .await?;
+            audit::record(&mut tx, audit, "alias", ns, &alias, &value).await?;
+            aliases.push(alias);
+        }
+        tx.commit().await?;
+        Ok(aliases)
+    })
+    .await?;
+
+    // 新 code 之前若属于一个已删除的映射，缓存里可能还有残留
+    if let Some(cache) = &state.cache {
+        for alias in &aliases {
+            cache.remove(&namespace::cache_key(ns, alias));
+        }
+    }
+    info!(code = %code, count = aliases.len(), "aliases created");
+    Ok(Json(AliasResponse { code, aliases }))
+}
+
+/// 给 rotate 和 POST /encode/alias 取一个没被占用的新 code。默认命名空间的自增方案要插入一行才能拿到新 id：
+/// 先插一行占位（value 全空）、删掉，再用它的 id 生成短码；AUTOINCREMENT 不会复用 id，这个编号以后也不会再发出去
+async fn fresh_code(state: &AppState, tx: &mut Tx<'_>, ns: &str) -> Result<String, ApiError> {
+    match state.code.strategy {
+        CodeStrategy::Sequential | CodeStrategy::Feistel if ns == namespace::DEFAULT => loop {
+            let id = sqlx::query_scalar::<_, i64>(
+                "INSERT INTO mappings (namespace) VALUES ($1) RETURNING id",
+            )
+            .bind(ns)
+            .fetch_one(&mut **tx)
+            .await?;
+            sqlx::query("DELETE FROM mappings WHERE id = $1")
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+            let code = state.generator.code_for(id)?;
+            if !code_taken(tx, ns, &code).await? {
+                return Ok(code);
+            }
+        },
+        CodeStrategy::Sequential | CodeStrategy::Feistel => {
+            next_namespace_code(tx, state.generator.as_ref(), ns).await
+        }
+        CodeStrategy::Random => next_random_code(tx, &state.code, ns).await,
+    }
+}
+
+/// 墓碑、预留这类没有 value 的行的 value_hash（kind 为 "tombstone" / "reserved"）：
+/// VALUE_HASH_DEDUP 下 (namespace, value_hash) 唯一，每行要各不相同。前缀和 Value::dedup_hash 的列名不同，不会和真 value 的哈希撞上
+fn placeholder_hash(kind: &str, ns: &str, code: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(kind.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(ns.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(code.as_bytes());
+    hasher.finalize().to_vec()
+}
+
+/// GET /admin/search：找出 value（文本）里包含 q 的映射，按 id 排序，最多 limit 条。
+///
+/// `LIKE '%q%'` 用不上索引，每次都是全表扫描，表大了会很慢：只给管理后台偶尔用，慢查询会打 warn 日志
+async fn admin_search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchParams>,
+) -> ApiResult<SearchResponse> {
+    let q = params.q.as_deref().unwrap_or_default();
+    if q.is_empty() {
+        return Err(ApiError::BadRequest("q is empty".to_string()));
+    }
+    if q.len() > state.max_value_len {
+        return Err(ApiError::BadRequest(format!(
+            "q too long (max {} bytes)",
+            state.max_value_len
+        )));
+    }
+    let limit = params.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be 1..={MAX_LIST_LIMIT}"
+        )));
+    }
+    let ns = params
+        .namespace
+        .as_deref()
+        .map(|ns| parse_namespace(Some(ns)))
+        .transpose()?;
+
+    // 多取一条用来判断是否被截断
+    let sql = format!(
+        "SELECT namespace, code, value FROM mappings WHERE {LIVE_MAPPINGS_FILTER} \
+         AND value LIKE $2 ESCAPE '\\'{} ORDER BY id LIMIT $3",
+        if ns.is_some() {
+            " AND namespace = $4"
+        } else {
+            ""
+        }
+    );
+    let mut query = sqlx::query_as::<_, (String, String, String)>(&sql)
+        .bind(now_unix())
+        .bind(format!("%{}%", escape_like(q)))
+        .bind(limit + 1);
+    if let Some(ns) = ns {
+        query = query.bind(ns);
+    }
+    let started = Instant::now();
+    let mut items: Vec<SearchItem> = query
+        .fetch_all(state.read_pool())
+        .await?
+        .into_iter()
+        .map(|(namespace, code, value)| SearchItem {
+            namespace,
+            code,
+            value,
+        })
+        .collect();
+    let elapsed = started.elapsed();
+    if elapsed >= SLOW_SEARCH {
+        warn!(
+            elapsed_ms = elapsed.as_millis() as u64,
+            "slow admin search: LIKE '%q%' scans the whole mappings table"
+        );
+    }
+
+    let truncated = items.len() as i64 > limit;
+    items.truncate(limit as usize);
+    Ok(Json(SearchResponse { items, truncated }))
+}
+
+/// GET /admin/stale（TRACK_LAST_ACCESS）：列出 before 之后没有被访问过的映射，最久没访问的在前。
+/// 从没记录过访问的按 created_at 算，before 之后才创建的不算
+async fn admin_stale(
+    State(state): State<AppState>,
+    Query(params): Query<StaleParams>,
+) -> ApiResult<StaleResponse> {
+    let before = params
+        .before
+        .ok_or_else(|| ApiError::BadRequest("before is required".to_string()))?;
+    let limit = params.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be 1..={MAX_LIST_LIMIT}"
+        )));
+    }
+    let ns = params
+        .namespace
+        .as_deref()
+        .map(|ns| parse_namespace(Some(ns)))
+        .transpose()?;
+
+    // 多取一条用来判断是否被截断
+    let sql = format!(
+        "SELECT namespace, code, last_accessed_at, created_at FROM mappings WHERE {LIVE_MAPPINGS_FILTER} \
+         AND COALESCE(last_accessed_at, created_at) < $2{} \
+         ORDER BY COALESCE(last_accessed_at, created_at), id LIMIT $3",
+        if ns.is_some() {
+            " AND namespace = $4"
+        } else {
+            ""
+        }
+    );
+    let mut query = sqlx::query_as::<_, (String, String, Option<i64>, i64)>(&sql)
+        .bind(now_unix())
+        .bind(before)
+        .bind(limit + 1);
+    if let Some(ns) = ns {
+        query = query.bind(ns);
+    }
+    let mut items: Vec<StaleItem> = query
+        .fetch_all(state.read_pool())
+        .await?
+        .into_iter()
+        .map(
+            |(namespace, code, last_accessed_at, created_at)| StaleItem {
+                namespace,
+                code,
+                last_accessed_at,
+                created_at,
+            },
+        )
+        .collect();
+
+    let truncated = items.len() as i64 > limit;
+    items.truncate(limit as usize);
+    Ok(Json(StaleResponse { items, truncated }))
+}
+
+/// POST /admin/value：stats 的反向查询，按 value 查 code 和统计信息；只读，永远不会新建映射。
+///
+/// 走 db::init_db 建的 value 唯一索引，是一次 O(log n) 的索引查找：默认是 (namespace, value) /
+/// (namespace, value_bin)，VALUE_HASH_DEDUP 开启时只有 (namespace, value_hash)。条件里两列都带上，哪种模式都能命中索引
+async fn admin_value(
+    State(state): State<AppState>,
+    JsonBody(req): JsonBody<AdminValueRequest>,
+) -> ApiResult<StatsResponse> {
+    let ns = parse_namespace(req.namespace.as_deref())?;
+    let text = req.value.as_deref().map(|v| state.normalize.apply(v));
+    let value = Value::from_fields(text, req.value_b64.as_deref()).map_err(ApiError::BadRequest)?;
+    validate_value(&state, value.as_bytes())?;
+
+    let (id, code, hit_count, created_at, deleted_at) =
+        sqlx::query_as::<_, (i64, String, i64, i64, Option<i64>)>(&format!(
+            "SELECT id, code, hit_count, created_at, deleted_at FROM mappings \
+         WHERE namespace = $1 AND value_hash = $4 AND {} = $2 AND code IS NOT NULL \
+         AND (expires_at IS NULL OR expires_at > $3)",
+            value.column()
+        ))
+        .bind(ns)
         .bind(&value)
-        .execute(&mut *tx)
-        .await?;
+        .bind(now_unix())
+        .bind(value.dedup_hash())
+        .fetch_optional(state.read_pool())
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    if deleted_at.is_some() {
+        return Err(ApiError::Gone);
+    }
 
-    tx.commit().await?;
+    Ok(Json(StatsResponse {
+        code,
+        value,
+        hit_count: hit_count + state.hits.pending(id),
+        created_at,
+    }))
+}
+
+/// GET /count：可见映射的总数（同 GET /mappings 的 total），可按 namespace 过滤。
+/// 大表上 COUNT(*) 要扫全表，结果缓存 COUNT_CACHE_TTL_SECS 秒
+async fn count_mappings(
+    State(state): State<AppState>,
+    Query(query): Query<NamespaceQuery>,
+) -> ApiResult<CountResponse> {
+    let ns = match query.namespace.as_deref() {
+        Some(raw) => Some(parse_namespace(Some(raw))?),
+        None => None,
+    };
+    let key = ns.unwrap_or(COUNT_ALL_KEY);
+    if let Some(count) = state.count_cache.get(key) {
+        return Ok(Json(CountResponse { count }));
+    }
 
-    Ok(Json(DecodeResponse { value }))
+    let count = match ns {
+        Some(ns) => {
+            sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT COUNT(*) FROM mappings WHERE {LIVE_MAPPINGS_FILTER} AND namespace = $2"
+            ))
+            .bind(now_unix())
+            .bind(ns)
+            .fetch_one(state.read_pool())
+            .await?
+        }
+        None => {
+            sqlx::query_scalar::<_, i64>(&format!(
+                "SELECT COUNT(*) FROM mappings WHERE {LIVE_MAPPINGS_FILTER}"
+            ))
+            .bind(now_unix())
+            .fetch_one(state.read_pool())
+            .await?
+        }
+    };
+    state.count_cache.insert(key, count);
+    Ok(Json(CountResponse { count }))
 }
 
-fn validate_code(code: &str) -> Result<(), ApiError> {
-    let len = code.len();
-    if !(2..=5).contains(&len) {
-        return Err(ApiError::BadRequest("code length must be 2..=5".to_string()));
+/// GET /admin/stats：不用 Prometheus 时的运行概况，每次现查
+async fn admin_stats(State(state): State<AppState>) -> ApiResult<AdminStatsResponse> {
+    let (mappings_total, stored_hits): (i64, i64) = sqlx::query_as(&format!(
+        "SELECT COUNT(*), COALESCE(SUM(hit_count), 0) FROM mappings WHERE {LIVE_MAPPINGS_FILTER}"
+    ))
+    .bind(now_unix())
+    .fetch_one(state.read_pool())
+    .await?;
+
+    let capacity = state.code.auto_capacity();
+    let namespaces = namespace_usage(state.read_pool(), &state.code)
+        .await?
+        .into_iter()
+        .map(|(namespace, used)| NamespaceCapacity {
+            namespace,
+            used,
+            capacity,
+            utilization_percent: used as f64 / capacity as f64 * 100.0,
+        })
+        .collect();
+
+    Ok(Json(AdminStatsResponse {
+        mappings_total,
+        decodes_total: stored_hits + state.hits.pending_total(),
+        decode_cache_hit_rate: state
+            .cache
+            .as_ref()
+            .map(|_| state.metrics.decode_cache_hit_rate().unwrap_or(0.0)),
+        uptime_secs: state.started_at.elapsed().as_secs(),
+        namespaces,
+    }))
+}
+
+/// GET /mappings?limit=&offset=&created_after=&created_before=：按 id 顺序分页列出映射（不含已过期的）
+async fn list_mappings(
+    State(state): State<AppState>,
+    Query(params): Query<ListParams>,
+) -> ApiResult<ListResponse> {
+    let limit = params.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if !(1..=MAX_LIST_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "limit must be 1..={MAX_LIST_LIMIT}"
+        )));
     }
-    if !code
-        .as_bytes()
-        .iter()
-        .all(|&b| CHARSET.contains(&b))
+    let offset = params.offset.unwrap_or(0);
+    if offset < 0 {
+        return Err(ApiError::BadRequest("offset must be >= 0".to_string()));
+    }
+    let created_after = parse_unix_param("created_after", params.created_after.as_deref())?;
+    let created_before = parse_unix_param("created_before", params.created_before.as_deref())?;
+    if let (Some(after), Some(before)) = (created_after, created_before)
+        && after > before
     {
         return Err(ApiError::BadRequest(
-            "code contains invalid characters".to_string(),
+            "created_after must be <= created_before".to_string(),
         ));
     }
+
+    // 没传的过滤条件不拼进 SQL（绑定 NULL 在 PostgreSQL 上推断不出参数类型），占位符按顺序编号
+    let mut filter = String::from(LIVE_MAPPINGS_FILTER);
+    let mut binds = vec![now_unix()];
+    for (cond, bound) in [(">=", created_after), ("<=", created_before)] {
+        if let Some(bound) = bound {
+            binds.push(bound);
+            filter.push_str(&format!(" AND created_at {cond} ${}", binds.len()));
+        }
+    }
+
+    let count_sql = format!("SELECT COUNT(*) FROM mappings WHERE {filter}");
+    let mut count = sqlx::query_scalar::<_, i64>(&count_sql);
+    for bind in &binds {
+        count = count.bind(*bind);
+    }
+    let total = count.fetch_one(state.read_pool()).await?;
+
+    let sql = format!(
+        "SELECT namespace, code, value, value_bin, created_at FROM mappings WHERE {filter} \
+         ORDER BY id LIMIT ${} OFFSET ${}",
+        binds.len() + 1,
+        binds.len() + 2
+    );
+    let mut query =
+        sqlx::query_as::<_, (String, String, Option<String>, Option<Vec<u8>>, i64)>(&sql);
+    for bind in &binds {
+        query = query.bind(*bind);
+    }
+    let items = query
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(state.read_pool())
+        .await?
+        .into_iter()
+        .map(|(namespace, code, text, bytes, created_at)| ListItem {
+            namespace,
+            code,
+            value: Value::from_columns(text, bytes),
+            created_at,
+        })
+        .collect();
+
+    Ok(Json(ListResponse { total, items }))
+}
+
+/// GET /export 的 channel 容量：客户端读得慢时，后台任务最多先取这么多行
+const EXPORT_CHANNEL_CAPACITY: usize = 256;
+
+/// GET /export 每行一条
+#[derive(Serialize)]
+struct ExportItem {
+    #[serde(skip_serializing_if = "str::is_empty")]
+    namespace: String,
+    code: String,
+    #[serde(flatten)]
+    value: Value,
+    created_at: i64,
+}
+
+/// GET /export：以 NDJSON 流式导出全部（未过期的）映射，用于备份。
+///
+/// 后台任务从连接池逐行读取、经 channel 交给响应体；客户端断开后响应体被丢弃，
+/// 下一次 send 失败，任务随之退出并释放数据库游标。中途出错只能截断响应（状态码已经发出去了）
+async fn export(State(state): State<AppState>) -> Response {
+    let (tx, mut rx) =
+        tokio::sync::mpsc::channel::<Result<String, sqlx::Error>>(EXPORT_CHANNEL_CAPACITY);
+    let pool = state.read_pool().clone();
+    let now = now_unix();
+    tokio::spawn(async move {
+        let mut rows = sqlx::query_as::<_, (String, String, Option<String>, Option<Vec<u8>>, i64)>(
+            "SELECT namespace, code, value, value_bin, created_at FROM mappings \
+             WHERE code IS NOT NULL AND (value IS NOT NULL OR value_bin IS NOT NULL) AND deleted_at IS NULL \
+             AND (expires_at IS NULL OR expires_at > $1) \
+             ORDER BY id",
+        )
+        .bind(now)
+        .fetch(&pool);
+        loop {
+            let line = match rows.try_next().await {
+                Ok(Some((namespace, code, text, bytes, created_at))) => {
+                    let value = Value::from_columns(text, bytes);
+                    let item = ExportItem {
+                        namespace,
+                        code,
+                        value,
+                        created_at,
+                    };
+                    let mut line = serde_json::to_string(&item).expect("export item serializes");
+                    line.push('\n');
+                    Ok(line)
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    error!(error = %e, "export stream failed");
+                    Err(e)
+                }
+            };
+            let failed = line.is_err();
+            if tx.send(line).await.is_err() {
+                info!("export client disconnected");
+                break;
+            }
+            if failed {
+                break;
+            }
+        }
+    });
+
+    // 同 encode_stream：用 poll_recv 而不是 unfold，流结束后再被 poll 不会 panic
+    let body = futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx));
+    (
+        [(header::CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+/// NDJSON 请求体里一行最长：value 全部被转义成 \uXXXX 时约为 6 倍，再加上 code 和字段名
+fn max_ndjson_line_len(state: &AppState) -> usize {
+    state.max_value_len.saturating_mul(6).saturating_add(1024)
+}
+
+/// POST /import 导入内容（NDJSON 请求体或 multipart 里的文件）的上限，超过返回 413
+#[derive(Clone, Copy)]
+struct ImportLimits {
+    max_bytes: u64,
+    max_lines: usize,
+}
+
+/// 按行读取 NDJSON（POST /import、POST /encode/stream 的请求体，或 multipart 里的文件），不把整个内容读进内存。
+/// 跳过空白行；最后一行可以没有换行符；一行超过 max_line_len 返回 400
+struct NdjsonLines<S = BodyDataStream> {
+    stream: S,
+    buf: Vec<u8>,
+    /// buf 里还没处理的部分从这里开始
+    start: usize,
+    line_no: usize,
+    max_line_len: usize,
+    eof: bool,
+    /// 设置了才检查总字节数和行数
+    limits: Option<ImportLimits>,
+    bytes_read: u64,
+}
+
+impl<S, E> NdjsonLines<S>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    fn new(stream: S, max_line_len: usize) -> Self {
+        NdjsonLines {
+            stream,
+            buf: Vec::new(),
+            start: 0,
+            line_no: 0,
+            max_line_len,
+            eof: false,
+            limits: None,
+            bytes_read: 0,
+        }
+    }
+
+    fn with_limits(mut self, limits: ImportLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// 下一个非空行及其行号（从 1 开始，空行也计数）；读完返回 None
+    async fn next(&mut self) -> Result<Option<(usize, Vec<u8>)>, ApiError> {
+        loop {
+            if let Some(pos) = self.buf[self.start..].iter().position(|&b| b == b'\n') {
+                let line = self.buf[self.start..self.start + pos].to_vec();
+                self.start += pos + 1;
+                self.line_no += 1;
+                if let Some(limits) = self.limits.filter(|l| self.line_no > l.max_lines) {
+                    return Err(ApiError::ImportFileTooLarge(format!(
+                        "import file has too many lines (max {})",
+                        limits.max_lines
+                    )));
+                }
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return Ok(Some((self.line_no, line)));
+            }
+            self.buf.drain(..self.start);
+            self.start = 0;
+            if self.buf.len() > self.max_line_len {
+                return Err(ApiError::BadRequest(format!(
+                    "line {} is too long",
+                    self.line_no + 1
+                )));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            let chunk =
+                self.stream.try_next().await.map_err(|e| {
+                    ApiError::BadRequest(format!("failed to read request body: {e}"))
+                })?;
+            match chunk {
+                Some(chunk) => {
+                    self.bytes_read += chunk.len() as u64;
+                    if let Some(limits) = self.limits.filter(|l| self.bytes_read > l.max_bytes) {
+                        return Err(ApiError::ImportFileTooLarge(format!(
+                            "import file too large (max {} bytes)",
+                            limits.max_bytes
+                        )));
+                    }
+                    self.buf.extend_from_slice(&chunk);
+                }
+                None => {
+                    self.eof = true;
+                    // 最后一行可以没有换行符
+                    if !self.buf.is_empty() {
+                        self.buf.push(b'\n');
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// POST /import 每多少行提交一次事务
+const IMPORT_BATCH_SIZE: usize = 500;
+
+/// POST /import 每行一条；created_at 可选（导入 GET /export 的备份时沿用原值）
+#[derive(Deserialize)]
+struct ImportItem {
+    #[serde(default)]
+    value: Option<String>,
+    #[serde(default)]
+    value_b64: Option<String>,
+    code: String,
+    #[serde(default)]
+    created_at: Option<i64>,
+    #[serde(default)]
+    namespace: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ImportResponse {
+    inserted: u64,
+    /// value 或 code 已存在的行
+    skipped: u64,
+    /// 解析失败、value / code 不合法的行
+    errors: u64,
+}
+
+/// POST /import 用 multipart/form-data 上传时，文件所在的字段名
+const IMPORT_FILE_FIELD: &str = "file";
+
+/// POST /import：导入 NDJSON（`{"value","code"}` 每行一条），可以直接用 GET /export 的输出。
+/// 请求体可以就是 NDJSON，也可以是 multipart/form-data 里名为 `file` 的文件（HTML 表单上传备份文件）。
+///
+/// 边读边处理，每 IMPORT_BATCH_SIZE 行提交一次事务，所以不受 MAX_BODY_BYTES 限制；两种请求体都按
+/// IMPORT_MAX_FILE_BYTES / IMPORT_MAX_LINES 限制，gzip 请求体按解压后的大小计算。
+/// 中途失败时已提交的批次会保留，重新导入同一份文件是安全的（已存在的行计入 skipped）
+async fn import(
+    State(state): State<AppState>,
+    audit: Audit,
+    req: Request,
+) -> ApiResult<ImportResponse> {
+    let max_line_len = max_ndjson_line_len(&state);
+    let is_multipart = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.trim_start()
+                .to_ascii_lowercase()
+                .starts_with("multipart/form-data")
+        });
+    let limits = state.import_limits;
+    let summary = if is_multipart {
+        let mut multipart = Multipart::from_request(req, &state)
+            .await
+            .map_err(|e| ApiError::BadRequest(e.body_text()))?;
+        // 表单里别的字段（提交按钮之类）直接跳过，只读第一个 file 字段
+        let field = loop {
+            match multipart
+                .next_field()
+                .await
+                .map_err(|e| ApiError::BadRequest(format!("invalid multipart body: {e}")))?
+            {
+                Some(field) if field.name() == Some(IMPORT_FILE_FIELD) => break field,
+                Some(_) => continue,
+                None => {
+                    return Err(ApiError::BadRequest(format!(
+                        "multipart body has no {IMPORT_FILE_FIELD:?} field"
+                    )));
+                }
+            }
+        };
+        import_lines(
+            &state,
+            audit,
+            NdjsonLines::new(field, max_line_len).with_limits(limits),
+        )
+        .await?
+    } else {
+        let lines =
+            NdjsonLines::new(req.into_body().into_data_stream(), max_line_len).with_limits(limits);
+        import_lines(&state, audit, lines).await?
+    };
+
+    info!(
+        inserted = summary.inserted,
+        skipped = summary.skipped,
+        errors = summary.errors,
+        "import finished"
+    );
+    Ok(Json(summary))
+}
+
+/// 逐行导入，每 IMPORT_BATCH_SIZE 行提交一次
+async fn import_lines<S, E>(
+    state: &AppState,
+    audit: Audit,
+    mut lines: NdjsonLines<S>,
+) -> Result<ImportResponse, ApiError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let mut summary = ImportResponse {
+        inserted: 0,
+        skipped: 0,
+        errors: 0,
+    };
+    let mut pending = 0;
+    let mut tx = state.pool.begin().await?;
+
+    while let Some((line_no, line)) = lines.next().await? {
+        import_line(state, &mut tx, audit, &line, line_no, &mut summary).await?;
+        pending += 1;
+        if pending == IMPORT_BATCH_SIZE {
+            tx.commit().await?;
+            tx = state.pool.begin().await?;
+            pending = 0;
+        }
+    }
+    tx.commit().await?;
+    Ok(summary)
+}
+
+#[derive(Deserialize)]
+struct VacuumQuery {
+    /// 顺带执行 `PRAGMA optimize`
+    #[serde(default)]
+    optimize: bool,
+}
+
+#[derive(Serialize)]
+struct VacuumResponse {
+    /// VACUUM 前后的库文件大小（字节）
+    size_before: i64,
+    size_after: i64,
+}
+
+/// POST /admin/vacuum（仅 SQLite）：VACUUM 回收删除留下的空间，返回前后的库文件大小。
+///
+/// 同一时间只允许一个维护操作，已经有在跑的返回 409。VACUUM 跑起来就没法安全地打断，
+/// 所以放在单独的任务里：超过 VACUUM_TIMEOUT_SECS 返回 408，VACUUM 在后台继续跑完，锁也一直占到那时
+async fn admin_vacuum(
+    State(state): State<AppState>,
+    Query(query): Query<VacuumQuery>,
+) -> ApiResult<VacuumResponse> {
+    let guard = state.maintenance.clone().try_lock_owned().map_err(|_| {
+        ApiError::Conflict("another maintenance operation is in progress".to_string())
+    })?;
+
+    let pool = state.pool.clone();
+    let task = tokio::spawn(async move {
+        let _guard = guard;
+        let start = Instant::now();
+        let size_before = db::sqlite_size(&pool).await?;
+        db::sqlite_vacuum(&pool, query.optimize).await?;
+        let size_after = db::sqlite_size(&pool).await?;
+        info!(
+            size_before,
+            size_after,
+            elapsed_ms = start.elapsed().as_millis() as u64,
+            "vacuum finished"
+        );
+        Ok::<_, sqlx::Error>(VacuumResponse {
+            size_before,
+            size_after,
+        })
+    });
+
+    match tokio::time::timeout(state.vacuum_timeout, task).await {
+        Ok(joined) => Ok(Json(joined.expect("vacuum task panicked")?)),
+        Err(_) => {
+            warn!(
+                timeout_secs = state.vacuum_timeout.as_secs(),
+                "vacuum still running, continuing in background"
+            );
+            Err(ApiError::Timeout)
+        }
+    }
+}
+
+/// 导入一行：不合法计入 errors，value / code 冲突计入 skipped
+async fn import_line(
+    state: &AppState,
+    tx: &mut Tx<'_>,
+    audit: Audit,
+    line: &[u8],
+    line_no: usize,
+    summary: &mut ImportResponse,
+) -> Result<(), ApiError> {
+    let item = match serde_json::from_slice::<ImportItem>(line) {
+        Ok(item) => item,
+        Err(e) => {
+            warn!(line = line_no, error = %e, "import: invalid json");
+            summary.errors += 1;
+            return Ok(());
+        }
+    };
+    let text = item.value.map(|v| state.normalize.apply(&v));
+    let parsed = Value::from_fields(text, item.value_b64.as_deref())
+        .map_err(ApiError::BadRequest)
+        .and_then(|value| {
+            validate_value(state, value.as_bytes())?;
+            check_whitespace(state, value.as_text())?;
+            check_value_pattern(state, value.as_bytes())?;
+            let ns = parse_namespace(item.namespace.as_deref())?;
+            Ok((ns, value, canonical_code(&state.code, &item.code)?))
+        });
+    let (ns, value, code) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            warn!(line = line_no, error = %e, "import: invalid mapping");
+            summary.errors += 1;
+            return Ok(());
+        }
+    };
+
+    // 不指定冲突列：value 或 code 任何一个已存在都跳过。别名在另一张表里，唯一约束管不到，先单独查
+    if code_taken(tx, ns, &code).await? {
+        summary.skipped += 1;
+        return Ok(());
+    }
+    let id = sqlx::query_scalar::<_, i64>(&format!(
+        "INSERT INTO mappings (namespace, {}, value_hash, code, created_at) VALUES ($1, $2, $5, $3, $4) \
+         ON CONFLICT DO NOTHING RETURNING id",
+        value.column()
+    ))
+    .bind(ns)
+    .bind(&value)
+    .bind(&code)
+    .bind(item.created_at.unwrap_or_else(now_unix))
+    .bind(value.dedup_hash())
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some(id) = id else {
+        summary.skipped += 1;
+        return Ok(());
+    };
+
+    sqlx::query(
+        "INSERT INTO events (action, mapping_id, code, value) VALUES ('import', $1, $2, $3)",
+    )
+    .bind(id)
+    .bind(&code)
+    .bind(value.as_text())
+    .execute(&mut **tx)
+    .await?;
+    audit::record(tx, audit, "import", ns, &code, &value).await?;
+    summary.inserted += 1;
     Ok(())
 }
 
-const CHARSET: &[u8; 62] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// DELETE /mappings/{code}：删除映射。id 是 AUTOINCREMENT，不会复用，
+/// 所以之后重新 encode 同一个 value 会拿到一个新的 code，旧 code 不会再被分配出去。
+async fn delete_mapping(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<NamespaceQuery>,
+    audit: Audit,
+) -> Result<StatusCode, ApiError> {
+    let ns = parse_namespace(query.namespace.as_deref())?;
+    let code = canonical_code(&state.code, &code)?;
+
+    // 和批量删除一样：SQLite 上并发写撞锁时重试整个事务，而不是返回 500
+    let found = with_busy_retry(&state, || async {
+        let mut tx = state.pool.begin().await?;
+        let found = delete_code(&state, &mut tx, ns, &code, audit).await?;
+        tx.commit().await?;
+        Ok(found)
+    })
+    .await?;
+    if !found {
+        return Err(ApiError::NotFound);
+    }
+
+    if let Some(cache) = &state.cache {
+        cache.remove(&namespace::cache_key(ns, &code));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// POST /mappings/delete：批量删除，整批在一个事务里（和单个删除一样按 SOFT_DELETE 软删或硬删）。
+/// 非法的 code 单独列出、不让整批失败；重复的 code 只删一次
+async fn delete_batch(
+    State(state): State<AppState>,
+    audit: Audit,
+    JsonBody(req): JsonBody<DeleteBatchRequest>,
+) -> ApiResult<DeleteBatchResponse> {
+    let ns = parse_namespace(req.namespace.as_deref())?;
+    if req.codes.is_empty() {
+        return Err(ApiError::BadRequest("codes is empty".to_string()));
+    }
+    if req.codes.len() > MAX_BATCH_SIZE {
+        return Err(ApiError::BadRequest(format!(
+            "too many codes (max {MAX_BATCH_SIZE})"
+        )));
+    }
+
+    let mut invalid = Vec::new();
+    let mut targets = Vec::new();
+    let mut seen = HashSet::new();
+    for code in req.codes {
+        match canonical_code(&state.code, &code) {
+            Err(e) => invalid.push(InvalidCode {
+                code,
+                error: e.to_string(),
+            }),
+            Ok(canonical) => {
+                if seen.insert(canonical.clone()) {
+                    targets.push((code, canonical));
+                }
+            }
+        }
+    }
+
+    let found = with_busy_retry(&state, || async {
+        let mut tx = state.pool.begin().await?;
+        let mut found = Vec::with_capacity(targets.len());
+        for (_, code) in &targets {
+            found.push(delete_code(&state, &mut tx, ns, code, audit).await?);
+        }
+        tx.commit().await?;
+        Ok(found)
+    })
+    .await?;
+
+    let mut deleted = 0;
+    let mut not_found = Vec::new();
+    for ((input, code), found) in targets.into_iter().zip(found) {
+        if !found {
+            not_found.push(input);
+            continue;
+        }
+        deleted += 1;
+        if let Some(cache) = &state.cache {
+            cache.remove(&namespace::cache_key(ns, &code));
+        }
+    }
+    Ok(Json(DeleteBatchResponse {
+        deleted,
+        not_found,
+        invalid,
+    }))
+}
+
+/// 在事务内删除一个 code 并记事件和审计，返回是否删到了
+async fn delete_code(
+    state: &AppState,
+    tx: &mut Tx<'_>,
+    ns: &str,
+    code: &str,
+    audit: Audit,
+) -> Result<bool, ApiError> {
+    // 软删除：只打标记，value 和 code 都还占着，重新 encode 同一个 value 会恢复这个 code
+    let deleted = if state.soft_delete {
+        sqlx::query_as::<_, (i64, Option<String>, Option<Vec<u8>>)>(
+            "UPDATE mappings SET deleted_at = $3 WHERE namespace = $1 AND code = $2 AND deleted_at IS NULL \
+             RETURNING id, value, value_bin",
+        )
+        .bind(ns)
+        .bind(code)
+        .bind(now_unix())
+        .fetch_optional(&mut **tx)
+        .await?
+    } else {
+        sqlx::query_as::<_, (i64, Option<String>, Option<Vec<u8>>)>(
+            "DELETE FROM mappings WHERE namespace = $1 AND code = $2 RETURNING id, value, value_bin",
+        )
+            .bind(ns)
+            .bind(code)
+            .fetch_optional(&mut **tx)
+            .await?
+    };
+    let Some((id, text, bytes)) = deleted else {
+        return Ok(false);
+    };
+    let value = Value::from_columns(text, bytes);
+
+    sqlx::query(
+        "INSERT INTO events (action, mapping_id, code, value) VALUES ('delete', $1, $2, $3)",
+    )
+    .bind(id)
+    .bind(code)
+    .bind(value.as_text())
+    .execute(&mut **tx)
+    .await?;
+    audit::record(tx, audit, "delete", ns, code, &value).await?;
+    Ok(true)
+}
+
+/// PATCH /mappings/{code}：原地改掉 code 背后的 value，code 保持不变（可编辑的短链接）。
+/// value 是 UNIQUE 的，新 value 已经映射到别的 code 时返回 409
+async fn update_mapping(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<NamespaceQuery>,
+    audit: Audit,
+    JsonBody(req): JsonBody<UpdateRequest>,
+) -> Result<StatusCode, ApiError> {
+    let ns = parse_namespace(query.namespace.as_deref())?;
+    let code = canonical_code(&state.code, &code)?;
+    let text = req.value.map(|v| state.normalize.apply(&v));
+    let value = Value::from_fields(text, req.value_b64.as_deref()).map_err(ApiError::BadRequest)?;
+    validate_value(&state, value.as_bytes())?;
+    check_whitespace(&state, value.as_text())?;
+    check_value_pattern(&state, value.as_bytes())?;
+
+    with_busy_retry(&state, || update_value(&state, ns, &code, &value, audit)).await?;
+
+    if let Some(cache) = &state.cache {
+        cache.remove(&namespace::cache_key(ns, &code));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn update_value(
+    state: &AppState,
+    ns: &str,
+    code: &str,
+    value: &Value,
+    audit: Audit,
+) -> Result<(), ApiError> {
+    let now = now_unix();
+    let mut tx = state.pool.begin().await?;
+
+    let (id, deleted_at) = sqlx::query_as::<_, (i64, Option<i64>)>(
+        "SELECT id, deleted_at FROM mappings WHERE namespace = $1 AND code = $2 \
+         AND (expires_at IS NULL OR expires_at > $3)",
+    )
+    .bind(ns)
+    .bind(code)
+    .bind(now)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(ApiError::NotFound)?;
+    if deleted_at.is_some() {
+        return Err(ApiError::Gone);
+    }
+
+    // 已过期的映射视为不存在：先清掉，它占着的 value 才能拿来用
+    let column = value.column();
+    let hash = value.dedup_hash();
+    sqlx::query(&format!(
+        "DELETE FROM mappings WHERE namespace = $1 AND value_hash = $4 AND {column} = $2 \
+         AND expires_at IS NOT NULL AND expires_at <= $3"
+    ))
+    .bind(ns)
+    .bind(value)
+    .bind(now)
+    .bind(&hash)
+    .execute(&mut *tx)
+    .await?;
 
-fn id_to_code(id: i64) -> Result<String, ApiError> {
-    if id <= 0 {
-        return Err(ApiError::BadRequest("invalid id".to_string()));
+    let owner = sqlx::query_as::<_, (i64, Option<String>)>(&format!(
+        "SELECT id, code FROM mappings WHERE namespace = $1 AND value_hash = $3 AND {column} = $2"
+    ))
+    .bind(ns)
+    .bind(value)
+    .bind(&hash)
+    .fetch_optional(&mut *tx)
+    .await?;
+    match owner {
+        Some((owner_id, _)) if owner_id == id => return Ok(()),
+        Some((_, Some(other))) => {
+            return Err(ApiError::Conflict(format!(
+                "value is already mapped to code {other}"
+            )));
+        }
+        Some((_, None)) => {
+            return Err(ApiError::Conflict(
+                "value is already mapped to another code".to_string(),
+            ));
+        }
+        None => {}
     }
-    let mut n = id as u64;
 
-    let mut buf = Vec::new();
-    while n > 0 {
-        let rem = (n % 62) as usize;
-        buf.push(CHARSET[rem]);
-        n /= 62;
+    // 文本和二进制 value 之间也可以互相改，另一列清空
+    let other_column = match value {
+        Value::Text(_) => "value_bin",
+        Value::Bytes(_) => "value",
+    };
+    sqlx::query(&format!(
+        "UPDATE mappings SET {column} = $1, {other_column} = NULL, value_hash = $3 WHERE id = $2"
+    ))
+    .bind(value)
+    .bind(id)
+    .bind(&hash)
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO events (action, mapping_id, code, value) VALUES ('update', $1, $2, $3)",
+    )
+    .bind(id)
+    .bind(code)
+    .bind(value.as_text())
+    .execute(&mut *tx)
+    .await?;
+    audit::record(&mut tx, audit, "update", ns, code, value).await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// 校验传入的 code，返回用于查库的规范形式（大小写不敏感模式下为小写）
+fn canonical_code(cfg: &CodeConfig, code: &str) -> Result<String, ApiError> {
+    let code = cfg.canonicalize(code);
+    validate_code(cfg, &code)?;
+    Ok(code)
+}
+
+/// 校验完整短码：先去掉前后缀，剩下的按长度和字符集检查；开启 CODE_CHECKSUM 时再核对校验字符，不对就不用查库了
+fn validate_code(cfg: &CodeConfig, code: &str) -> Result<(), ApiError> {
+    let code = code
+        .strip_prefix(&*cfg.prefix)
+        .ok_or_else(|| ApiError::BadRequest(format!("code must start with {:?}", cfg.prefix)))?;
+    let code = code
+        .strip_suffix(&*cfg.suffix)
+        .ok_or_else(|| ApiError::BadRequest(format!("code must end with {:?}", cfg.suffix)))?;
+    let (min_len, max_len) = cfg.code_len_bounds();
+    check_code_chars(cfg, code, min_len, max_len)?;
+    if cfg.checksum {
+        let (body, check) = code.as_bytes().split_at(code.len() - 1);
+        if cfg.charset[luhn_check_index(&cfg.charset, body)] != check[0] {
+            return Err(ApiError::BadRequest("checksum mismatch".to_string()));
+        }
     }
-    buf.reverse();
-    let mut s = String::from_utf8(buf).expect("charset is ascii");
+    Ok(())
+}
+
+/// 校验不含校验字符的短码主体（自定义短码）
+fn validate_code_body(cfg: &CodeConfig, code: &str) -> Result<(), ApiError> {
+    check_code_chars(cfg, code, cfg.min_len, cfg.max_len)
+}
 
-    if s.len() > 5 {
-        return Err(ApiError::Exhausted);
+fn check_code_chars(
+    cfg: &CodeConfig,
+    code: &str,
+    min_len: usize,
+    max_len: usize,
+) -> Result<(), ApiError> {
+    let len = code.len();
+    if !(min_len..=max_len).contains(&len) {
+        return Err(ApiError::BadRequest(format!(
+            "code length must be {min_len}..={max_len}"
+        )));
     }
-    if s.len() < 2 {
-        s = format!("{:0>2}", s);
+    if !code.as_bytes().iter().all(|&b| cfg.charset.contains(&b)) {
+        return Err(ApiError::BadRequest(
+            "code contains invalid characters".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Luhn mod N：N 为字符集大小，能发现任意单个字符写错和相邻字符对调；唯一的例外是偶数 N 时
+/// 字符集第一个和最后一个字符对调（同经典 Luhn 的 09 ↔ 90），奇数 N 没有例外。
+/// 返回校验字符在字符集中的下标；body 里的字符必须都在字符集内
+fn luhn_check_index(charset: &[u8], body: &[u8]) -> usize {
+    let n = charset.len();
+    let mut sum = 0;
+    // 从右往左，最右边的字符先乘 2（校验字符之后会补在它右边）
+    for (i, b) in body.iter().rev().enumerate() {
+        let v = charset
+            .iter()
+            .position(|c| c == b)
+            .expect("validated charset");
+        sum += if i % 2 == 1 {
+            v
+        } else if n.is_multiple_of(2) {
+            // 偶数 N：经典 Luhn 的“乘 2 后数位相加”
+            (v * 2) / n + (v * 2) % n
+        } else {
+            // 奇数 N 时上面的映射会撞车，改用 2v mod N（2 与 N 互素，仍是置换）
+            (v * 2) % n
+        };
     }
-    Ok(s)
+    (n - sum % n) % n
 }
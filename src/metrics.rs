@@ -0,0 +1,194 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// 请求耗时直方图的桶上界（秒）
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Prometheus 指标。计数走原子变量，/metrics 抓取时渲染成文本格式。
+#[derive(Default)]
+pub struct Metrics {
+    pub encode_requests: AtomicU64,
+    /// encode 时按 value 反查到已有映射、直接返回原 code 的次数
+    pub encode_existing: AtomicU64,
+    pub decode_requests: AtomicU64,
+    pub decode_not_found: AtomicU64,
+    pub decode_cache_hits: AtomicU64,
+    /// 启用了 LRU 缓存时，没命中、去查了数据库的 decode 查找次数
+    pub decode_cache_misses: AtomicU64,
+    /// 后台检查发现短码空间用量超过 CODE_CAPACITY_WARN_FRACTION 的次数
+    pub code_capacity_warnings: AtomicU64,
+    /// 某个 IP 在 SCAN_DETECT_WINDOW_SECS 内的未命中次数超过 SCAN_DETECT_THRESHOLD 的次数
+    pub scan_suspects: AtomicU64,
+    /// 因为 MAX_CONCURRENT_REQUESTS 满了被 503 拒绝的请求数
+    pub requests_overloaded: AtomicU64,
+    /// 当前正在处理的业务请求数（不含探针、/metrics）
+    pub requests_in_flight: AtomicU64,
+    /// 最近一次检查时短码空间的用量比例（f64 的位模式）
+    code_capacity_used: AtomicU64,
+    latency: Mutex<BTreeMap<String, Histogram>>,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum_secs: f64,
+}
+
+pub fn inc(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+impl Metrics {
+    /// LRU 缓存命中率；还没有过查找时为 None
+    pub fn decode_cache_hit_rate(&self) -> Option<f64> {
+        let hits = self.decode_cache_hits.load(Ordering::Relaxed);
+        let total = hits + self.decode_cache_misses.load(Ordering::Relaxed);
+        (total > 0).then(|| hits as f64 / total as f64)
+    }
+
+    pub fn set_code_capacity_used(&self, ratio: f64) {
+        self.code_capacity_used
+            .store(ratio.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn observe_latency(&self, route: &str, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let mut latency = self.latency.lock().unwrap();
+        let h = latency.entry(route.to_string()).or_default();
+        for (i, le) in LATENCY_BUCKETS.iter().enumerate() {
+            if secs <= *le {
+                h.buckets[i] += 1;
+            }
+        }
+        h.count += 1;
+        h.sum_secs += secs;
+    }
+
+    /// 渲染成 Prometheus 文本格式；mappings_total 由调用方在抓取时现查
+    pub fn render(&self, mappings_total: i64) -> String {
+        let mut out = String::new();
+
+        let counters = [
+            (
+                "encode_requests_total",
+                "Total number of encode requests.",
+                &self.encode_requests,
+            ),
+            (
+                "encode_existing_total",
+                "Total number of encode requests answered by an existing mapping for the same value.",
+                &self.encode_existing,
+            ),
+            (
+                "decode_requests_total",
+                "Total number of decode requests.",
+                &self.decode_requests,
+            ),
+            (
+                "decode_not_found_total",
+                "Total number of decode lookups for unknown codes.",
+                &self.decode_not_found,
+            ),
+            (
+                "decode_cache_hits_total",
+                "Total number of decode lookups served from the LRU cache.",
+                &self.decode_cache_hits,
+            ),
+            (
+                "decode_cache_misses_total",
+                "Total number of decode lookups that missed the LRU cache and went to the database.",
+                &self.decode_cache_misses,
+            ),
+            (
+                "code_capacity_warnings_total",
+                "Total number of capacity checks that found the code space above CODE_CAPACITY_WARN_FRACTION.",
+                &self.code_capacity_warnings,
+            ),
+            (
+                "scan_suspects_total",
+                "Total number of times a client IP exceeded SCAN_DETECT_THRESHOLD lookup misses within SCAN_DETECT_WINDOW_SECS.",
+                &self.scan_suspects,
+            ),
+            (
+                "requests_overloaded_total",
+                "Total number of requests rejected with 503 because MAX_CONCURRENT_REQUESTS were already in flight.",
+                &self.requests_overloaded,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            let _ = writeln!(out, "{name} {}", value.load(Ordering::Relaxed));
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP mappings_total Current number of rows in the mappings table."
+        );
+        let _ = writeln!(out, "# TYPE mappings_total gauge");
+        let _ = writeln!(out, "mappings_total {mappings_total}");
+
+        let _ = writeln!(
+            out,
+            "# HELP requests_in_flight Current number of API requests being handled."
+        );
+        let _ = writeln!(out, "# TYPE requests_in_flight gauge");
+        let _ = writeln!(
+            out,
+            "requests_in_flight {}",
+            self.requests_in_flight.load(Ordering::Relaxed)
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP code_capacity_used_ratio Fraction of the auto-assignable code space in use, as of the last capacity check."
+        );
+        let _ = writeln!(out, "# TYPE code_capacity_used_ratio gauge");
+        let _ = writeln!(
+            out,
+            "code_capacity_used_ratio {}",
+            f64::from_bits(self.code_capacity_used.load(Ordering::Relaxed))
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP http_request_duration_seconds HTTP request latency by route."
+        );
+        let _ = writeln!(out, "# TYPE http_request_duration_seconds histogram");
+        for (route, h) in self.latency.lock().unwrap().iter() {
+            for (le, n) in LATENCY_BUCKETS.iter().zip(h.buckets) {
+                let _ = writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"{le}\"}} {n}"
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{route=\"{route}\",le=\"+Inf\"}} {}",
+                h.count
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_sum{{route=\"{route}\"}} {}",
+                h.sum_secs
+            );
+            let _ = writeln!(
+                out,
+                "http_request_duration_seconds_count{{route=\"{route}\"}} {}",
+                h.count
+            );
+        }
+
+        out
+    }
+}
@@ -0,0 +1,56 @@
+use std::borrow::Cow;
+
+use crate::db::Tx;
+
+/// 默认命名空间：请求里不带 namespace 时落在这里，行为与引入命名空间之前完全一致
+pub const DEFAULT: &str = "";
+
+/// namespace 的最大长度
+pub const MAX_LEN: usize = 64;
+
+/// 请求里的 namespace 字段：不传为默认命名空间；传了必须是 1..=64 个 `[A-Za-z0-9_-]`
+pub fn parse(raw: Option<&str>) -> Result<&str, String> {
+    let Some(ns) = raw else {
+        return Ok(DEFAULT);
+    };
+    let valid = (1..=MAX_LEN).contains(&ns.len())
+        && ns
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+    if !valid {
+        return Err(format!(
+            "namespace must be 1..={MAX_LEN} chars of [A-Za-z0-9_-]"
+        ));
+    }
+    Ok(ns)
+}
+
+/// decode LRU 缓存的 key：默认命名空间就是 code 本身，其它命名空间加上前缀。
+/// namespace 里不会出现 NUL，所以不会和别的 (namespace, code) 撞上
+pub fn cache_key<'a>(ns: &str, code: &'a str) -> Cow<'a, str> {
+    if ns == DEFAULT {
+        Cow::Borrowed(code)
+    } else {
+        Cow::Owned(format!("{ns}\0{code}"))
+    }
+}
+
+/// cache_key 的逆过程：拆回 (namespace, code)
+pub fn split_cache_key(key: &str) -> (&str, &str) {
+    key.split_once('\0').unwrap_or((DEFAULT, key))
+}
+
+/// 非默认命名空间的下一个序号（在 encode 事务内执行）。
+///
+/// 每个命名空间在 namespace_counters 里有自己的计数器，短码由这个序号生成，
+/// 所以新命名空间的短码同样从最短的开始；默认命名空间仍然直接用 mappings 的自增 id
+pub async fn next_seq(tx: &mut Tx<'_>, ns: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar::<_, i64>(
+        "INSERT INTO namespace_counters (namespace, last_seq) VALUES ($1, 1) \
+         ON CONFLICT (namespace) DO UPDATE SET last_seq = namespace_counters.last_seq + 1 \
+         RETURNING last_seq",
+    )
+    .bind(ns)
+    .fetch_one(&mut **tx)
+    .await
+}
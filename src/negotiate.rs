@@ -0,0 +1,38 @@
+use axum::http::{HeaderMap, header};
+
+/// 响应格式：按请求头 Accept 在 JSON 和纯文本之间选择
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    /// `text/plain`：只返回 code / value 本身，方便 shell 脚本直接用
+    Text,
+}
+
+impl Format {
+    /// 没有 Accept、或 Accept 里 application/json 的优先级不低于 text/plain 时用 JSON；
+    /// 只有 text/plain 的 q 值严格更高时才返回纯文本
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let Some(accept) = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok()) else {
+            return Format::Json;
+        };
+        let (mut json, mut text) = (0.0_f32, 0.0_f32);
+        for range in accept.split(',') {
+            let mut parts = range.split(';').map(str::trim);
+            let media = parts.next().unwrap_or_default().to_ascii_lowercase();
+            let q = parts
+                .filter_map(|p| p.strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            match media.as_str() {
+                "application/json" => json = json.max(q),
+                "text/plain" => text = text.max(q),
+                _ => {}
+            }
+        }
+        if text > json {
+            Format::Text
+        } else {
+            Format::Json
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::str::FromStr;
+
+/// encode 前对文本 value 做的规范化（NORMALIZE_* 环境变量），每一步单独开关，默认全关。
+///
+/// 规范化发生在去重查询和写库之前，存进去的就是规范化之后的形式，decode 拿到的也是它。
+/// URL 相关的几步只对 `http://` / `https://` 开头的 value 生效，其它 value 原样保留
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Normalizer {
+    /// 首尾空白的处理方式
+    pub trim: TrimMode,
+    /// scheme 和 host 转小写（userinfo、path、query 大小写敏感，不动）
+    pub lowercase_host: bool,
+    /// 去掉默认端口：http 的 :80、https 的 :443
+    pub strip_default_port: bool,
+    /// 去掉 path 末尾的 `/`（包括根路径：`http://x.com/` -> `http://x.com`）
+    pub strip_trailing_slash: bool,
+}
+
+/// NORMALIZE_TRIM：不处理（默认）、去掉首尾空白、或者拒绝带首尾空白的 value
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrimMode {
+    #[default]
+    Off,
+    Trim,
+    Strict,
+}
+
+impl FromStr for TrimMode {
+    type Err = anyhow::Error;
+
+    /// 兼容原来的开关写法（1/true/yes/on），另加 strict
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "0" | "false" | "no" | "off" => Ok(TrimMode::Off),
+            "1" | "true" | "yes" | "on" => Ok(TrimMode::Trim),
+            "strict" => Ok(TrimMode::Strict),
+            other => anyhow::bail!("invalid NORMALIZE_TRIM: {other} (expected 1|0|strict)"),
+        }
+    }
+}
+
+impl Normalizer {
+    pub fn is_enabled(&self) -> bool {
+        self.trim != TrimMode::Off
+            || self.lowercase_host
+            || self.strip_default_port
+            || self.strip_trailing_slash
+    }
+
+    /// NORMALIZE_TRIM=strict 时拒绝带首尾空白的文本 value；其它模式总是通过
+    pub fn check_whitespace(&self, value: &str) -> Result<(), &'static str> {
+        if self.trim == TrimMode::Strict && value.trim() != value {
+            return Err("has leading or trailing whitespace");
+        }
+        Ok(())
+    }
+
+    pub fn apply(&self, value: &str) -> String {
+        let value = if self.trim == TrimMode::Trim {
+            value.trim()
+        } else {
+            value
+        };
+        if !(self.lowercase_host || self.strip_default_port || self.strip_trailing_slash) {
+            return value.to_string();
+        }
+        self.apply_url(value).unwrap_or_else(|| value.to_string())
+    }
+
+    /// 按 `scheme://authority path?query#fragment` 切开再逐段处理；不是 http(s) URL 时返回 None
+    fn apply_url(&self, value: &str) -> Option<String> {
+        let (scheme, rest) = value.split_once("://")?;
+        let default_port = match scheme.to_ascii_lowercase().as_str() {
+            "http" => ":80",
+            "https" => ":443",
+            _ => return None,
+        };
+
+        let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let (authority, tail) = rest.split_at(authority_end);
+        let (userinfo, host_port) = match authority.rsplit_once('@') {
+            Some((userinfo, host_port)) => (Some(userinfo), host_port),
+            None => (None, authority),
+        };
+
+        let mut host_port = host_port.to_string();
+        if self.strip_default_port
+            && let Some(host) = host_port.strip_suffix(default_port)
+            // IPv6 字面量里的冒号不是端口分隔符：[::80] 不能被截成 [:
+            && (!host.starts_with('[') || host.ends_with(']'))
+        {
+            host_port.truncate(host.len());
+        }
+        if self.lowercase_host {
+            host_port.make_ascii_lowercase();
+        }
+
+        let path_end = tail.find(['?', '#']).unwrap_or(tail.len());
+        let (path, suffix) = tail.split_at(path_end);
+        let path = if self.strip_trailing_slash {
+            path.strip_suffix('/').unwrap_or(path)
+        } else {
+            path
+        };
+
+        let scheme = if self.lowercase_host {
+            scheme.to_ascii_lowercase()
+        } else {
+            scheme.to_string()
+        };
+        let userinfo = userinfo.map(|u| format!("{u}@")).unwrap_or_default();
+        Some(format!("{scheme}://{userinfo}{host_port}{path}{suffix}"))
+    }
+}
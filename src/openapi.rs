@@ -0,0 +1,34 @@
+use axum::{
+    http::header,
+    response::{Html, IntoResponse, Response},
+};
+
+/// 手写的 OpenAPI 3 描述；新增或修改接口时记得同步更新 openapi.json
+const SPEC: &str = include_str!("openapi.json");
+
+/// GET /openapi.json
+pub async fn spec() -> Response {
+    ([(header::CONTENT_TYPE, "application/json")], SPEC).into_response()
+}
+
+/// GET /docs：Swagger UI（静态资源从 CDN 加载）
+pub async fn docs() -> Html<&'static str> {
+    Html(
+        r##"<!doctype html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>bpb_short_code_server API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##,
+    )
+}
@@ -0,0 +1,101 @@
+use std::io::Cursor;
+
+use image::{ImageFormat, Luma};
+use qrcode::{EcLevel, QrCode};
+
+/// 查询参数里的纠错等级 `L` / `M` / `Q` / `H`（不区分大小写），分别能容忍大约 7% / 15% / 25% / 30% 的污损
+pub fn parse_ecc(s: &str) -> Option<EcLevel> {
+    match s.to_ascii_uppercase().as_str() {
+        "L" => Some(EcLevel::L),
+        "M" => Some(EcLevel::M),
+        "Q" => Some(EcLevel::Q),
+        "H" => Some(EcLevel::H),
+        _ => None,
+    }
+}
+
+/// 按字节模式编码成 1 位灰度 PNG（自动选能放下内容的最小版本），每个模块 scale × scale 像素，
+/// 四周留 4 个模块宽的空白。内容超出最大版本（40）的容量时返回 None
+pub fn png(content: &[u8], ecc: EcLevel, scale: u32) -> Option<Vec<u8>> {
+    let code = QrCode::with_error_correction_level(content, ecc).ok()?;
+    let image = code
+        .render::<Luma<u8>>()
+        .quiet_zone(true)
+        .module_dimensions(scale, scale)
+        .build();
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .expect("encoding png into memory does not fail");
+    Some(png)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// 用独立的解码器（rqrr）读回 PNG 里的内容，同时返回版本（决定模块数）
+    pub fn decode(png: &[u8]) -> (String, usize) {
+        let image = image::load_from_memory_with_format(png, ImageFormat::Png)
+            .unwrap()
+            .to_luma8();
+        let mut prepared = rqrr::PreparedImage::prepare(image);
+        let grids = prepared.detect_grids();
+        assert_eq!(grids.len(), 1, "expected exactly one qr code");
+        let (meta, content) = grids[0].decode().unwrap();
+        (content, meta.version.0)
+    }
+
+    #[test]
+    fn round_trips_through_a_reference_decoder() {
+        let long = format!("https://s.example.com/{}", "x".repeat(300));
+        for content in ["01", "https://s.example.com/aZ9", long.as_str()] {
+            for ecc in [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H] {
+                let png = png(content.as_bytes(), ecc, 4).unwrap();
+                assert_eq!(decode(&png).0, content, "{ecc:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn size_is_modules_plus_quiet_zone_times_scale() {
+        for scale in [1, 3, 32] {
+            let png = png(b"01", EcLevel::M, scale).unwrap();
+            let image = image::load_from_memory(&png).unwrap();
+            // 版本 1 是 21 × 21 个模块，四周各 4 个模块的空白
+            assert_eq!(
+                (image.width(), image.height()),
+                ((21 + 8) * scale, (21 + 8) * scale)
+            );
+        }
+    }
+
+    #[test]
+    fn higher_ecc_needs_a_larger_version() {
+        let content = "https://s.example.com/abcdefghijklmnopqrstuvwxyz";
+        let versions: Vec<usize> = [EcLevel::L, EcLevel::M, EcLevel::Q, EcLevel::H]
+            .into_iter()
+            .map(|ecc| decode(&png(content.as_bytes(), ecc, 2).unwrap()).1)
+            .collect();
+        assert!(
+            versions.is_sorted() && versions[0] < versions[3],
+            "{versions:?}"
+        );
+    }
+
+    #[test]
+    fn rejects_content_beyond_version_40() {
+        // 版本 40 的字节模式容量：L 2953 字节，H 1273 字节
+        assert!(png(&[b'a'; 2953], EcLevel::L, 1).is_some());
+        assert!(png(&[b'a'; 2954], EcLevel::L, 1).is_none());
+        assert!(png(&[b'a'; 1274], EcLevel::H, 1).is_none());
+    }
+
+    #[test]
+    fn parses_ecc_case_insensitively() {
+        assert_eq!(parse_ecc("h"), Some(EcLevel::H));
+        assert_eq!(parse_ecc("Q"), Some(EcLevel::Q));
+        assert_eq!(parse_ecc("X"), None);
+        assert_eq!(parse_ecc(""), None);
+    }
+}
@@ -0,0 +1,100 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{Extensions, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use crate::ApiError;
+
+/// 超过这么多个 IP 时，顺手清掉已经回满的桶，避免表无限增长
+const MAX_TRACKED_IPS: usize = 10_000;
+
+/// 按客户端 IP 的令牌桶限流：每秒补充 `rate` 个令牌，最多攒 `burst` 个，每个请求消耗一个
+pub struct RateLimiter {
+    rate: f64,
+    burst: f64,
+    trust_proxy: bool,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: f64, burst: f64, trust_proxy: bool) -> Self {
+        RateLimiter {
+            rate,
+            burst,
+            trust_proxy,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 取一个令牌；不够时返回还要等多少秒（向上取整，至少 1）
+    fn acquire(&self, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_IPS && !buckets.contains_key(&ip) {
+            let (rate, burst) = (self.rate, self.burst);
+            buckets
+                .retain(|_, b| b.tokens + now.duration_since(b.last).as_secs_f64() * rate < burst);
+        }
+
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: self.burst,
+            last: now,
+        });
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+        bucket.last = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((((1.0 - bucket.tokens) / self.rate).ceil() as u64).max(1))
+        }
+    }
+}
+
+/// 客户端 IP：TRUST_PROXY 时取 X-Forwarded-For 最左边的地址，否则用 TCP 对端地址
+pub fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trust_proxy: bool,
+) -> Option<IpAddr> {
+    if trust_proxy
+        && let Some(ip) = headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.split(',').next())
+            .and_then(|v| v.trim().parse().ok())
+    {
+        return Some(ip);
+    }
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+/// 超出限额返回 429 + Retry-After
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if let Some(ip) = client_ip(req.headers(), req.extensions(), limiter.trust_proxy) {
+        limiter.acquire(ip).map_err(ApiError::RateLimited)?;
+    }
+    Ok(next.run(req).await)
+}
@@ -0,0 +1,52 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+pub static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// 客户端传来的 X-Request-Id 最长保留多少字节，超长的当作没传
+const MAX_LEN: usize = 128;
+
+/// 给每个请求一个 id：沿用客户端的 X-Request-Id，没有（或不合法）时生成一个随机 UUID；
+/// 挂到 tracing span 上（这个请求的所有日志都带 request_id），并在响应头里原样返回
+pub async fn request_id(req: Request, next: Next) -> Response {
+    let id = req
+        .headers()
+        .get(&X_REQUEST_ID)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid(v))
+        .map_or_else(generate, str::to_owned);
+
+    let span = tracing::info_span!("request", request_id = %id);
+    let mut resp = next.run(req).instrument(span).await;
+    resp.headers_mut().insert(
+        X_REQUEST_ID.clone(),
+        HeaderValue::from_str(&id).expect("validated request id"),
+    );
+    resp
+}
+
+/// 只接受可见 ASCII（不含空格），避免把奇怪的内容写进日志和响应头
+fn is_valid(id: &str) -> bool {
+    (1..=MAX_LEN).contains(&id.len()) && id.bytes().all(|b| b.is_ascii_graphic())
+}
+
+/// 随机 UUID v4（122 位随机数），同一进程内实际上不会重复
+fn generate() -> String {
+    let mut b: [u8; 16] = rand::random();
+    b[6] = (b[6] & 0x0f) | 0x40;
+    b[8] = (b[8] & 0x3f) | 0x80;
+    let hex: String = b.iter().map(|x| format!("{x:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
@@ -0,0 +1,107 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+use crate::metrics::{self, Metrics};
+use crate::ratelimit;
+
+/// 最多跟踪这么多个 IP；满了先清掉窗口已经结束的，还是满的话新 IP 就不跟踪了
+const MAX_TRACKED_IPS: usize = 10_000;
+
+/// 响应整体不是 404、但里面有没找到的 code 时（POST /decode/batch），handler 用这个响应扩展告诉 track_misses 有几个
+#[derive(Clone, Copy)]
+pub struct LookupMisses(pub u64);
+
+/// 按客户端 IP 统计读接口的未命中（404）次数：固定窗口内超过阈值就打 warn 日志并计数，
+/// 用来发现有人在枚举短码。只记录不拦截，拦截交给限流
+pub struct ScanDetector {
+    threshold: u64,
+    window: Duration,
+    trust_proxy: bool,
+    metrics: Arc<Metrics>,
+    windows: Mutex<HashMap<IpAddr, Window>>,
+}
+
+struct Window {
+    start: Instant,
+    misses: u64,
+}
+
+impl ScanDetector {
+    pub fn new(threshold: u64, window: Duration, trust_proxy: bool, metrics: Arc<Metrics>) -> Self {
+        ScanDetector {
+            threshold,
+            window,
+            trust_proxy,
+            metrics,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 记下 n 次未命中；本窗口内第一次越过阈值时告警（同一个窗口只报一次）
+    fn record(&self, ip: IpAddr, n: u64) {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        if windows.len() >= MAX_TRACKED_IPS && !windows.contains_key(&ip) {
+            let window = self.window;
+            windows.retain(|_, w| now.duration_since(w.start) < window);
+            if windows.len() >= MAX_TRACKED_IPS {
+                return;
+            }
+        }
+
+        let w = windows.entry(ip).or_insert(Window {
+            start: now,
+            misses: 0,
+        });
+        if now.duration_since(w.start) >= self.window {
+            *w = Window {
+                start: now,
+                misses: 0,
+            };
+        }
+        let before = w.misses;
+        w.misses += n;
+        if before <= self.threshold && w.misses > self.threshold {
+            metrics::inc(&self.metrics.scan_suspects);
+            warn!(
+                client_ip = %ip,
+                misses = w.misses,
+                window_secs = self.window.as_secs(),
+                "possible code scanning: too many lookup misses"
+            );
+        }
+    }
+}
+
+/// 挂在读接口上：404 记一次未命中，批量 decode 按 LookupMisses 记；拿不到客户端 IP 时不统计
+pub async fn track_misses(
+    State(detector): State<Arc<ScanDetector>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let ip = ratelimit::client_ip(req.headers(), req.extensions(), detector.trust_proxy);
+    let resp = next.run(req).await;
+    let misses = if resp.status() == StatusCode::NOT_FOUND {
+        1
+    } else {
+        resp.extensions().get::<LookupMisses>().map_or(0, |m| m.0)
+    };
+    if misses > 0
+        && let Some(ip) = ip
+    {
+        detector.record(ip, misses);
+    }
+    resp
+}
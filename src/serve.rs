@@ -0,0 +1,58 @@
+use std::{future::Future, net::SocketAddr, pin::pin};
+
+use axum::{Router, body::Body, extract::ConnectInfo, serve::Listener};
+use hyper::{Request, body::Incoming};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto::Builder, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use tower::ServiceExt;
+use tracing::debug;
+
+/// 接受连接并交给 hyper 处理，代替 axum::serve：axum::serve 编进了 HTTP/2 之后总会识别 h2c 的连接前言，
+/// 没法按配置关掉。http2 为 false 时连接只按 HTTP/1.1 解析，h2c 前言当作坏请求直接断开。
+///
+/// peer 从 accept 拿到的地址里取出对端 IP，放进请求的 ConnectInfo<SocketAddr>（限流、审计要用），
+/// Unix socket 没有对端 IP 时返回 None。shutdown 完成后不再 accept，等已有连接处理完才返回
+pub async fn serve<L: Listener>(
+    mut listener: L,
+    app: Router,
+    http2: bool,
+    peer: fn(&L::Addr) -> Option<SocketAddr>,
+    shutdown: impl Future<Output = ()>,
+) {
+    // 不用 serve_connection_with_upgrades：没有 websocket 之类要升级的接口，而且 http1_only 对它不生效
+    let mut builder = Builder::new(TokioExecutor::new());
+    if !http2 {
+        builder = builder.http1_only();
+    }
+
+    let graceful = GracefulShutdown::new();
+    let mut shutdown = pin!(shutdown);
+    loop {
+        let (io, addr) = tokio::select! {
+            conn = listener.accept() => conn,
+            _ = &mut shutdown => break,
+        };
+        let remote = peer(&addr);
+        let service = app.clone().map_request(move |req: Request<Incoming>| {
+            let mut req = req.map(Body::new);
+            if let Some(remote) = remote {
+                req.extensions_mut().insert(ConnectInfo(remote));
+            }
+            req
+        });
+        let conn = builder.serve_connection(TokioIo::new(io), TowerToHyperService::new(service));
+        let conn = graceful.watch(conn.into_owned());
+        tokio::spawn(async move {
+            if let Err(e) = conn.await {
+                debug!(error = %e, "failed to serve connection");
+            }
+        });
+    }
+
+    // 先关掉监听，新连接直接被拒；已有连接在处理完当前请求后关闭
+    drop(listener);
+    graceful.shutdown().await;
+}
@@ -0,0 +1,55 @@
+//! MAX_BODY_BYTES / MAX_BATCH_BODY_BYTES：超过时返回 413 和统一的错误 JSON
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use serde_json::json;
+
+use super::{TestResponse, app, post, send};
+
+fn assert_too_large(resp: &TestResponse) {
+    assert_eq!(resp.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        resp.json(),
+        json!({ "error": "request body too large", "code": "payload_too_large" })
+    );
+}
+
+#[tokio::test]
+async fn oversized_json_body_is_413_json() {
+    let (app, _) = app(&[("MAX_BODY_BYTES", "256")]).await;
+    let resp = post(&app, "/encode", json!({ "value": "a".repeat(300) })).await;
+    assert_too_large(&resp);
+
+    // 上限以内照常处理
+    let resp = post(&app, "/encode", json!({ "value": "a".repeat(200) })).await;
+    assert_eq!(resp.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn oversized_form_body_is_413_json() {
+    let (app, _) = app(&[("MAX_BODY_BYTES", "256")]).await;
+    let req = Request::post("/encode")
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(format!("value={}", "a".repeat(300))))
+        .unwrap();
+    assert_too_large(&send(&app, req).await);
+}
+
+#[tokio::test]
+async fn batch_routes_use_the_batch_limit() {
+    let (app, _) = app(&[("MAX_BODY_BYTES", "256"), ("MAX_BATCH_BODY_BYTES", "1024")]).await;
+    let values: Vec<String> = (0..10)
+        .map(|i| format!("https://example.com/{i:03}"))
+        .collect();
+    let body = json!({ "values": values });
+    assert!((256..1024).contains(&body.to_string().len()));
+    let resp = post(&app, "/encode/batch", body).await;
+    assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+
+    let values: Vec<String> = (0..50)
+        .map(|i| format!("https://example.com/{i:03}"))
+        .collect();
+    assert_too_large(&post(&app, "/encode/batch", json!({ "values": values })).await);
+}
@@ -0,0 +1,102 @@
+//! CODE_CHECKSUM（Luhn mod N）：单个字符写错、相邻字符对调都能被 validate_code 拦下
+
+use crate::{CodeConfig, config::Config, validate_code};
+
+fn checksum_config(charset: &str) -> CodeConfig {
+    let vars = [
+        ("CODE_CHECKSUM", "1"),
+        ("CODE_CHARSET", charset),
+        ("CODE_MIN_LEN", "1"),
+        ("CODE_MAX_LEN", "6"),
+    ];
+    Config::from_vars(&vars).unwrap().code
+}
+
+/// 长度 1、2 的全部主体，再加一批固定种子的伪随机长主体
+fn bodies(charset: &[u8]) -> Vec<String> {
+    let n = charset.len();
+    let mut out: Vec<String> = (0..n).map(|i| (charset[i] as char).to_string()).collect();
+    for i in 0..n {
+        for j in 0..n {
+            out.push([charset[i] as char, charset[j] as char].iter().collect());
+        }
+    }
+    let mut seed = 0x2545_f491_4f6c_dd1du64;
+    for _ in 0..300 {
+        let body = (0..5)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                charset[(seed % n as u64) as usize] as char
+            })
+            .collect();
+        out.push(body);
+    }
+    out
+}
+
+/// 对调后仍能通过校验的字符对：偶数 N 时是 Luhn 固有的 (第一个, 最后一个字符)，奇数 N 时没有
+fn blind_pair(cfg: &CodeConfig) -> Option<(u8, u8)> {
+    let n = cfg.charset.len();
+    n.is_multiple_of(2)
+        .then(|| (cfg.charset[0], cfg.charset[n - 1]))
+}
+
+fn assert_detects_errors(charset: &str) {
+    let cfg = checksum_config(charset);
+    for body in bodies(&cfg.charset) {
+        let code = cfg.full_code(body);
+        assert!(validate_code(&cfg, &code).is_ok(), "{code}");
+        let bytes = code.as_bytes();
+
+        for i in 0..bytes.len() {
+            for &c in cfg.charset.iter().filter(|&&c| c != bytes[i]) {
+                let mut typo = bytes.to_vec();
+                typo[i] = c;
+                let typo = String::from_utf8(typo).unwrap();
+                assert!(
+                    validate_code(&cfg, &typo).is_err(),
+                    "substitution {code} -> {typo} not detected"
+                );
+            }
+        }
+
+        for i in 0..bytes.len() - 1 {
+            let (a, b) = (bytes[i], bytes[i + 1]);
+            if a == b {
+                continue;
+            }
+            let mut swapped = bytes.to_vec();
+            swapped.swap(i, i + 1);
+            let swapped = String::from_utf8(swapped).unwrap();
+            let blind = blind_pair(&cfg).is_some_and(|(x, y)| (a, b) == (x, y) || (a, b) == (y, x));
+            assert_eq!(
+                validate_code(&cfg, &swapped).is_err(),
+                !blind,
+                "transposition {code} -> {swapped}"
+            );
+        }
+    }
+}
+
+#[test]
+fn even_charsets_catch_substitutions_and_transpositions() {
+    assert_detects_errors("0123456789abcdef");
+    assert_detects_errors("0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ");
+}
+
+#[test]
+fn odd_charsets_catch_substitutions_and_every_transposition() {
+    assert_detects_errors("0123456789abcdefg");
+    assert_detects_errors("23456789abcdefghjkmnpqrstuvwxyz");
+}
+
+#[test]
+fn check_char_only_depends_on_the_body() {
+    let cfg = checksum_config("0123456789abcdef");
+    // 手算：主体 "12"，从右往左 2 翻倍得 4，加上 1 得 5，校验下标 (16 - 5) % 16 = 11
+    assert_eq!(cfg.full_code("12".to_string()), "12b");
+    assert!(validate_code(&cfg, "12b").is_ok());
+    assert!(validate_code(&cfg, "12a").is_err());
+}
@@ -0,0 +1,160 @@
+//! 响应压缩和请求体解压：结果用 flate2 当参考实现来压 / 解
+
+use std::io::{Read, Write};
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use flate2::{Compression, read::MultiGzDecoder, write::GzEncoder};
+
+use super::{API_KEY, TestResponse, app, encode, get, send};
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    MultiGzDecoder::new(data).read_to_end(&mut out).unwrap();
+    out
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+async fn get_with(app: &Router, uri: &str, accept_encoding: &str) -> TestResponse {
+    let req = Request::get(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {API_KEY}"))
+        .header(header::ACCEPT_ENCODING, accept_encoding)
+        .body(Body::empty())
+        .unwrap();
+    send(app, req).await
+}
+
+async fn post_encoded(app: &Router, uri: &str, encoding: &str, body: Vec<u8>) -> TestResponse {
+    let req = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_ENCODING, encoding)
+        .body(Body::from(body))
+        .unwrap();
+    send(app, req).await
+}
+
+#[tokio::test]
+async fn large_response_is_gzipped() {
+    let (app, _) = app(&[]).await;
+    let plain = get(&app, "/openapi.json").await;
+    assert!(plain.body.len() > 1024);
+    assert!(!plain.headers.contains_key(header::CONTENT_ENCODING));
+    assert_eq!(plain.headers[header::VARY], "accept-encoding");
+
+    let resp = get_with(&app, "/openapi.json", "br;q=1, gzip;q=0.5").await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.headers[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(resp.headers[header::VARY], "accept-encoding");
+    assert!(resp.body.len() < plain.body.len());
+    assert_eq!(gunzip(&resp.body), plain.body);
+}
+
+#[tokio::test]
+async fn streamed_export_round_trips() {
+    let (app, _) = app(&[("API_KEYS", API_KEY)]).await;
+    for i in 0..200 {
+        encode(&app, &format!("https://example.com/export/{i}")).await;
+    }
+    let plain = get(&app, "/export").await;
+    let resp = get_with(&app, "/export", "gzip").await;
+    assert_eq!(resp.headers[header::CONTENT_ENCODING], "gzip");
+    assert_eq!(gunzip(&resp.body), plain.body);
+    assert_eq!(
+        String::from_utf8(plain.body.to_vec())
+            .unwrap()
+            .lines()
+            .count(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn gzipped_stream_in_and_out() {
+    let (app, _) = app(&[]).await;
+    let lines: String = (0..100)
+        .map(|i| format!("{{\"value\":\"https://example.com/stream/{i}\"}}\n"))
+        .collect();
+    let req = Request::post("/encode/stream")
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .header(header::CONTENT_ENCODING, "gzip")
+        .header(header::ACCEPT_ENCODING, "gzip")
+        .body(Body::from(gzip(lines.as_bytes())))
+        .unwrap();
+    let resp = send(&app, req).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.headers[header::CONTENT_ENCODING], "gzip");
+    let out = String::from_utf8(gunzip(&resp.body)).unwrap();
+    assert_eq!(out.lines().count(), 100);
+    assert!(out.lines().all(|line| line.contains("\"code\"")), "{out}");
+}
+
+#[tokio::test]
+async fn small_etag_and_refused_responses_stay_plain() {
+    let (app, _) = app(&[]).await;
+    let code = encode(&app, &format!("https://example.com/{}", "x".repeat(2000))).await;
+    let small = get_with(&app, &format!("/validate/{code}"), "gzip").await;
+    let etag = get_with(&app, &format!("/decode/{code}"), "gzip").await;
+    let refused = get_with(&app, "/openapi.json", "gzip;q=0, identity").await;
+    for resp in [small, etag, refused] {
+        assert_eq!(resp.status, StatusCode::OK);
+        assert!(
+            !resp.headers.contains_key(header::CONTENT_ENCODING),
+            "{:?}",
+            resp.headers
+        );
+    }
+}
+
+#[tokio::test]
+async fn compression_level_zero_disables_it() {
+    let (app, _) = app(&[("COMPRESSION_LEVEL", "0")]).await;
+    let resp = get_with(&app, "/openapi.json", "gzip").await;
+    assert!(!resp.headers.contains_key(header::CONTENT_ENCODING));
+}
+
+#[tokio::test]
+async fn gzipped_request_body_is_decoded() {
+    let (app, _) = app(&[]).await;
+    let json = br#"{"value":"https://example.com/gzipped"}"#;
+    for encoding in ["gzip", "X-Gzip", " GZIP "] {
+        let resp = post_encoded(&app, "/encode", encoding, gzip(json)).await;
+        assert_eq!(resp.status, StatusCode::OK, "{encoding}: {:?}", resp.body);
+        assert_eq!(resp.json()["code"], "01");
+    }
+    let resp = post_encoded(&app, "/encode", "identity", json.to_vec()).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["code"], "01");
+}
+
+#[tokio::test]
+async fn body_limit_applies_after_decompression() {
+    let (app, _) = app(&[("MAX_BODY_BYTES", "4096")]).await;
+    let json = serde_json::json!({ "value": "a".repeat(8192) }).to_string();
+    let compressed = gzip(json.as_bytes());
+    assert!(compressed.len() < 4096);
+    let resp = post_encoded(&app, "/encode", "gzip", compressed).await;
+    assert_eq!(resp.status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn unsupported_and_corrupt_bodies_are_rejected() {
+    let (app, _) = app(&[]).await;
+    let resp = post_encoded(&app, "/encode", "br", b"{}".to_vec()).await;
+    assert_eq!(resp.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(resp.json()["code"], "unsupported_encoding");
+    assert_eq!(resp.json()["error"], "unsupported content-encoding: br");
+
+    let mut corrupt = gzip(br#"{"value":"https://example.com/corrupt"}"#);
+    let mid = corrupt.len() / 2;
+    corrupt[mid] ^= 0xff;
+    let resp = post_encoded(&app, "/encode", "gzip", corrupt).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+}
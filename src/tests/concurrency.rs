@@ -0,0 +1,128 @@
+//! 并发 encode：文件库 + 多个连接，真正并行地写；以及 MAX_CONCURRENT_REQUESTS 的并发上限
+
+use axum::http::{StatusCode, header};
+use serde_json::json;
+use std::{sync::atomic::Ordering, time::Duration};
+
+use super::{app, app_with_db, get, post, remove_db, temp_path};
+
+const PARALLEL: usize = 32;
+
+/// 同时提交 PARALLEL 次，返回每次的 (code, created)
+async fn encode_in_parallel(
+    values: Vec<String>,
+) -> (Vec<(String, bool)>, crate::AppState, std::path::PathBuf) {
+    let path = temp_path("concurrency.db");
+    let url = format!("sqlite://{}", path.display());
+    let (app, state) = app_with_db(&url, &[("DB_MAX_CONNECTIONS", "8")]).await;
+    let tasks: Vec<_> = values
+        .into_iter()
+        .map(|value| {
+            let app = app.clone();
+            tokio::spawn(async move {
+                let resp = post(&app, "/encode", json!({ "value": value })).await;
+                assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+                let body = resp.json();
+                (
+                    body["code"].as_str().unwrap().to_string(),
+                    body["created"].as_bool().unwrap(),
+                )
+            })
+        })
+        .collect();
+    let mut results = Vec::new();
+    for task in tasks {
+        results.push(task.await.unwrap());
+    }
+    (results, state, path)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn same_value_in_parallel_gets_one_code_and_one_row() {
+    let value = "https://example.com/parallel";
+    let (results, state, path) = encode_in_parallel(vec![value.to_string(); PARALLEL]).await;
+
+    let code = &results[0].0;
+    assert!(results.iter().all(|(c, _)| c == code), "{results:?}");
+    assert_eq!(
+        results.iter().filter(|(_, created)| *created).count(),
+        1,
+        "{results:?}"
+    );
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mappings WHERE value = $1")
+        .bind(value)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+    state.pool.close().await;
+    remove_db(&path);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn distinct_values_in_parallel_get_distinct_codes() {
+    let values = (0..PARALLEL)
+        .map(|i| format!("https://example.com/parallel/{i}"))
+        .collect();
+    let (results, state, path) = encode_in_parallel(values).await;
+
+    let codes: std::collections::HashSet<_> = results.iter().map(|(code, _)| code).collect();
+    assert_eq!(codes.len(), PARALLEL);
+    assert!(results.iter().all(|(_, created)| *created));
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mappings")
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, PARALLEL as i64);
+    state.pool.close().await;
+    remove_db(&path);
+}
+
+#[tokio::test]
+async fn requests_over_the_limit_are_shed_with_503() {
+    let (app, state) = app(&[("MAX_CONCURRENT_REQUESTS", "1")]).await;
+    // 占住唯一的连接，第一个 encode 拿不到连接就一直占着名额
+    let held = state.pool.acquire().await.unwrap();
+    let first = tokio::spawn({
+        let app = app.clone();
+        async move {
+            post(
+                &app,
+                "/encode",
+                json!({ "value": "https://example.com/first" }),
+            )
+            .await
+        }
+    });
+    while state.metrics.requests_in_flight.load(Ordering::Relaxed) == 0 {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/second" }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(
+        resp.json(),
+        json!({ "error": "server is overloaded, please retry", "code": "overloaded" })
+    );
+    assert_eq!(resp.headers[header::RETRY_AFTER], "1");
+    assert_eq!(state.metrics.requests_overloaded.load(Ordering::Relaxed), 1);
+    assert_eq!(state.metrics.requests_in_flight.load(Ordering::Relaxed), 1);
+    // 探针不受限
+    assert_eq!(get(&app, "/healthz").await.status, StatusCode::OK);
+
+    drop(held);
+    assert_eq!(first.await.unwrap().status, StatusCode::OK);
+    assert_eq!(state.metrics.requests_in_flight.load(Ordering::Relaxed), 0);
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/second" }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK);
+}
@@ -0,0 +1,142 @@
+//! ALLOWED_ORIGINS：预检请求、允许和不允许的来源
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+
+use super::{API_KEY, TestResponse, app, encode, send};
+
+const ALLOWED: &str = "https://app.example.com";
+
+async fn preflight(app: &Router, uri: &str, origin: &str, method: &str) -> TestResponse {
+    let req = Request::builder()
+        .method(Method::OPTIONS)
+        .uri(uri)
+        .header(header::ORIGIN, origin)
+        .header(header::ACCESS_CONTROL_REQUEST_METHOD, method)
+        .header(
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            "content-type, idempotency-key",
+        )
+        .body(Body::empty())
+        .unwrap();
+    send(app, req).await
+}
+
+async fn get_from(app: &Router, uri: &str, origin: &str) -> TestResponse {
+    let req = Request::get(uri)
+        .header(header::ORIGIN, origin)
+        .body(Body::empty())
+        .unwrap();
+    send(app, req).await
+}
+
+fn header<'a>(resp: &'a TestResponse, name: header::HeaderName) -> Option<&'a str> {
+    resp.headers.get(name).map(|v| v.to_str().unwrap())
+}
+
+#[tokio::test]
+async fn preflight_from_an_allowed_origin_is_answered_without_auth() {
+    let vars = [
+        (
+            "ALLOWED_ORIGINS",
+            "https://app.example.com/, https://admin.example.com",
+        ),
+        ("API_KEYS", API_KEY),
+    ];
+    let (app, _) = app(&vars).await;
+    for (uri, method) in [("/encode", "POST"), ("/decode/abc", "GET")] {
+        let resp = preflight(&app, uri, ALLOWED, method).await;
+        assert_eq!(resp.status, StatusCode::OK, "{uri}");
+        assert_eq!(
+            header(&resp, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+            Some(ALLOWED)
+        );
+        let methods = header(&resp, header::ACCESS_CONTROL_ALLOW_METHODS).unwrap();
+        assert!(methods.contains(method), "{methods}");
+        let headers = header(&resp, header::ACCESS_CONTROL_ALLOW_HEADERS).unwrap();
+        assert!(
+            headers.contains("content-type") && headers.contains("idempotency-key"),
+            "{headers}"
+        );
+        assert_eq!(header(&resp, header::ACCESS_CONTROL_MAX_AGE), Some("600"));
+        assert!(header(&resp, header::VARY).unwrap().contains("origin"));
+    }
+}
+
+#[tokio::test]
+async fn allowed_origin_gets_cors_headers_on_normal_requests() {
+    let (app, _) = app(&[("ALLOWED_ORIGINS", ALLOWED)]).await;
+    let code = encode(&app, "https://example.com/cors").await;
+    let resp = get_from(&app, &format!("/decode/{code}"), ALLOWED).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(
+        header(&resp, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+        Some(ALLOWED)
+    );
+    let exposed = header(&resp, header::ACCESS_CONTROL_EXPOSE_HEADERS).unwrap();
+    assert!(
+        exposed.contains("etag") && exposed.contains("x-request-id"),
+        "{exposed}"
+    );
+}
+
+#[tokio::test]
+async fn disallowed_origin_gets_no_cors_headers() {
+    let (app, _) = app(&[("ALLOWED_ORIGINS", ALLOWED)]).await;
+    let code = encode(&app, "https://example.com/cors").await;
+    let evil = "https://evil.example.com";
+    // 决定浏览器放不放行的是 Access-Control-Allow-Origin：CorsLayer 仍会带上 Vary 和 Allow-Methods 这些固定的头
+    for resp in [
+        preflight(&app, "/encode", evil, "POST").await,
+        get_from(&app, &format!("/decode/{code}"), evil).await,
+    ] {
+        assert!(
+            resp.headers
+                .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+                .is_none(),
+            "{:?}",
+            resp.headers
+        );
+        assert!(
+            resp.headers
+                .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+                .is_none()
+        );
+    }
+    // 没有 Origin 的同源请求同样不带
+    let resp = send(
+        &app,
+        Request::get(format!("/decode/{code}"))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert!(
+        resp.headers
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none()
+    );
+}
+
+#[tokio::test]
+async fn wildcard_and_unset() {
+    let (any, _) = app(&[("ALLOWED_ORIGINS", "*")]).await;
+    let resp = preflight(&any, "/encode", "https://anywhere.example.com", "POST").await;
+    assert_eq!(
+        header(&resp, header::ACCESS_CONTROL_ALLOW_ORIGIN),
+        Some("*")
+    );
+
+    // 不设置时不挂 CORS，预检请求就是普通的 OPTIONS
+    let (app, _) = app(&[]).await;
+    let resp = preflight(&app, "/encode", ALLOWED, "POST").await;
+    assert!(
+        resp.headers
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none()
+    );
+    assert_eq!(resp.status, StatusCode::METHOD_NOT_ALLOWED);
+}
@@ -0,0 +1,66 @@
+//! is_unique_violation：SQLite / PostgreSQL 的唯一约束错误码，以及真实 SQLite 报错的判断
+
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+
+use super::app;
+use crate::{ApiError, is_unique_violation, is_unique_violation_code};
+
+#[test]
+fn unique_violation_codes() {
+    // SQLITE_CONSTRAINT_UNIQUE、SQLITE_CONSTRAINT_PRIMARYKEY、PostgreSQL unique_violation
+    for code in ["2067", "1555", "23505"] {
+        assert!(is_unique_violation_code(code), "{code}");
+    }
+    // SQLITE_CONSTRAINT_NOTNULL / FOREIGNKEY / CHECK、SQLITE_BUSY、PostgreSQL foreign_key_violation / not_null_violation
+    for code in [
+        "1299", "787", "275", "19", "5", "23503", "23502", "40001", "",
+    ] {
+        assert!(!is_unique_violation_code(code), "{code}");
+    }
+}
+
+#[tokio::test]
+async fn classifies_real_sqlite_errors() {
+    let (_, state) = app(&[]).await;
+    let insert =
+        "INSERT INTO mappings (namespace, code, value, created_at) VALUES ('', 'dup', $1, 0)";
+    sqlx::query(insert)
+        .bind("first")
+        .execute(&state.pool)
+        .await
+        .unwrap();
+
+    // (namespace, code) 的唯一约束
+    let unique = sqlx::query(insert)
+        .bind("second")
+        .execute(&state.pool)
+        .await
+        .unwrap_err();
+    assert!(is_unique_violation(&unique), "{unique}");
+    assert_eq!(
+        ApiError::from(unique).into_response().status(),
+        StatusCode::CONFLICT
+    );
+
+    // 主键冲突
+    let primary = sqlx::query("INSERT INTO mappings (id, namespace, code, created_at) SELECT id, '', 'other', 0 FROM mappings")
+        .execute(&state.pool)
+        .await
+        .unwrap_err();
+    assert!(is_unique_violation(&primary), "{primary}");
+
+    // 其它数据库错误、非数据库错误都不算
+    let syntax = sqlx::query("INSERT INTO no_such_table VALUES (1)")
+        .execute(&state.pool)
+        .await
+        .unwrap_err();
+    assert!(!is_unique_violation(&syntax));
+    assert!(!is_unique_violation(&sqlx::Error::RowNotFound));
+    assert_eq!(
+        ApiError::from(sqlx::Error::RowNotFound)
+            .into_response()
+            .status(),
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
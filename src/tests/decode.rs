@@ -0,0 +1,126 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+use std::sync::atomic::Ordering;
+
+use super::*;
+
+async fn scalar(state: &AppState, sql: &str, code: &str) -> i64 {
+    sqlx::query_scalar(sql)
+        .bind(code)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn cache_hits_still_count_decodes_and_events() {
+    let (app, state) = app(&[("DECODE_LRU_CAPACITY", "16")]).await;
+    let code = encode(&app, "https://example.com/hot").await;
+
+    for _ in 0..3 {
+        let resp = get(&app, &format!("/decode/{code}")).await;
+        assert_eq!(resp.status, StatusCode::OK);
+        assert_eq!(resp.json()["value"], "https://example.com/hot");
+    }
+    // 第一次查库并回填，后两次命中缓存
+    assert_eq!(state.metrics.decode_cache_misses.load(Ordering::Relaxed), 1);
+    assert_eq!(state.metrics.decode_cache_hits.load(Ordering::Relaxed), 2);
+
+    state.hits.flush(&state.pool).await.unwrap();
+    let decode_count = scalar(
+        &state,
+        "SELECT decode_count FROM mappings WHERE code = $1",
+        &code,
+    )
+    .await;
+    let events = scalar(
+        &state,
+        "SELECT COUNT(*) FROM events WHERE action = 'decode' AND code = $1",
+        &code,
+    )
+    .await;
+    let hit_count = scalar(
+        &state,
+        "SELECT hit_count FROM mappings WHERE code = $1",
+        &code,
+    )
+    .await;
+    assert_eq!((decode_count, events, hit_count), (3, 3, 3));
+
+    // HEAD 命中缓存同样不计数
+    let resp = call(&app, Method::HEAD, &format!("/decode/{code}"), None).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    state.hits.flush(&state.pool).await.unwrap();
+    assert_eq!(
+        scalar(
+            &state,
+            "SELECT decode_count FROM mappings WHERE code = $1",
+            &code
+        )
+        .await,
+        3
+    );
+}
+
+#[tokio::test]
+async fn cache_is_invalidated_by_update_and_delete() {
+    let (app, _) = app(&[("DECODE_LRU_CAPACITY", "16"), ("API_KEYS", API_KEY)]).await;
+    let code = encode(&app, "https://example.com/old").await;
+    let uri = format!("/decode/{code}");
+    assert_eq!(
+        get(&app, &uri).await.json()["value"],
+        "https://example.com/old"
+    );
+
+    let body = json!({ "value": "https://example.com/new" });
+    let resp = call(
+        &app,
+        Method::PATCH,
+        &format!("/mappings/{code}"),
+        Some(body),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::NO_CONTENT, "{:?}", resp.body);
+    assert_eq!(
+        get(&app, &uri).await.json()["value"],
+        "https://example.com/new"
+    );
+
+    let resp = call(&app, Method::DELETE, &format!("/mappings/{code}"), None).await;
+    assert_eq!(resp.status, StatusCode::NO_CONTENT, "{:?}", resp.body);
+    assert_eq!(get(&app, &uri).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn reordered_charset_round_trips() {
+    let order = "abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let (app, _) = app(&[("CODE_CHARSET_ORDER", order)]).await;
+    let values: Vec<String> = (0..100)
+        .map(|i| format!("https://example.com/order/{i}"))
+        .collect();
+    let mut codes = Vec::new();
+    for value in &values {
+        codes.push(encode(&app, value).await);
+    }
+    // 补位和第一个编号都用重排后的字符：默认字符集下是 "01"
+    assert_eq!(codes[0], "ab");
+    for (value, code) in values.iter().zip(&codes) {
+        let resp = get(&app, &format!("/decode/{code}")).await;
+        assert_eq!(resp.status, StatusCode::OK, "{code}");
+        assert_eq!(resp.json()["value"], *value);
+    }
+    assert_eq!(get(&app, "/decode/01").await.status, StatusCode::NOT_FOUND);
+
+    // 自定义短码仍按字符集校验，和顺序无关
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/order/custom", "custom_code": "Z9a" }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+    assert_eq!(
+        get(&app, "/decode/Z9a").await.json()["value"],
+        "https://example.com/order/custom"
+    );
+}
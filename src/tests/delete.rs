@@ -0,0 +1,141 @@
+//! DELETE /mappings/{code}：硬删除释放 value，SOFT_DELETE 下 decode 返回 410、重新 encode 拿回原来的 code
+
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use super::{app, app_with_db, call, encode, get, post, remove_db, temp_path};
+
+const VALUE: &str = "https://example.com/deleted";
+
+async fn delete(app: &axum::Router, code: &str) {
+    let resp = call(app, Method::DELETE, &format!("/mappings/{code}"), None).await;
+    assert_eq!(resp.status, StatusCode::NO_CONTENT, "{:?}", resp.body);
+}
+
+#[tokio::test]
+async fn hard_delete_then_404_then_reencode_mints_a_new_code() {
+    let (app, _) = app(&[]).await;
+    let code = encode(&app, VALUE).await;
+    encode(&app, "https://example.com/other").await;
+    delete(&app, &code).await;
+
+    let resp = post(&app, "/decode", json!({ "code": code })).await;
+    assert_eq!(resp.status, StatusCode::NOT_FOUND);
+
+    let resp = post(&app, "/encode", json!({ "value": VALUE })).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_ne!(resp.json()["code"], code);
+    assert_eq!(resp.json()["created"], true);
+    assert_eq!(
+        post(&app, "/decode", json!({ "code": code })).await.status,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn soft_delete_then_410_then_reencode_restores_the_code() {
+    let (app, _) = app(&[("SOFT_DELETE", "1")]).await;
+    let code = encode(&app, VALUE).await;
+    encode(&app, "https://example.com/other").await;
+    delete(&app, &code).await;
+
+    let resp = post(&app, "/decode", json!({ "code": code })).await;
+    assert_eq!(resp.status, StatusCode::GONE);
+    assert_eq!(resp.json()["code"], "gone");
+    for uri in [
+        format!("/decode/{code}"),
+        format!("/stats/{code}"),
+        format!("/qr/{code}"),
+    ] {
+        assert_eq!(get(&app, &uri).await.status, StatusCode::GONE, "{uri}");
+    }
+
+    // 重新 encode：恢复原来的 code，而不是分配一个新的
+    let resp = post(&app, "/encode", json!({ "value": VALUE })).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["code"], code);
+    assert_eq!(resp.json()["created"], false);
+    let resp = post(&app, "/decode", json!({ "code": code })).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["value"], VALUE);
+}
+
+#[tokio::test]
+async fn fail_if_exists_restores_a_soft_deleted_value() {
+    let (app, _) = app(&[("SOFT_DELETE", "1")]).await;
+    let code = encode(&app, VALUE).await;
+    delete(&app, &code).await;
+
+    let resp = post(
+        &app,
+        "/encode?fail_if_exists=true",
+        json!({ "value": VALUE }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+    assert_eq!(resp.json()["code"], code);
+    assert_eq!(resp.json()["created"], false);
+
+    // 恢复之后就是已存在的映射了
+    let resp = post(
+        &app,
+        "/encode?fail_if_exists=true",
+        json!({ "value": VALUE }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::CONFLICT);
+    assert_eq!(resp.json()["code"], "already_exists");
+    assert_eq!(resp.json()["existing_code"], code);
+}
+
+#[tokio::test]
+async fn fail_if_exists_restores_a_soft_deleted_custom_code() {
+    let (app, _) = app(&[("SOFT_DELETE", "1")]).await;
+    let body = json!({ "value": VALUE, "custom_code": "mine" });
+    assert_eq!(
+        post(&app, "/encode", body.clone()).await.json()["code"],
+        "mine"
+    );
+    delete(&app, "mine").await;
+
+    let resp = post(&app, "/encode?fail_if_exists=true", body.clone()).await;
+    assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+    assert_eq!(resp.json()["code"], "mine");
+    let resp = post(&app, "/encode?fail_if_exists=true", body).await;
+    assert_eq!(resp.status, StatusCode::CONFLICT);
+    assert_eq!(resp.json()["existing_code"], "mine");
+}
+
+/// 文件库多个连接：并发的删除和写入撞上 SQLITE_BUSY 时重试，而不是 500
+#[tokio::test]
+async fn concurrent_deletes_and_writes_all_succeed() {
+    let path = temp_path("delete.db");
+    let (app, state) = app_with_db(
+        &format!("sqlite://{}", path.display()),
+        &[("DB_MAX_CONNECTIONS", "8")],
+    )
+    .await;
+    let mut codes = Vec::new();
+    for i in 0..16 {
+        codes.push(encode(&app, &format!("https://example.com/delete/{i}")).await);
+    }
+    let mut tasks = Vec::new();
+    for (i, code) in codes.into_iter().enumerate() {
+        let app = app.clone();
+        tasks.push(tokio::spawn(async move {
+            delete(&app, &code).await;
+            encode(&app, &format!("https://example.com/kept/{i}")).await;
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mappings")
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, 16);
+    state.pool.close().await;
+    remove_db(&path);
+}
@@ -0,0 +1,242 @@
+//! POST /encode 的请求格式和响应字段
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use serde_json::json;
+
+use super::{TestResponse, app, post, send};
+use crate::{assign_code, audit::Audit, namespace, value::Value};
+
+async fn post_form(app: &Router, uri: &str, fields: &[(&str, &str)]) -> TestResponse {
+    let body = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(fields)
+        .finish();
+    let req = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+        .body(Body::from(body))
+        .unwrap();
+    send(app, req).await
+}
+
+#[tokio::test]
+async fn form_and_json_bodies_give_the_same_code() {
+    let (app, _) = app(&[]).await;
+    // 需要百分号编码的字符：空格、&、=、+、中文
+    let value = "https://example.com/a b?x=1&y=2+3#中文";
+
+    let json_resp = post(&app, "/encode", json!({ "value": value })).await;
+    assert_eq!(json_resp.status, StatusCode::OK);
+    let form_resp = post_form(&app, "/encode", &[("value", value)]).await;
+    assert_eq!(form_resp.status, StatusCode::OK, "{:?}", form_resp.body);
+    assert_eq!(form_resp.json()["code"], json_resp.json()["code"]);
+    assert_eq!(form_resp.json()["created"], false);
+
+    // 反过来：先用表单，再用 JSON
+    let form_resp = post_form(
+        &app,
+        "/encode",
+        &[("value", "https://example.com/form-first")],
+    )
+    .await;
+    assert_eq!(form_resp.json()["created"], true);
+    let json_resp = post(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/form-first" }),
+    )
+    .await;
+    assert_eq!(json_resp.json()["code"], form_resp.json()["code"]);
+}
+
+#[tokio::test]
+async fn form_accepts_the_same_optional_fields() {
+    let (app, _) = app(&[]).await;
+    let fields = [
+        ("value", "https://example.com/vani"),
+        ("custom_code", "vani"),
+        ("namespace", "team"),
+    ];
+    let form_resp = post_form(&app, "/encode", &fields).await;
+    assert_eq!(form_resp.status, StatusCode::OK, "{:?}", form_resp.body);
+    assert_eq!(form_resp.json()["code"], "vani");
+
+    let body =
+        json!({ "value": "https://example.com/vani", "custom_code": "vani", "namespace": "team" });
+    let json_resp = post(&app, "/encode", body).await;
+    assert_eq!(json_resp.json()["code"], "vani");
+    assert_eq!(json_resp.json()["created"], false);
+}
+
+#[tokio::test]
+async fn created_is_true_only_for_the_call_that_inserts() {
+    let (app, _) = app(&[]).await;
+    let value = json!({ "value": "https://example.com/created" });
+    let resp = post(&app, "/encode", value.clone()).await;
+    assert_eq!(resp.json()["created"], true);
+    let code = resp.json()["code"].clone();
+    // 快路径：已有的映射
+    let resp = post(&app, "/encode", value).await;
+    assert_eq!(
+        (resp.json()["code"].clone(), resp.json()["created"].clone()),
+        (code, json!(false))
+    );
+    // 同一个 value 在另一个命名空间里是新映射
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/created", "namespace": "team" }),
+    )
+    .await;
+    assert_eq!(resp.json()["created"], true);
+
+    let custom = json!({ "value": "https://example.com/custom", "custom_code": "cust" });
+    assert_eq!(
+        post(&app, "/encode", custom.clone()).await.json()["created"],
+        true
+    );
+    assert_eq!(post(&app, "/encode", custom).await.json()["created"], false);
+}
+
+#[tokio::test]
+async fn idempotent_replay_is_not_created() {
+    let (app, _) = app(&[]).await;
+    let request = || {
+        Request::post("/encode")
+            .header(header::CONTENT_TYPE, "application/json")
+            .header("idempotency-key", "created-flag")
+            .body(Body::from(
+                json!({ "value": "https://example.com/idem" }).to_string(),
+            ))
+            .unwrap()
+    };
+    let first = send(&app, request()).await;
+    assert_eq!(first.json()["created"], true);
+    let replay = send(&app, request()).await;
+    assert_eq!(replay.json()["code"], first.json()["code"]);
+    assert_eq!(replay.json()["created"], false);
+}
+
+/// 并发时快路径没查到、事务里才发现别人已经插入：插入路径也不能报 created
+#[tokio::test]
+async fn insert_path_does_not_claim_an_existing_row() {
+    let (app, state) = app(&[]).await;
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/raced" }),
+    )
+    .await;
+    assert_eq!(resp.json()["created"], true);
+
+    let value = Value::Text("https://example.com/raced".to_string());
+    let mut tx = state.pool.begin().await.unwrap();
+    let (code, created) = assign_code(
+        &mut tx,
+        &state.code,
+        &*state.generator,
+        namespace::DEFAULT,
+        &value,
+        None,
+        Audit::DISABLED,
+    )
+    .await
+    .unwrap();
+    tx.commit().await.unwrap();
+    assert_eq!(code, resp.json()["code"].as_str().unwrap());
+    assert!(!created);
+}
+
+#[tokio::test]
+async fn invalid_form_body_is_a_json_400() {
+    let (app, _) = app(&[]).await;
+    let resp = post_form(
+        &app,
+        "/encode",
+        &[
+            ("value", "https://example.com/ttl"),
+            ("ttl_seconds", "soon"),
+        ],
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.json()["code"], "bad_request");
+    assert!(
+        resp.json()["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid form body"),
+        "{:?}",
+        resp.body
+    );
+}
+
+#[tokio::test]
+async fn fail_if_exists_returns_409_with_the_existing_code() {
+    let (app, state) = app(&[]).await;
+    let value = "https://example.com/create-only";
+    let resp = post(
+        &app,
+        "/encode?fail_if_exists=true",
+        json!({ "value": value }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["created"], true);
+    let code = resp.json()["code"].clone();
+
+    let resp = post(
+        &app,
+        "/encode?fail_if_exists=true",
+        json!({ "value": value }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::CONFLICT);
+    assert_eq!(
+        resp.json(),
+        json!({ "error": "value already exists", "code": "already_exists", "existing_code": code })
+    );
+
+    // 不带参数时照旧幂等地返回已有的 code，也没有多出行来
+    let resp = post(&app, "/encode", json!({ "value": value })).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["code"], code);
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mappings")
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, 1);
+}
+
+#[tokio::test]
+async fn fail_if_exists_with_the_same_custom_code_is_409() {
+    let (app, _) = app(&[]).await;
+    let body = json!({ "value": "https://example.com/mine", "custom_code": "mine" });
+    assert_eq!(
+        post(&app, "/encode?fail_if_exists=true", body.clone())
+            .await
+            .status,
+        StatusCode::OK
+    );
+    let resp = post(&app, "/encode?fail_if_exists=true", body.clone()).await;
+    assert_eq!(resp.status, StatusCode::CONFLICT);
+    assert_eq!(resp.json()["code"], "already_exists");
+    assert_eq!(resp.json()["existing_code"], "mine");
+    // 不带参数时同一对 (value, code) 是幂等的
+    assert_eq!(post(&app, "/encode", body).await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn fail_if_exists_false_is_the_default() {
+    let (app, _) = app(&[]).await;
+    let value = json!({ "value": "https://example.com/default" });
+    assert_eq!(
+        post(&app, "/encode", value.clone()).await.status,
+        StatusCode::OK
+    );
+    let resp = post(&app, "/encode?fail_if_exists=false", value).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["created"], false);
+}
@@ -0,0 +1,119 @@
+//! ErrorResponse.code 是给客户端分支用的稳定错误码：
+//! 每个 ApiError 变体的 code / 状态码，以及 README 和 openapi.json 里的列表
+
+use axum::{http::StatusCode, response::IntoResponse};
+
+use crate::ApiError;
+
+/// 每个变体期望的 (状态码, code)。没有通配分支：新增变体时这里编译不过，
+/// 必须同时决定它的 code
+fn expected(e: &ApiError) -> (StatusCode, &'static str) {
+    match e {
+        ApiError::BadRequest(_) => (StatusCode::BAD_REQUEST, "bad_request"),
+        ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized"),
+        ApiError::NotFound => (StatusCode::NOT_FOUND, "not_found"),
+        ApiError::Gone => (StatusCode::GONE, "gone"),
+        ApiError::Reserved => (StatusCode::CONFLICT, "reserved"),
+        ApiError::Conflict(_) => (StatusCode::CONFLICT, "conflict"),
+        ApiError::AlreadyExists(_) => (StatusCode::CONFLICT, "already_exists"),
+        ApiError::RateLimited(_) => (StatusCode::TOO_MANY_REQUESTS, "rate_limited"),
+        ApiError::NotReady => (StatusCode::SERVICE_UNAVAILABLE, "not_ready"),
+        ApiError::Overloaded => (StatusCode::SERVICE_UNAVAILABLE, "overloaded"),
+        ApiError::Timeout => (StatusCode::REQUEST_TIMEOUT, "timeout"),
+        ApiError::UnsupportedEncoding(_) => {
+            (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported_encoding")
+        }
+        ApiError::UnsupportedContentType(_) => (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_content_type",
+        ),
+        ApiError::PayloadTooLarge | ApiError::ImportFileTooLarge(_) => {
+            (StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large")
+        }
+        ApiError::Exhausted { .. } => (StatusCode::INSUFFICIENT_STORAGE, "exhausted"),
+        ApiError::RandomCodeCollision(_) => {
+            (StatusCode::INSUFFICIENT_STORAGE, "random_code_collision")
+        }
+        ApiError::Sqlx(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal"),
+    }
+}
+
+fn all_variants() -> Vec<ApiError> {
+    vec![
+        ApiError::BadRequest("bad".to_string()),
+        ApiError::Unauthorized,
+        ApiError::NotFound,
+        ApiError::Gone,
+        ApiError::Reserved,
+        ApiError::Conflict("conflict".to_string()),
+        ApiError::AlreadyExists("01".to_string()),
+        ApiError::RateLimited(1),
+        ApiError::NotReady,
+        ApiError::Overloaded,
+        ApiError::Timeout,
+        ApiError::UnsupportedEncoding("br".to_string()),
+        ApiError::UnsupportedContentType("application/json"),
+        ApiError::PayloadTooLarge,
+        ApiError::ImportFileTooLarge("too large".to_string()),
+        ApiError::Exhausted {
+            max_len: 5,
+            max_capacity: 916_132_831,
+        },
+        ApiError::RandomCodeCollision(8),
+        ApiError::Sqlx(sqlx::Error::RowNotFound),
+    ]
+}
+
+/// 文档里列出的全部 code（去重、保持顺序）
+fn documented_codes() -> Vec<&'static str> {
+    let mut codes: Vec<&str> = Vec::new();
+    for e in all_variants() {
+        let code = expected(&e).1;
+        if !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+    codes
+}
+
+#[tokio::test]
+async fn every_variant_has_its_stable_code_and_status() {
+    for e in all_variants() {
+        let (status, code) = expected(&e);
+        assert_eq!(e.code(), code);
+        let resp = e.into_response();
+        assert_eq!(resp.status(), status, "{code}");
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], code);
+        assert!(
+            body["error"].as_str().is_some_and(|m| !m.is_empty()),
+            "{code}: {body}"
+        );
+    }
+}
+
+#[test]
+fn openapi_lists_exactly_these_codes() {
+    let spec: serde_json::Value = serde_json::from_str(include_str!("../openapi.json")).unwrap();
+    let listed: Vec<&str> =
+        spec["components"]["schemas"]["ErrorResponse"]["properties"]["code"]["enum"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap())
+            .collect();
+    assert_eq!(listed, documented_codes());
+}
+
+#[test]
+fn readme_table_lists_every_code_with_its_status() {
+    let readme = include_str!("../../README.md");
+    for e in all_variants() {
+        let (status, code) = expected(&e);
+        let row = format!("| `{code}` | `{}` |", status.as_u16());
+        assert!(readme.contains(&row), "README error table is missing {row}");
+    }
+}
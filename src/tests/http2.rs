@@ -0,0 +1,167 @@
+use axum::body::Body;
+use hyper::{Request, Response, StatusCode, Version, body::Incoming, header};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::pki_types::{CertificateDer, ServerName};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::*;
+use crate::{serve, tls};
+
+fn encode_request(addr: SocketAddr) -> Request<Body> {
+    Request::post(format!("http://{addr}/encode"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"value":"https://example.com/h2"}"#))
+        .unwrap()
+}
+
+/// 返回的 encode 结果里的 code
+async fn encoded_code(resp: Response<Incoming>) -> String {
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(Body::new(resp.into_body()), usize::MAX)
+        .await
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    body["code"].as_str().unwrap().to_string()
+}
+
+/// 在随机端口上起服务（明文或 TLS），返回地址
+async fn spawn_server(http2: bool, tls: Option<Arc<rustls::ServerConfig>>) -> SocketAddr {
+    let (app, _) = app(&[]).await;
+    let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = tcp.local_addr().unwrap();
+    let shutdown = std::future::pending();
+    match tls {
+        Some(config) => {
+            let listener = tls::TlsListener::new(tcp, config);
+            tokio::spawn(serve::serve(
+                listener,
+                app,
+                http2,
+                |addr| Some(*addr),
+                shutdown,
+            ));
+        }
+        None => {
+            tokio::spawn(serve::serve(tcp, app, http2, |addr| Some(*addr), shutdown));
+        }
+    }
+    addr
+}
+
+async fn h2_encode<T>(io: T, addr: SocketAddr) -> hyper::Result<Response<Incoming>>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) =
+        hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(io)).await?;
+    tokio::spawn(conn);
+    sender.send_request(encode_request(addr)).await
+}
+
+async fn h1_encode<T>(io: T, addr: SocketAddr) -> hyper::Result<Response<Incoming>>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, conn) = hyper::client::conn::http1::handshake(TokioIo::new(io)).await?;
+    tokio::spawn(conn);
+    sender.send_request(encode_request(addr)).await
+}
+
+#[tokio::test]
+async fn h2c_only_when_enabled() {
+    let addr = spawn_server(true, None).await;
+    let resp = h2_encode(TcpStream::connect(addr).await.unwrap(), addr)
+        .await
+        .unwrap();
+    assert_eq!(resp.version(), Version::HTTP_2);
+    assert_eq!(encoded_code(resp).await, "01");
+    // 同一个端口仍然接受 HTTP/1.1
+    let resp = h1_encode(TcpStream::connect(addr).await.unwrap(), addr)
+        .await
+        .unwrap();
+    assert_eq!(resp.version(), Version::HTTP_11);
+    assert_eq!(encoded_code(resp).await, "01");
+
+    // 关闭时 h2c 的连接前言按 HTTP/1.1 解析不了，连接直接断开
+    let addr = spawn_server(false, None).await;
+    assert!(
+        h2_encode(TcpStream::connect(addr).await.unwrap(), addr)
+            .await
+            .is_err()
+    );
+    let resp = h1_encode(TcpStream::connect(addr).await.unwrap(), addr)
+        .await
+        .unwrap();
+    assert_eq!(encoded_code(resp).await, "01");
+}
+
+/// 自签名的 localhost 证书：服务端配置用 tls::load_config 从 PEM 文件读，客户端只信任这一张
+fn self_signed(http2: bool) -> (Arc<rustls::ServerConfig>, CertificateDer<'static>) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let (cert_path, key_path) = (temp_path("cert.pem"), temp_path("key.pem"));
+    std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+    std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+    let config = tls::load_config(
+        cert_path.to_str().unwrap(),
+        key_path.to_str().unwrap(),
+        http2,
+    )
+    .unwrap();
+    let _ = (
+        std::fs::remove_file(cert_path),
+        std::fs::remove_file(key_path),
+    );
+    (config, cert.cert.der().clone())
+}
+
+/// 客户端 ALPN 同时提供 h2 和 http/1.1，返回握手完成的连接和协商出的协议
+async fn tls_connect(
+    addr: SocketAddr,
+    cert: CertificateDer<'static>,
+) -> (impl AsyncRead + AsyncWrite + Unpin + Send, Vec<u8>) {
+    let mut roots = rustls::RootCertStore::empty();
+    roots.add(cert).unwrap();
+    let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .unwrap()
+    .with_root_certificates(roots)
+    .with_no_client_auth();
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let tcp = TcpStream::connect(addr).await.unwrap();
+    let name = ServerName::try_from("localhost").unwrap();
+    let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(name, tcp)
+        .await
+        .unwrap();
+    let alpn = stream
+        .get_ref()
+        .1
+        .alpn_protocol()
+        .unwrap_or_default()
+        .to_vec();
+    (stream, alpn)
+}
+
+#[tokio::test]
+async fn tls_negotiates_h2_via_alpn() {
+    let (config, cert) = self_signed(true);
+    let addr = spawn_server(true, Some(config)).await;
+    let (stream, alpn) = tls_connect(addr, cert).await;
+    assert_eq!(alpn, b"h2");
+    let resp = h2_encode(stream, addr).await.unwrap();
+    assert_eq!(resp.version(), Version::HTTP_2);
+    assert_eq!(encoded_code(resp).await, "01");
+
+    // 未开启时 ALPN 回落到 http/1.1
+    let (config, cert) = self_signed(false);
+    let addr = spawn_server(false, Some(config)).await;
+    let (stream, alpn) = tls_connect(addr, cert).await;
+    assert_eq!(alpn, b"http/1.1");
+    let resp = h1_encode(stream, addr).await.unwrap();
+    assert_eq!(resp.version(), Version::HTTP_11);
+    assert_eq!(encoded_code(resp).await, "01");
+}
@@ -0,0 +1,109 @@
+//! POST /import 的 IMPORT_MAX_FILE_BYTES / IMPORT_MAX_LINES：NDJSON 请求体（包括 gzip）和 multipart 文件都受限制
+
+use std::io::Write;
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+use flate2::{Compression, write::GzEncoder};
+
+use super::{API_KEY, TestResponse, app, send};
+
+fn ndjson(lines: usize) -> String {
+    (0..lines)
+        .map(|i| format!("{{\"value\":\"https://example.com/{i}\",\"code\":\"i{i}\"}}\n"))
+        .collect()
+}
+
+async fn import(
+    app: &Router,
+    content_type: &str,
+    encoding: Option<&str>,
+    body: Vec<u8>,
+) -> TestResponse {
+    let mut req = Request::post("/import")
+        .header(header::AUTHORIZATION, format!("Bearer {API_KEY}"))
+        .header(header::CONTENT_TYPE, content_type);
+    if let Some(encoding) = encoding {
+        req = req.header(header::CONTENT_ENCODING, encoding);
+    }
+    send(app, req.body(Body::from(body)).unwrap()).await
+}
+
+async fn import_limited() -> Router {
+    app(&[
+        ("API_KEYS", API_KEY),
+        ("IMPORT_MAX_LINES", "10"),
+        ("IMPORT_MAX_FILE_BYTES", "2048"),
+    ])
+    .await
+    .0
+}
+
+#[tokio::test]
+async fn ndjson_body_within_limits_is_imported() {
+    let app = import_limited().await;
+    let resp = import(&app, "application/x-ndjson", None, ndjson(10).into_bytes()).await;
+    assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+    assert_eq!(resp.json()["inserted"], 10);
+}
+
+#[tokio::test]
+async fn ndjson_body_over_max_lines_is_413() {
+    let app = import_limited().await;
+    let resp = import(&app, "application/x-ndjson", None, ndjson(11).into_bytes()).await;
+    assert_eq!(resp.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(resp.json()["code"], "payload_too_large");
+    assert_eq!(
+        resp.json()["error"],
+        "import file has too many lines (max 10)"
+    );
+}
+
+#[tokio::test]
+async fn ndjson_body_over_max_bytes_is_413() {
+    let app = import_limited().await;
+    let line = format!(
+        "{{\"value\":\"https://example.com/{}\",\"code\":\"big\"}}\n",
+        "x".repeat(4096)
+    );
+    let resp = import(&app, "application/x-ndjson", None, line.into_bytes()).await;
+    assert_eq!(resp.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        resp.json()["error"],
+        "import file too large (max 2048 bytes)"
+    );
+}
+
+#[tokio::test]
+async fn gzip_body_is_limited_by_decompressed_size() {
+    let app = import_limited().await;
+    // 1MB 的空白压缩后只有 1KB 左右
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&vec![b' '; 1 << 20]).unwrap();
+    let bomb = encoder.finish().unwrap();
+    assert!(bomb.len() < 2048);
+    let resp = import(&app, "application/x-ndjson", Some("gzip"), bomb).await;
+    assert_eq!(resp.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(resp.json()["code"], "payload_too_large");
+}
+
+#[tokio::test]
+async fn multipart_file_over_max_lines_is_413() {
+    let app = import_limited().await;
+    let boundary = "import-test-boundary";
+    let body = format!(
+        "--{boundary}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"backup.ndjson\"\r\n\
+         Content-Type: application/x-ndjson\r\n\r\n{}\r\n--{boundary}--\r\n",
+        ndjson(11)
+    );
+    let content_type = format!("multipart/form-data; boundary={boundary}");
+    let resp = import(&app, &content_type, None, body.into_bytes()).await;
+    assert_eq!(resp.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        resp.json()["error"],
+        "import file has too many lines (max 10)"
+    );
+}
@@ -0,0 +1,109 @@
+//! 请求体 JSON 解析失败时的错误响应：和其它错误一样的 ErrorResponse，而不是 axum 默认的纯文本
+
+use axum::{
+    Router,
+    body::Body,
+    http::{Request, StatusCode, header},
+};
+
+use super::{TestResponse, app, send};
+
+/// 一律带 `Accept: text/plain`：请求体解析失败不做内容协商，错误总是 JSON
+async fn post_raw(app: &Router, uri: &str, content_type: Option<&str>, body: &str) -> TestResponse {
+    let mut req = Request::post(uri).header(header::ACCEPT, "text/plain");
+    if let Some(content_type) = content_type {
+        req = req.header(header::CONTENT_TYPE, content_type);
+    }
+    send(app, req.body(Body::from(body.to_string())).unwrap()).await
+}
+
+/// 400 bad_request，error 以 prefix 开头；返回 error 方便再检查细节
+fn assert_bad_request(resp: &TestResponse, prefix: &str) -> String {
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST, "{:?}", resp.body);
+    assert_eq!(resp.headers[header::CONTENT_TYPE], "application/json");
+    let body = resp.json();
+    assert_eq!(body["code"], "bad_request");
+    let error = body["error"].as_str().unwrap().to_string();
+    assert!(error.starts_with(prefix), "{error}");
+    error
+}
+
+#[tokio::test]
+async fn syntax_errors_are_malformed() {
+    let (app, _) = app(&[]).await;
+    for body in ["{\"value\":", "not json", "{\"value\": \"a\",}", ""] {
+        let resp = post_raw(&app, "/encode", Some("application/json"), body).await;
+        assert_bad_request(&resp, "malformed JSON body: ");
+    }
+}
+
+#[tokio::test]
+async fn wrong_types_name_the_field() {
+    let (app, _) = app(&[]).await;
+    let resp = post_raw(&app, "/encode", Some("application/json"), r#"{"value": 1}"#).await;
+    let error = assert_bad_request(&resp, "invalid JSON body: ");
+    assert!(
+        error.contains("value: invalid type: integer `1`"),
+        "{error}"
+    );
+
+    let resp = post_raw(
+        &app,
+        "/encode",
+        Some("application/json"),
+        r#"{"value": "a", "ttl_seconds": -1}"#,
+    )
+    .await;
+    let error = assert_bad_request(&resp, "invalid JSON body: ");
+    assert!(error.contains("ttl_seconds"), "{error}");
+
+    let resp = post_raw(
+        &app,
+        "/encode/batch",
+        Some("application/json"),
+        r#"{"values": "a"}"#,
+    )
+    .await;
+    assert_bad_request(&resp, "invalid JSON body: values: ");
+}
+
+#[tokio::test]
+async fn missing_fields_are_invalid() {
+    let (app, _) = app(&[]).await;
+    let resp = post_raw(&app, "/value/lookup", Some("application/json"), "{}").await;
+    let error = assert_bad_request(&resp, "invalid JSON body: ");
+    assert!(error.contains("missing field `value`"), "{error}");
+
+    let resp = post_raw(
+        &app,
+        "/encode/batch",
+        Some("application/json"),
+        r#"{"namespace": "team"}"#,
+    )
+    .await;
+    let error = assert_bad_request(&resp, "invalid JSON body: ");
+    assert!(error.contains("missing field `values`"), "{error}");
+}
+
+#[tokio::test]
+async fn content_type_is_checked_before_parsing() {
+    let (app, _) = app(&[]).await;
+    for content_type in [None, Some("text/plain")] {
+        let resp = post_raw(&app, "/value/lookup", content_type, r#"{"value": "a"}"#).await;
+        assert_eq!(
+            resp.status,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "{content_type:?}"
+        );
+        assert_eq!(resp.json()["code"], "unsupported_content_type");
+    }
+    // 带 charset 参数的照常解析
+    let resp = post_raw(
+        &app,
+        "/value/lookup",
+        Some("application/json; charset=utf-8"),
+        r#"{"value": "a"}"#,
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::NOT_FOUND);
+}
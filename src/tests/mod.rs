@@ -0,0 +1,142 @@
+//! handler 级别的测试：内存 SQLite 上跑完迁移的完整 Router，用 `oneshot` 发请求，不监听端口
+
+mod body_limit;
+mod checksum;
+mod compression;
+mod concurrency;
+mod cors;
+mod db_errors;
+mod decode;
+mod delete;
+mod encode;
+mod errors;
+mod http2;
+mod import;
+mod json_body;
+mod qr;
+mod routes;
+mod timeout;
+mod value_len;
+mod value_pattern;
+
+use axum::{
+    Router,
+    body::{Body, Bytes},
+    http::{HeaderMap, Method, Request, StatusCode, header},
+};
+use std::{
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+use tower::ServiceExt;
+
+use crate::config::Config;
+use crate::{AppState, app_state, db, router};
+
+/// 测试里用的 API key（配置了 API_KEYS 的用例）
+pub const API_KEY: &str = "test-key";
+
+/// 内存库 + 默认配置、已经 ready 的 app。vars 覆盖默认值，不读运行测试时的环境变量。
+/// 内存库每个连接各是一个库，所以连接池只开一个连接
+pub async fn app(vars: &[(&str, &str)]) -> (Router, AppState) {
+    app_with_db("sqlite::memory:", vars).await
+}
+
+/// 同 app，但用给定的数据库（需要多个连接真正并发时用文件库）
+pub async fn app_with_db(db_url: &str, vars: &[(&str, &str)]) -> (Router, AppState) {
+    let is_memory = db_url == "sqlite::memory:";
+    let mut all = vars.to_vec();
+    all.push(("DATABASE_URL", db_url));
+    all.push(("SKIP_SELF_CHECK", "1"));
+    if is_memory {
+        all.extend([("DB_MAX_CONNECTIONS", "1"), ("DB_MIN_CONNECTIONS", "1")]);
+    }
+    let config = Config::from_vars(&all).expect("valid test config");
+    let pool = db::connect(&config.db_url, config.backend, &config.pool)
+        .await
+        .unwrap();
+    db::init_db(&pool, config.backend, config.value_hash_dedup)
+        .await
+        .unwrap();
+    let state = app_state(&config, pool, None);
+    state.ready.store(true, Ordering::Release);
+    (router(&config, state.clone()), state)
+}
+
+/// 临时目录下本次测试独占的文件路径（按进程号和序号区分，并行的用例互不干扰）
+pub fn temp_path(name: &str) -> PathBuf {
+    static SEQ: AtomicUsize = AtomicUsize::new(0);
+    let seq = SEQ.fetch_add(1, Ordering::Relaxed);
+    std::env::temp_dir().join(format!("bpb-test-{}-{seq}-{name}", std::process::id()))
+}
+
+/// 删掉 temp_path 建的 SQLite 文件库（连同 WAL 模式的 -wal / -shm 文件）
+pub fn remove_db(path: &std::path::Path) {
+    for suffix in ["", "-wal", "-shm"] {
+        let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+    }
+}
+
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl TestResponse {
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body)
+            .unwrap_or_else(|e| panic!("invalid json body ({e}): {:?}", self.body))
+    }
+}
+
+pub async fn send(app: &Router, req: Request<Body>) -> TestResponse {
+    let resp = app.clone().oneshot(req).await.unwrap();
+    let (parts, body) = resp.into_parts();
+    TestResponse {
+        status: parts.status,
+        headers: parts.headers,
+        body: axum::body::to_bytes(body, usize::MAX).await.unwrap(),
+    }
+}
+
+/// 带 JSON 请求体（或没有请求体）的请求。一律带上 API_KEY，没配置 API_KEYS 时这个头不起作用
+pub async fn call(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    body: Option<serde_json::Value>,
+) -> TestResponse {
+    let mut req = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {API_KEY}"));
+    let body = match body {
+        Some(body) => {
+            req = req.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    send(app, req.body(body).unwrap()).await
+}
+
+pub async fn get(app: &Router, uri: &str) -> TestResponse {
+    call(app, Method::GET, uri, None).await
+}
+
+pub async fn post(app: &Router, uri: &str, body: serde_json::Value) -> TestResponse {
+    call(app, Method::POST, uri, Some(body)).await
+}
+
+/// POST /encode，断言成功并返回 code
+pub async fn encode(app: &Router, value: &str) -> String {
+    let resp = post(app, "/encode", serde_json::json!({ "value": value })).await;
+    assert_eq!(
+        resp.status,
+        StatusCode::OK,
+        "encode failed: {:?}",
+        resp.body
+    );
+    resp.json()["code"].as_str().unwrap().to_string()
+}
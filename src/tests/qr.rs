@@ -0,0 +1,58 @@
+//! GET /qr/{code}：返回的 PNG 用独立的解码器读回，以及参数边界
+
+use axum::http::StatusCode;
+
+use super::{app, encode, get};
+use crate::qr::tests::decode;
+
+#[tokio::test]
+async fn png_decodes_back_to_the_code() {
+    let (app, _) = app(&[]).await;
+    let code = encode(&app, "https://example.com/qr").await;
+    for ecc in ["L", "M", "Q", "H"] {
+        let resp = get(&app, &format!("/qr/{code}?ecc={ecc}")).await;
+        assert_eq!(resp.status, StatusCode::OK, "{ecc}");
+        assert!(resp.body.starts_with(b"\x89PNG\r\n\x1a\n"));
+        assert_eq!(decode(&resp.body).0, code, "{ecc}");
+    }
+}
+
+#[tokio::test]
+async fn redirect_mode_encodes_the_short_url() {
+    let (app, _) = app(&[
+        ("REDIRECT_MODE", "1"),
+        ("BASE_URL", "https://s.example.com"),
+    ])
+    .await;
+    let code = encode(&app, "https://example.com/qr").await;
+    let resp = get(&app, &format!("/qr/{code}")).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(
+        decode(&resp.body).0,
+        format!("https://s.example.com/{code}")
+    );
+}
+
+#[tokio::test]
+async fn unknown_code_is_404() {
+    let (app, _) = app(&[]).await;
+    encode(&app, "https://example.com/qr").await;
+    assert_eq!(get(&app, "/qr/zz").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn scale_and_ecc_bounds() {
+    let (app, _) = app(&[]).await;
+    let code = encode(&app, "https://example.com/qr").await;
+    for query in ["scale=0", "scale=33", "scale=-1", "ecc=X", "ecc="] {
+        let resp = get(&app, &format!("/qr/{code}?{query}")).await;
+        assert_eq!(resp.status, StatusCode::BAD_REQUEST, "{query}");
+    }
+    for scale in [1, 32] {
+        let resp = get(&app, &format!("/qr/{code}?scale={scale}")).await;
+        assert_eq!(resp.status, StatusCode::OK, "scale={scale}");
+        let (_, version) = decode(&resp.body);
+        let width = image::load_from_memory(&resp.body).unwrap().width();
+        assert_eq!(width, (17 + 4 * version as u32 + 8) * scale);
+    }
+}
@@ -0,0 +1,63 @@
+use axum::http::{Method, StatusCode};
+use serde_json::json;
+
+use super::*;
+
+#[tokio::test]
+async fn disable_encode_drops_admin_write_routes() {
+    let (app, _) = app(&[("API_KEYS", API_KEY), ("DISABLE_ENCODE", "1")]).await;
+
+    assert_eq!(
+        post(&app, "/encode", json!({ "value": "a" })).await.status,
+        StatusCode::NOT_FOUND
+    );
+    for (method, uri, body) in [
+        (Method::POST, "/admin/rotate", Some(json!({ "code": "01" }))),
+        (Method::POST, "/reserve", Some(json!({ "code": "abc" }))),
+        (Method::POST, "/encode/alias", Some(json!({ "code": "01" }))),
+        (Method::PATCH, "/mappings/01", Some(json!({ "value": "b" }))),
+        (Method::DELETE, "/mappings/01", None),
+        (
+            Method::POST,
+            "/mappings/delete",
+            Some(json!({ "codes": ["01"] })),
+        ),
+        (Method::POST, "/import", Some(json!({}))),
+        (Method::POST, "/admin/vacuum", None),
+    ] {
+        let resp = call(&app, method.clone(), uri, body).await;
+        assert_eq!(resp.status, StatusCode::NOT_FOUND, "{method} {uri}");
+    }
+
+    // 只读的管理接口照常可用
+    assert_eq!(get(&app, "/mappings").await.status, StatusCode::OK);
+    assert_eq!(get(&app, "/count").await.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn disable_decode_drops_value_returning_reads() {
+    let (app, _) = app(&[("DISABLE_DECODE", "1")]).await;
+    let code = encode(&app, "https://example.com").await;
+
+    for uri in [
+        format!("/decode/{code}"),
+        format!("/stats/{code}"),
+        format!("/qr/{code}"),
+    ] {
+        assert_eq!(get(&app, &uri).await.status, StatusCode::NOT_FOUND, "{uri}");
+    }
+    for uri in ["/decode", "/decode/batch", "/stats/batch", "/value/lookup"] {
+        let resp = post(
+            &app,
+            uri,
+            json!({ "code": code, "codes": [code], "value": "https://example.com" }),
+        )
+        .await;
+        assert_eq!(resp.status, StatusCode::NOT_FOUND, "{uri}");
+    }
+
+    // validate 不返回 value，照常可用
+    let resp = get(&app, &format!("/validate/{code}")).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["valid"], true);
+}
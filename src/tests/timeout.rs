@@ -0,0 +1,85 @@
+//! REQUEST_TIMEOUT_SECS：处理太久的请求返回 408，丢弃 handler 时未提交的事务回滚
+
+use std::time::Duration;
+
+use axum::{
+    Router,
+    body::Body,
+    extract::State,
+    http::{Request, StatusCode},
+    middleware,
+    routing::post,
+};
+use serde_json::json;
+use tower_http::timeout::TimeoutLayer;
+
+use super::{app, post as post_json, send};
+use crate::{AppState, timeout_response};
+
+/// 写了一行就卡住的 handler，用来观察超时后事务有没有回滚
+async fn stuck_writer(State(state): State<AppState>) -> StatusCode {
+    let mut tx = state.pool.begin().await.unwrap();
+    sqlx::query("INSERT INTO mappings (namespace, code, value, created_at) VALUES ('', 'stuck', 'stuck', 0)")
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    std::future::pending::<()>().await;
+    tx.commit().await.unwrap();
+    StatusCode::OK
+}
+
+#[tokio::test]
+async fn slow_handler_times_out_and_rolls_back() {
+    let (_, state) = app(&[]).await;
+    let slow = Router::new()
+        .route("/slow", post(stuck_writer))
+        .route_layer(TimeoutLayer::with_status_code(
+            StatusCode::REQUEST_TIMEOUT,
+            Duration::from_millis(200),
+        ))
+        .route_layer(middleware::map_response_with_state(
+            Duration::from_millis(200),
+            timeout_response,
+        ))
+        .with_state(state.clone());
+
+    let resp = send(&slow, Request::post("/slow").body(Body::empty()).unwrap()).await;
+    assert_eq!(resp.status, StatusCode::REQUEST_TIMEOUT);
+    assert_eq!(
+        resp.json(),
+        json!({ "error": "request timed out", "code": "timeout" })
+    );
+
+    // 内存库只有一个连接：能拿到它时，被丢弃的事务已经回滚
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM mappings WHERE code = 'stuck'")
+        .fetch_one(&state.pool)
+        .await
+        .unwrap();
+    assert_eq!(rows, 0);
+}
+
+#[tokio::test]
+async fn routes_are_wrapped_in_the_request_timeout() {
+    let (app, state) = app(&[("REQUEST_TIMEOUT_SECS", "1")]).await;
+    // 占住唯一的连接，encode 拿不到连接就一直等（DB_ACQUIRE_TIMEOUT_SECS 默认 30 秒）
+    let held = state.pool.acquire().await.unwrap();
+    let resp = post_json(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/slow" }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::REQUEST_TIMEOUT);
+    assert_eq!(resp.json()["code"], "timeout");
+    drop(held);
+
+    // 连接还回去之后照常处理，超时的那次没有留下映射
+    let resp = post_json(
+        &app,
+        "/encode",
+        json!({ "value": "https://example.com/slow" }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["created"], true);
+}
@@ -0,0 +1,86 @@
+//! MAX_VALUE_LEN 按 UTF-8 字节数计算：正好到上限、超一个字节、多字节字符跨过上限
+
+use axum::http::StatusCode;
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::json;
+
+use super::{app, encode, post};
+
+const MAX: &str = "16";
+
+#[tokio::test]
+async fn value_at_the_limit_is_accepted() {
+    let (app, _) = app(&[("MAX_VALUE_LEN", MAX)]).await;
+    let value = "a".repeat(16);
+    let code = encode(&app, &value).await;
+    let resp = post(&app, "/decode", json!({ "code": code })).await;
+    assert_eq!(resp.json()["value"], value);
+}
+
+#[tokio::test]
+async fn one_byte_over_is_rejected() {
+    let (app, _) = app(&[("MAX_VALUE_LEN", MAX)]).await;
+    let resp = post(&app, "/encode", json!({ "value": "a".repeat(17) })).await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.json()["code"], "bad_request");
+    assert_eq!(resp.json()["error"], "value too long");
+}
+
+#[tokio::test]
+async fn multibyte_chars_count_by_bytes() {
+    let (app, _) = app(&[("MAX_VALUE_LEN", MAX)]).await;
+    // 14 + 2 字节正好 16；15 + 2 = 17 字节虽然只有 16 个字符，也超了
+    encode(&app, &format!("{}é", "a".repeat(14))).await;
+    // "€" 是 3 字节：13 + 3 = 16 可以，14 + 3 = 17 跨过上限
+    encode(&app, &format!("{}€", "a".repeat(13))).await;
+    for value in [
+        format!("{}é", "a".repeat(15)),
+        format!("{}€", "a".repeat(14)),
+        "€".repeat(6),
+    ] {
+        assert!(value.chars().count() <= 16);
+        let resp = post(&app, "/encode", json!({ "value": value })).await;
+        assert_eq!(resp.status, StatusCode::BAD_REQUEST, "{value}");
+        assert_eq!(resp.json()["error"], "value too long");
+    }
+}
+
+#[tokio::test]
+async fn binary_values_count_decoded_bytes() {
+    let (app, _) = app(&[("MAX_VALUE_LEN", MAX)]).await;
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value_b64": STANDARD.encode([0xffu8; 16]) }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value_b64": STANDARD.encode([0xffu8; 17]) }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.json()["error"], "value too long");
+}
+
+#[tokio::test]
+async fn batch_rejects_any_value_over_the_limit() {
+    let (app, _) = app(&[("MAX_VALUE_LEN", MAX)]).await;
+    let resp = post(
+        &app,
+        "/encode/batch",
+        json!({ "values": ["a".repeat(16), format!("{}é", "a".repeat(15))] }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+    assert_eq!(resp.json()["code"], "bad_request");
+    let resp = post(
+        &app,
+        "/encode/batch",
+        json!({ "values": ["a".repeat(16), format!("{}é", "a".repeat(14))] }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK, "{:?}", resp.body);
+}
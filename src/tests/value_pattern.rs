@@ -0,0 +1,144 @@
+//! VALUE_PATTERN：写入 value 的接口按配置接受 / 拒绝，查询接口不检查
+
+use axum::http::{Method, StatusCode};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde_json::json;
+
+use super::{API_KEY, TestResponse, app, app_with_db, call, encode, post, remove_db, temp_path};
+use crate::config::Config;
+
+const URL_ONLY: &str = r"^https?://[^\s/]+(/\S*)?$";
+
+fn assert_rejected(resp: &TestResponse) {
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST, "{:?}", resp.body);
+    assert_eq!(
+        resp.json(),
+        json!({ "error": "value does not match required format", "code": "bad_request" })
+    );
+}
+
+#[tokio::test]
+async fn encode_accepts_and_rejects_as_configured() {
+    let (app, _) = app(&[("VALUE_PATTERN", URL_ONLY)]).await;
+    for value in [
+        "https://example.com",
+        "http://example.com/a?b=c",
+        "https://例子.com/路径",
+    ] {
+        let resp = post(&app, "/encode", json!({ "value": value })).await;
+        assert_eq!(resp.status, StatusCode::OK, "{value}: {:?}", resp.body);
+    }
+    for value in [
+        "ftp://example.com",
+        "javascript:alert(1)",
+        "https://exa mple.com",
+        "see https://example.com",
+    ] {
+        assert_rejected(&post(&app, "/encode", json!({ "value": value })).await);
+    }
+    // 二进制 value 按原始字节匹配
+    let resp = post(
+        &app,
+        "/encode",
+        json!({ "value_b64": STANDARD.encode("https://example.com/bin") }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_rejected(
+        &post(
+            &app,
+            "/encode",
+            json!({ "value_b64": STANDARD.encode([0xff, 0x00]) }),
+        )
+        .await,
+    );
+}
+
+#[tokio::test]
+async fn pattern_is_a_substring_search_unless_anchored() {
+    let (app, _) = app(&[("VALUE_PATTERN", "example")]).await;
+    encode(&app, "https://example.com").await;
+    encode(&app, "counterexamples").await;
+    assert_rejected(&post(&app, "/encode", json!({ "value": "https://other.com" })).await);
+}
+
+#[tokio::test]
+async fn every_write_route_checks_the_pattern() {
+    let (app, _) = app(&[("VALUE_PATTERN", URL_ONLY), ("API_KEYS", API_KEY)]).await;
+    assert_rejected(&post(&app, "/encode/preview", json!({ "value": "not a url" })).await);
+    assert_eq!(
+        post(
+            &app,
+            "/encode/preview",
+            json!({ "value": "https://example.com" })
+        )
+        .await
+        .status,
+        StatusCode::OK
+    );
+
+    let resp = post(
+        &app,
+        "/encode/batch",
+        json!({ "values": ["https://example.com/1", "not a url"] }),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+
+    let code = encode(&app, "https://example.com/patch").await;
+    let uri = format!("/mappings/{code}");
+    assert_rejected(
+        &call(
+            &app,
+            Method::PATCH,
+            &uri,
+            Some(json!({ "value": "not a url" })),
+        )
+        .await,
+    );
+    let resp = call(
+        &app,
+        Method::PATCH,
+        &uri,
+        Some(json!({ "value": "https://example.com/patched" })),
+    )
+    .await;
+    assert_eq!(resp.status, StatusCode::NO_CONTENT);
+}
+
+#[tokio::test]
+async fn values_stored_before_the_pattern_still_decode() {
+    let path = temp_path("value-pattern.db");
+    let url = format!("sqlite://{}", path.display());
+    let (before, state) = app_with_db(&url, &[]).await;
+    let code = encode(&before, "ftp://example.com/legacy").await;
+    state.pool.close().await;
+
+    let (after, state) = app_with_db(&url, &[("VALUE_PATTERN", URL_ONLY)]).await;
+    let resp = post(&after, "/decode", json!({ "code": code })).await;
+    assert_eq!(resp.status, StatusCode::OK);
+    assert_eq!(resp.json()["value"], "ftp://example.com/legacy");
+    // 但不能再用它新建（或找回）映射
+    assert_rejected(
+        &post(
+            &after,
+            "/encode",
+            json!({ "value": "ftp://example.com/legacy" }),
+        )
+        .await,
+    );
+    state.pool.close().await;
+    remove_db(&path);
+}
+
+#[test]
+fn invalid_pattern_fails_at_startup() {
+    let err = Config::from_vars(&[("VALUE_PATTERN", "(unclosed")])
+        .err()
+        .expect("invalid regex must be rejected");
+    assert!(
+        err.to_string()
+            .starts_with("invalid VALUE_PATTERN=\"(unclosed\""),
+        "{err}"
+    );
+}
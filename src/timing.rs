@@ -0,0 +1,83 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+pub static X_DB_TIME_MS: HeaderName = HeaderName::from_static("x-db-time-ms");
+pub static SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// 计时结果写进哪些响应头
+#[derive(Clone, Copy)]
+pub struct TimingHeaders {
+    /// DEBUG_TIMING：X-DB-Time-Ms
+    pub db_time_ms: bool,
+    /// SERVER_TIMING：标准的 Server-Timing，浏览器开发者工具和 APM 能直接显示
+    pub server_timing: bool,
+}
+
+tokio::task_local! {
+    /// 当前请求里 db() 包住的数据库操作累计耗时；没有被统计过时为 None
+    static DB_TIME: Cell<Option<Duration>>;
+}
+
+/// DEBUG_TIMING 或 SERVER_TIMING 打开时才挂上：给请求开一个累加器，handler 里经过 db() 的数据库操作把耗时加进去。
+/// 响应时按开关写进 X-DB-Time-Ms（毫秒，三位小数），和 Server-Timing 的 `db;dur=`（同样是毫秒）加上整个 handler 的 `total;dur=`。
+/// 没有统计到数据库操作的请求（纯校验等）不带 X-DB-Time-Ms，Server-Timing 里也没有 db 这一项
+pub async fn track_db_time(
+    State(headers): State<TimingHeaders>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let (mut resp, db) = DB_TIME
+        .scope(Cell::new(None), async {
+            let resp = next.run(req).await;
+            (resp, DB_TIME.with(Cell::get))
+        })
+        .await;
+    let total = start.elapsed();
+
+    if headers.db_time_ms
+        && let Some(db) = db
+    {
+        resp.headers_mut().insert(
+            X_DB_TIME_MS.clone(),
+            timing_header(format!("{:.3}", millis(db))),
+        );
+    }
+    if headers.server_timing {
+        let value = match db {
+            Some(db) => format!("db;dur={:.3}, total;dur={:.3}", millis(db), millis(total)),
+            None => format!("total;dur={:.3}", millis(total)),
+        };
+        // 追加而不是覆盖：前面的代理可能已经写了自己的指标
+        resp.headers_mut()
+            .append(SERVER_TIMING.clone(), timing_header(value));
+    }
+    resp
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+fn timing_header(value: String) -> HeaderValue {
+    HeaderValue::from_str(&value).expect("number is a valid header value")
+}
+
+/// 计时一次数据库操作（包括从连接池取连接、等锁）。不在 track_db_time 里（DEBUG_TIMING 关闭、后台任务）时只是原样 await
+pub async fn db<F: Future>(fut: F) -> F::Output {
+    let start = Instant::now();
+    let out = fut.await;
+    let _ = DB_TIME
+        .try_with(|total| total.set(Some(total.get().unwrap_or_default() + start.elapsed())));
+    out
+}
@@ -0,0 +1,245 @@
+use std::{
+    io::{self, Read, Write},
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll, ready},
+};
+
+use anyhow::Context as _;
+use rustls::{
+    ServerConfig, ServerConnection,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpStream},
+};
+use tracing::warn;
+
+/// 读取 TLS_CERT（PEM 证书链，叶子证书在前）和 TLS_KEY（PEM 私钥，PKCS#8 / PKCS#1 / SEC1），
+/// 任何一步失败都带上文件路径报错，启动时直接退出。http2 为 true 时 ALPN 优先协商 h2
+pub fn load_config(
+    cert_path: &str,
+    key_path: &str,
+    http2: bool,
+) -> anyhow::Result<Arc<ServerConfig>> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|iter| iter.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("failed to load TLS_CERT {cert_path}"))?;
+    if certs.is_empty() {
+        anyhow::bail!("failed to load TLS_CERT {cert_path}: no certificate found");
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("failed to load TLS_KEY {key_path}"))?;
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut config = ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("failed to configure TLS")?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .with_context(|| format!("invalid TLS_CERT / TLS_KEY ({cert_path}, {key_path})"))?;
+    config.alpn_protocols = if http2 {
+        vec![b"h2".to_vec(), b"http/1.1".to_vec()]
+    } else {
+        vec![b"http/1.1".to_vec()]
+    };
+    Ok(Arc::new(config))
+}
+
+/// TCP 之上做 TLS 的监听。accept 只建连接不握手：握手在第一次读写时由 TlsStream 推进，
+/// 慢吞吞的客户端不会卡住后面的 accept
+pub struct TlsListener {
+    tcp: TcpListener,
+    config: Arc<ServerConfig>,
+}
+
+impl TlsListener {
+    pub fn new(tcp: TcpListener, config: Arc<ServerConfig>) -> Self {
+        Self { tcp, config }
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            let (io, addr) = axum::serve::Listener::accept(&mut self.tcp).await;
+            match ServerConnection::new(self.config.clone()) {
+                Ok(conn) => return (TlsStream { io, conn }, addr),
+                Err(e) => warn!(error = %e, remote = %addr, "failed to create tls connection"),
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.tcp.local_addr()
+    }
+}
+
+/// 一条 TLS 连接：rustls 的同步状态机 + 底层 TcpStream，按 tokio 的 AsyncRead / AsyncWrite 暴露
+pub struct TlsStream {
+    io: TcpStream,
+    conn: ServerConnection,
+}
+
+/// 把 poll 风格的 TcpStream 包成 std::io 的 Read / Write 给 rustls 用，Pending 映射成 WouldBlock
+struct SyncIo<'a, 'b> {
+    io: &'a mut TcpStream,
+    cx: &'a mut Context<'b>,
+}
+
+impl Read for SyncIo<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut buf = ReadBuf::new(buf);
+        match Pin::new(&mut *self.io).poll_read(self.cx, &mut buf) {
+            Poll::Ready(Ok(())) => Ok(buf.filled().len()),
+            Poll::Ready(Err(e)) => Err(e),
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+impl Write for SyncIo<'_, '_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match Pin::new(&mut *self.io).poll_write(self.cx, buf) {
+            Poll::Ready(r) => r,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match Pin::new(&mut *self.io).poll_flush(self.cx) {
+            Poll::Ready(r) => r,
+            Poll::Pending => Err(io::ErrorKind::WouldBlock.into()),
+        }
+    }
+}
+
+fn poll_io<T>(r: io::Result<T>) -> Poll<io::Result<T>> {
+    match r {
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => Poll::Pending,
+        r => Poll::Ready(r),
+    }
+}
+
+impl TlsStream {
+    /// 从 socket 读一段密文交给 rustls 解析；返回 0 表示对端关闭了 TCP
+    fn read_tls(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<usize>> {
+        let n = ready!(poll_io(self.conn.read_tls(&mut SyncIo {
+            io: &mut self.io,
+            cx
+        })))?;
+        if let Err(e) = self.conn.process_new_packets() {
+            // 尽量把 rustls 排好的 alert 发给对端，再断开
+            let _ = self.conn.write_tls(&mut SyncIo {
+                io: &mut self.io,
+                cx,
+            });
+            return Poll::Ready(Err(io::Error::new(io::ErrorKind::InvalidData, e)));
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    /// 把 rustls 里排队的密文全部写到 socket
+    fn flush_tls(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.conn.wants_write() {
+            let n = ready!(poll_io(self.conn.write_tls(&mut SyncIo {
+                io: &mut self.io,
+                cx
+            })))?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    /// 推进握手直到完成（正常情况下读第一个请求时就已经握手完了）
+    fn poll_handshake(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.conn.is_handshaking() {
+            ready!(self.flush_tls(cx))?;
+            if !self.conn.is_handshaking() {
+                break;
+            }
+            if ready!(self.read_tls(cx))? == 0 {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+        }
+        self.flush_tls(cx)
+    }
+}
+
+impl AsyncRead for TlsStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        loop {
+            match this.conn.reader().read(buf.initialize_unfilled()) {
+                Ok(n) => {
+                    buf.advance(n);
+                    return Poll::Ready(Ok(()));
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Poll::Ready(Err(e)),
+            }
+            // 握手阶段读到的数据可能触发要回给对端的消息，先发出去；发不出去不影响继续读
+            if let Poll::Ready(Err(e)) = this.flush_tls(cx) {
+                return Poll::Ready(Err(e));
+            }
+            if ready!(this.read_tls(cx))? == 0 {
+                // rustls 已经知道 EOF：下一轮 reader() 会返回 Ok(0)（收到过 close_notify）或 UnexpectedEof
+                match this.conn.reader().read(buf.initialize_unfilled()) {
+                    Ok(n) => buf.advance(n),
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+impl AsyncWrite for TlsStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        ready!(this.poll_handshake(cx))?;
+        loop {
+            let n = this.conn.writer().write(buf)?;
+            if n > 0 || buf.is_empty() {
+                // 密文暂时写不出去也没关系，已经在 rustls 的缓冲里了，poll_flush 会继续写
+                if let Poll::Ready(Err(e)) = this.flush_tls(cx) {
+                    return Poll::Ready(Err(e));
+                }
+                return Poll::Ready(Ok(n));
+            }
+            // rustls 的发送缓冲满了：先把密文写出去腾地方，不能返回 Ok(0)
+            ready!(this.flush_tls(cx))?;
+        }
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        this.conn.writer().flush()?;
+        ready!(this.flush_tls(cx))?;
+        Pin::new(&mut this.io).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        // send_close_notify 可以重复调用，只会发一次
+        this.conn.send_close_notify();
+        ready!(this.flush_tls(cx))?;
+        Pin::new(&mut this.io).poll_shutdown(cx)
+    }
+}
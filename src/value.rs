@@ -0,0 +1,109 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use sqlx::{
+    Any, Database, Type,
+    encode::{Encode, IsNull},
+    error::BoxDynError,
+};
+
+/// 映射里存的 value：默认是 UTF-8 文本（`value` 列），也可以是任意字节（`value_bin` 列，
+/// 请求 / 响应里用 base64 的 `value_b64` 字段）。去重靠 db::init_db 建的唯一索引：默认是
+/// (namespace, value) / (namespace, value_bin)，VALUE_HASH_DEDUP 开启时只有 (namespace, value_hash)，查到后再比较原文
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum Value {
+    #[serde(rename = "value")]
+    Text(String),
+    #[serde(rename = "value_b64", serialize_with = "serialize_b64")]
+    Bytes(Vec<u8>),
+}
+
+impl Value {
+    /// 按请求里出现的字段选择：只能给 `value` 或 `value_b64` 其中一个
+    pub fn from_fields(value: Option<String>, value_b64: Option<&str>) -> Result<Self, String> {
+        match (value, value_b64) {
+            (Some(_), Some(_)) => Err("provide either value or value_b64, not both".to_string()),
+            (Some(text), None) => Ok(Value::Text(text)),
+            (None, Some(b64)) => STANDARD
+                .decode(b64)
+                .map(Value::Bytes)
+                .map_err(|_| "value_b64 is not valid base64".to_string()),
+            (None, None) => Err("value is required".to_string()),
+        }
+    }
+
+    /// 从一行的 value / value_bin 两列还原；两列恰好有一列非空
+    pub fn from_columns(text: Option<String>, bytes: Option<Vec<u8>>) -> Self {
+        match (text, bytes) {
+            (Some(text), _) => Value::Text(text),
+            (None, bytes) => Value::Bytes(bytes.unwrap_or_default()),
+        }
+    }
+
+    /// 存放这个 value 的列名
+    pub fn column(&self) -> &'static str {
+        match self {
+            Value::Text(_) => "value",
+            Value::Bytes(_) => "value_bin",
+        }
+    }
+
+    /// 存进 value_hash 列的 SHA-256。带上列名一起算：字节相同的文本 value 和二进制 value 是两条映射，
+    /// 不能撞在同一个哈希上
+    pub fn dedup_hash(&self) -> Vec<u8> {
+        Sha256::new()
+            .chain_update(self.column())
+            .chain_update([0])
+            .chain_update(self.as_bytes())
+            .finalize()
+            .to_vec()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Value::Text(text) => text.as_bytes(),
+            Value::Bytes(bytes) => bytes,
+        }
+    }
+
+    /// 文本 value；二进制的返回 None（事件表的 value 列只记文本）
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text(text) => Some(text),
+            Value::Bytes(_) => None,
+        }
+    }
+
+    /// base64 形式，二进制 value 才有
+    pub fn to_b64(&self) -> Option<String> {
+        match self {
+            Value::Text(_) => None,
+            Value::Bytes(bytes) => Some(STANDARD.encode(bytes)),
+        }
+    }
+}
+
+fn serialize_b64<S: Serializer>(bytes: &[u8], s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&STANDARD.encode(bytes))
+}
+
+/// 直接 `.bind(&value)`：文本绑定成 TEXT，二进制绑定成 BLOB / BYTEA，配合 `column()` 使用
+impl Type<Any> for Value {
+    fn type_info() -> <Any as Database>::TypeInfo {
+        <str as Type<Any>>::type_info()
+    }
+}
+
+impl<'q> Encode<'q, Any> for &'q Value {
+    fn encode_by_ref(
+        &self,
+        buf: &mut <Any as Database>::ArgumentBuffer<'q>,
+    ) -> Result<IsNull, BoxDynError> {
+        match self {
+            Value::Text(text) => <&str as Encode<'q, Any>>::encode_by_ref(&text.as_str(), buf),
+            Value::Bytes(bytes) => {
+                <&[u8] as Encode<'q, Any>>::encode_by_ref(&bytes.as_slice(), buf)
+            }
+        }
+    }
+}